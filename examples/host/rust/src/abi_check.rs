@@ -0,0 +1,435 @@
+// Core signature check against the canonical ABI (`abi-check`)
+//
+// Flattens every function of a WIT world with wit-parser's ABI helpers and
+// compares the result with the core module's actual imports and exports, as
+// read by wasmparser. Covers interface and world-level functions, resource
+// intrinsics, `cabi_post_*` and `cabi_realloc`, and reports per function a
+// wrong parameter count, a missing retptr or a wrong result arity. Run it on
+// the `moon build` output to see the imports componentize has to patch, or
+// on the patched module to check that the patch agrees with the ABI.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use wasmparser::{CompositeInnerType, ExternalKind, Parser, Payload, TypeRef, ValType};
+use wit_parser::abi::{AbiVariant, WasmType};
+use wit_parser::{Function, Resolve, TypeDefKind, WorldId, WorldItem};
+
+const USAGE: &str = "usage: rust-host abi-check <wit-path> <core.wasm> [--world NAME]";
+
+/// A core signature, `(params, results)`.
+type Signature = (Vec<ValType>, Vec<ValType>);
+
+/// What the canonical ABI says a core function should look like.
+struct Expected {
+    signature: Signature,
+    /// The results come back through a pointer: an extra parameter for
+    /// imports, a single pointer result for exports.
+    retptr: bool,
+    /// Core modules only import what they use, and need not define every
+    /// destructor.
+    optional: bool,
+}
+
+fn core_type(ty: WasmType) -> ValType {
+    match ty {
+        WasmType::I32 | WasmType::Pointer | WasmType::Length => ValType::I32,
+        WasmType::I64 | WasmType::PointerOrI64 => ValType::I64,
+        WasmType::F32 => ValType::F32,
+        WasmType::F64 => ValType::F64,
+    }
+}
+
+fn flatten(resolve: &Resolve, variant: AbiVariant, func: &Function) -> Expected {
+    let sig = resolve.wasm_signature(variant, func);
+    Expected {
+        signature: (
+            sig.params.into_iter().map(core_type).collect(),
+            sig.results.into_iter().map(core_type).collect(),
+        ),
+        retptr: sig.retptr,
+        optional: variant == AbiVariant::GuestImport,
+    }
+}
+
+fn fixed(params: &[ValType], results: &[ValType], optional: bool) -> Expected {
+    Expected {
+        signature: (params.to_vec(), results.to_vec()),
+        retptr: false,
+        optional,
+    }
+}
+
+fn show(sig: &Signature) -> String {
+    let list = |types: &[ValType]| {
+        types
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    format!("({}) -> ({})", list(&sig.0), list(&sig.1))
+}
+
+/// Expected imports keyed by `(module, name)` and exports keyed by name.
+#[derive(Default)]
+struct World {
+    imports: HashMap<(String, String), Expected>,
+    exports: Vec<(String, Expected)>,
+}
+
+impl World {
+    fn from_wit(resolve: &Resolve, world: WorldId) -> World {
+        use ValType::I32;
+        let mut out = World::default();
+        let world = &resolve.worlds[world];
+        let resources = |id| {
+            resolve.interfaces[id]
+                .types
+                .iter()
+                .filter(|(_, ty)| resolve.types[**ty].kind == TypeDefKind::Resource)
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        for (key, item) in &world.imports {
+            match item {
+                WorldItem::Interface { id, .. } => {
+                    let module = resolve.name_world_key(key);
+                    for func in resolve.interfaces[*id].functions.values() {
+                        out.imports.insert(
+                            (module.clone(), func.name.clone()),
+                            flatten(resolve, AbiVariant::GuestImport, func),
+                        );
+                    }
+                    for resource in resources(*id) {
+                        out.imports.insert(
+                            (module.clone(), format!("[resource-drop]{}", resource)),
+                            fixed(&[I32], &[], true),
+                        );
+                    }
+                }
+                WorldItem::Function(func) => {
+                    out.imports.insert(
+                        ("$root".to_string(), func.name.clone()),
+                        flatten(resolve, AbiVariant::GuestImport, func),
+                    );
+                }
+                WorldItem::Type(id) => {
+                    let ty = &resolve.types[*id];
+                    if ty.kind == TypeDefKind::Resource {
+                        if let Some(name) = &ty.name {
+                            out.imports.insert(
+                                ("$root".to_string(), format!("[resource-drop]{}", name)),
+                                fixed(&[I32], &[], true),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        for (key, item) in &world.exports {
+            match item {
+                WorldItem::Interface { id, .. } => {
+                    let name = resolve.name_world_key(key);
+                    for func in resolve.interfaces[*id].functions.values() {
+                        out.exports.push((
+                            format!("{}#{}", name, func.name),
+                            flatten(resolve, AbiVariant::GuestExport, func),
+                        ));
+                    }
+                    let module = format!("[export]{}", name);
+                    for resource in resources(*id) {
+                        for (intrinsic, results) in [
+                            ("resource-new", &[I32][..]),
+                            ("resource-rep", &[I32][..]),
+                            ("resource-drop", &[][..]),
+                        ] {
+                            out.imports.insert(
+                                (module.clone(), format!("[{}]{}", intrinsic, resource)),
+                                fixed(&[I32], results, true),
+                            );
+                        }
+                        out.exports.push((
+                            format!("{}#[dtor]{}", name, resource),
+                            fixed(&[I32], &[], true),
+                        ));
+                    }
+                }
+                WorldItem::Function(func) => {
+                    out.exports.push((
+                        func.name.clone(),
+                        flatten(resolve, AbiVariant::GuestExport, func),
+                    ));
+                }
+                WorldItem::Type(_) => {}
+            }
+        }
+        out
+    }
+}
+
+/// Function imports and exports of a core module, with their signatures.
+struct Module {
+    imports: Vec<((String, String), Signature)>,
+    exports: Vec<(String, Signature)>,
+}
+
+fn read_module(bytes: &[u8]) -> Result<Module> {
+    let mut types: Vec<Option<Signature>> = Vec::new();
+    let mut funcs: Vec<u32> = Vec::new();
+    let mut imports = Vec::new();
+    let mut export_indices = Vec::new();
+    for payload in Parser::new(0).parse_all(bytes) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for group in reader {
+                    for sub in group?.into_types() {
+                        types.push(match sub.composite_type.inner {
+                            CompositeInnerType::Func(f) => {
+                                Some((f.params().to_vec(), f.results().to_vec()))
+                            }
+                            _ => None,
+                        });
+                    }
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    let import = import?;
+                    if let TypeRef::Func(ty) = import.ty {
+                        funcs.push(ty);
+                        imports.push(((import.module.to_string(), import.name.to_string()), ty));
+                    }
+                }
+            }
+            Payload::FunctionSection(reader) => {
+                for ty in reader {
+                    funcs.push(ty?);
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func {
+                        export_indices.push((export.name.to_string(), export.index));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let signature = |ty: u32| -> Result<Signature> {
+        types
+            .get(ty as usize)
+            .cloned()
+            .flatten()
+            .with_context(|| format!("type {} is not a function type", ty))
+    };
+    Ok(Module {
+        imports: imports
+            .into_iter()
+            .map(|(name, ty)| Ok((name, signature(ty)?)))
+            .collect::<Result<_>>()?,
+        exports: export_indices
+            .into_iter()
+            .map(|(name, index)| {
+                let ty = *funcs
+                    .get(index as usize)
+                    .with_context(|| format!("export {} has no function {}", name, index))?;
+                Ok((name, signature(ty)?))
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+/// Imports of the core module that return their result through a retptr but
+/// also declare a core result, as `moon build` emits them. componentize keeps
+/// their parameters and drops the result.
+pub fn retptr_imports(resolve: &Resolve, world: WorldId, bytes: &[u8]) -> Result<Vec<(String, String)>> {
+    let world = World::from_wit(resolve, world);
+    Ok(read_module(bytes)?
+        .imports
+        .into_iter()
+        .filter(|(name, actual)| {
+            world.imports.get(name).is_some_and(|expected| {
+                expected.retptr && actual.0 == expected.signature.0 && !actual.1.is_empty()
+            })
+        })
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// Everything wrong with `actual` against `expected`, per the ABI variant.
+fn compare(expected: &Expected, actual: &Signature, variant: AbiVariant) -> Vec<String> {
+    let mut problems = Vec::new();
+    let (params, results) = &expected.signature;
+    if params.len() != actual.0.len() {
+        if expected.retptr
+            && variant == AbiVariant::GuestImport
+            && actual.0.len() + 1 == params.len()
+        {
+            problems.push("missing retptr parameter".to_string());
+        } else {
+            problems.push(format!(
+                "wrong param count: expected {}, found {}",
+                params.len(),
+                actual.0.len()
+            ));
+        }
+    } else if let Some(i) = (0..params.len()).find(|&i| params[i] != actual.0[i]) {
+        problems.push(format!(
+            "param {} is {}, expected {}",
+            i, actual.0[i], params[i]
+        ));
+    }
+    if results.len() != actual.1.len() {
+        let mut problem = format!(
+            "wrong result arity: expected {}, found {}",
+            results.len(),
+            actual.1.len()
+        );
+        if expected.retptr && variant == AbiVariant::GuestImport {
+            problem.push_str(" (componentize removes this result when it patches retptr imports)");
+        } else if expected.retptr && actual.1.is_empty() {
+            problem = "missing retptr result".to_string();
+        }
+        problems.push(problem);
+    } else if let Some(i) = (0..results.len()).find(|&i| results[i] != actual.1[i]) {
+        problems.push(format!(
+            "result {} is {}, expected {}",
+            i, actual.1[i], results[i]
+        ));
+    }
+    problems
+}
+
+pub fn run_abi_check(args: &[String]) -> Result<()> {
+    let (Some(wit_path), Some(module_path)) = (args.first(), args.get(1)) else {
+        bail!(USAGE);
+    };
+    let world_name = match args.get(2).map(|s| s.as_str()) {
+        Some("--world") => Some(args.get(3).context("--world needs a name")?.as_str()),
+        Some(other) => bail!("unexpected argument: {}\n{}", other, USAGE),
+        None => None,
+    };
+
+    let mut resolve = Resolve::default();
+    let (package, _) = resolve
+        .push_path(wit_path)
+        .with_context(|| format!("failed to parse WIT {}", wit_path))?;
+    let world_id = resolve.select_world(package, world_name)?;
+    println!("World: {}", resolve.worlds[world_id].name);
+    let world = World::from_wit(&resolve, world_id);
+
+    println!("Core module: {}", module_path);
+    let bytes =
+        std::fs::read(module_path).with_context(|| format!("failed to read {}", module_path))?;
+    let module = read_module(&bytes)?;
+
+    let mut checked = 0;
+    let mut failures = 0;
+    let mut report = |name: &str, actual: &Signature, problems: Vec<String>| {
+        checked += 1;
+        if problems.is_empty() {
+            println!("  ✓ {:<56} {}", name, show(actual));
+        } else {
+            failures += 1;
+            println!("  ✗ {:<56} {}", name, show(actual));
+            for problem in problems {
+                println!("      {}", problem);
+            }
+        }
+    };
+
+    println!("\nImports:");
+    for ((module_name, name), actual) in &module.imports {
+        let label = format!("{}::{}", module_name, name);
+        match world.imports.get(&(module_name.clone(), name.clone())) {
+            Some(expected) => {
+                let mut problems = compare(expected, actual, AbiVariant::GuestImport);
+                if !problems.is_empty() {
+                    problems.push(format!("canonical ABI: {}", show(&expected.signature)));
+                }
+                report(&label, actual, problems);
+            }
+            // WASI preview1 and other core-level imports are outside the world.
+            None if !module_name.contains(':')
+                && !module_name.starts_with('[')
+                && module_name != "$root" => {}
+            None => report(
+                &label,
+                actual,
+                vec!["not imported by the world".to_string()],
+            ),
+        }
+    }
+
+    println!("\nExports:");
+    let exports: HashMap<&str, &Signature> = module
+        .exports
+        .iter()
+        .map(|(name, sig)| (name.as_str(), sig))
+        .collect();
+    for (name, expected) in &world.exports {
+        match exports.get(name.as_str()) {
+            Some(actual) => {
+                let mut problems = compare(expected, actual, AbiVariant::GuestExport);
+                if !problems.is_empty() {
+                    problems.push(format!("canonical ABI: {}", show(&expected.signature)));
+                }
+                report(name, actual, problems);
+            }
+            None if expected.optional => {}
+            None => report(
+                name,
+                &expected.signature,
+                vec!["not exported by the core module".to_string()],
+            ),
+        }
+    }
+
+    use ValType::I32;
+    let mut has_realloc = false;
+    for (name, actual) in &module.exports {
+        if let Some(export) = name.strip_prefix("cabi_post_") {
+            // Post-return receives exactly what the export returned.
+            let problems = match world.exports.iter().find(|(n, _)| n == export) {
+                Some((_, expected)) => compare(
+                    &fixed(&expected.signature.1, &[], false),
+                    actual,
+                    AbiVariant::GuestExport,
+                ),
+                None => vec![format!("{} is not a world export", export)],
+            };
+            report(name, actual, problems);
+        } else if name == "cabi_realloc" {
+            has_realloc = true;
+            report(
+                name,
+                actual,
+                compare(
+                    &fixed(&[I32, I32, I32, I32], &[I32], false),
+                    actual,
+                    AbiVariant::GuestExport,
+                ),
+            );
+        }
+    }
+    if !has_realloc {
+        println!("  (no cabi_realloc export)");
+    }
+
+    println!(
+        "\n{} function(s) checked, {} mismatch(es)",
+        checked, failures
+    );
+    if failures > 0 {
+        bail!(
+            "{} core signature(s) disagree with the canonical ABI",
+            failures
+        );
+    }
+    println!("All core signatures match the canonical ABI ✓");
+    Ok(())
+}
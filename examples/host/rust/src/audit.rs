@@ -0,0 +1,66 @@
+// Import audit (`audit`)
+//
+// Lists every import of a component with the capability it needs and the
+// functions it uses, and with `--policy` checks them against the allow-list:
+// the command fails if any import is not allowed.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::host;
+use crate::policy::{self, ImportKind};
+
+pub fn run_audit(args: &[String]) -> Result<()> {
+    let component_path = args
+        .first()
+        .context("usage: rust-host audit <component> [--policy FILE]")?;
+
+    let engine = Engine::new(&host::component_config())?;
+    println!("Loading component: {}", component_path);
+    let component = Component::from_file(&engine, component_path)?;
+    let imports = policy::imports(&engine, &component);
+    let policy = policy::policy();
+
+    println!("\nImports ({}):", imports.len());
+    let mut denied = Vec::new();
+    for import in &imports {
+        let verdict = match policy {
+            Some(policy) if policy.allows(&import.name) => "allowed",
+            Some(_) => {
+                denied.push(import.name.as_str());
+                "DENIED"
+            }
+            None => "",
+        };
+        println!(
+            "  {:<48} {:<11} {}",
+            import.name, import.capability, verdict
+        );
+        if let ImportKind::Instance { funcs, .. } = &import.kind {
+            for func in funcs {
+                println!("      {}", func);
+            }
+        }
+    }
+
+    let capabilities: BTreeSet<_> = imports.iter().map(|i| i.capability).collect();
+    println!(
+        "\nCapabilities: {}",
+        capabilities.into_iter().collect::<Vec<_>>().join(", ")
+    );
+
+    if policy.is_none() {
+        return Ok(());
+    }
+    if !denied.is_empty() {
+        bail!(
+            "{} import(s) not allowed by policy: {}",
+            denied.len(),
+            denied.join(", ")
+        );
+    }
+    println!("\nAll imports allowed by policy ✓");
+    Ok(())
+}
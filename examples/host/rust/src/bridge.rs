@@ -0,0 +1,139 @@
+// WASI 0.2.x version bridging
+//
+// The host implements one WASI 0.2 release (whatever wasmtime-wasi provides).
+// Components pin whichever 0.2.N their WIT was generated from, and the linker
+// resolves `wasi:*@0.2.N` onto the host's 0.2.M definitions as long as every
+// function and resource the component uses exists with the same type. This
+// module makes that visible: it reports which versions were bridged, and when
+// some cannot be, lists every failing interface with the reason instead of
+// stopping at wasmtime's first "matching implementation was not found".
+
+use anyhow::{bail, Result};
+use wasmtime::component::{Component, Linker};
+use wasmtime::Engine;
+
+use crate::host::HostState;
+use crate::policy::{self, Import};
+
+/// WASI release wasmtime-wasi 29 and wasmtime-wasi-http 29 implement.
+pub const HOST_WASI_VERSION: &str = "0.2.3";
+
+/// Packages served at `HOST_WASI_VERSION`; the draft proposals the host
+/// implements (keyvalue, config, logging) are versioned on their own.
+const WASI_PACKAGES: &[&str] = &[
+    "wasi:cli",
+    "wasi:clocks",
+    "wasi:filesystem",
+    "wasi:http",
+    "wasi:io",
+    "wasi:random",
+    "wasi:sockets",
+];
+
+/// `(package, version)` of an import named `wasi:pkg/iface@version`, for
+/// the packages the host serves at `HOST_WASI_VERSION`.
+fn wasi_version(name: &str) -> Option<(&str, &str)> {
+    let (path, version) = name.split_once('@')?;
+    let (package, _) = path.split_once('/')?;
+    WASI_PACKAGES
+        .contains(&package)
+        .then_some((package, version))
+}
+
+/// Semver compatibility track: `0.2` for `0.2.N`, `1` for `1.N.M`. Pre-release
+/// versions are only compatible with themselves, so they have none.
+fn track(version: &str) -> Option<String> {
+    if version.contains('-') || version.contains('+') {
+        return None;
+    }
+    let mut parts = version.split('.');
+    let major = parts.next()?;
+    let minor = parts.next()?;
+    Some(match major {
+        "0" => format!("0.{}", minor),
+        _ => major.to_string(),
+    })
+}
+
+/// Host version an import of `version` resolves to, if any.
+fn bridged_to(version: &str) -> Option<&'static str> {
+    let version_track = track(version)?;
+    (track(HOST_WASI_VERSION) == Some(version_track)).then_some(HOST_WASI_VERSION)
+}
+
+/// Name of the import a linker error is about: wasmtime reports the first
+/// import it cannot satisfy as "component imports instance `NAME`, ...".
+fn failing_import(err: &anyhow::Error) -> Option<String> {
+    let message = err.chain().next()?.to_string();
+    let (_, rest) = message.split_once('`')?;
+    let (name, _) = rest.split_once('`')?;
+    Some(name.to_string())
+}
+
+/// Report the WASI versions `component` is bridged across, and fail with every
+/// import that cannot be bridged onto the host's version.
+pub fn check(linker: &Linker<HostState>, engine: &Engine, component: &Component) -> Result<()> {
+    let imports = policy::imports(engine, component);
+    let mut versions: Vec<(&str, &str)> = imports
+        .iter()
+        .filter_map(|import| wasi_version(&import.name))
+        .filter(|(_, version)| *version != HOST_WASI_VERSION)
+        .collect();
+    versions.sort();
+    versions.dedup();
+    for (package, version) in &versions {
+        if let Some(host_version) = bridged_to(version) {
+            eprintln!("[wasi] {}@{} -> {}", package, version, host_version);
+        }
+    }
+
+    // Each failing interface is replaced with a trapping stand-in in a
+    // throwaway linker, so the next type check gets past it.
+    let mut probe = linker.clone();
+    probe.allow_shadowing(true);
+    let mut failures: Vec<(&Import, String)> = Vec::new();
+    while let Err(err) = probe.instantiate_pre(component) {
+        let Some(import) = failing_import(&err)
+            .and_then(|name| imports.iter().find(|import| import.name == name))
+        else {
+            break;
+        };
+        if wasi_version(&import.name).is_none()
+            || failures.iter().any(|(failed, _)| failed.name == import.name)
+        {
+            break;
+        }
+        let reason = err
+            .chain()
+            .skip(1)
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>()
+            .join(": ");
+        failures.push((import, reason));
+        policy::define_trapping(&mut probe, import, "cannot be bridged")?;
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    let mut report = String::new();
+    for (import, reason) in &failures {
+        let (_, version) = wasi_version(&import.name).unwrap();
+        let target = match bridged_to(version) {
+            Some(host_version) => host_version.to_string(),
+            None => format!("none (host implements {})", HOST_WASI_VERSION),
+        };
+        let reason = if reason.is_empty() {
+            "not implemented by the host"
+        } else {
+            reason.as_str()
+        };
+        report.push_str(&format!("\n  {} -> {}: {}", import.name, target, reason));
+    }
+    bail!(
+        "{} WASI import(s) cannot be bridged onto the host's WASI {}:{}",
+        failures.len(),
+        HOST_WASI_VERSION,
+        report
+    );
+}
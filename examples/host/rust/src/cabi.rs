@@ -0,0 +1,1231 @@
+// Canonical ABI driver for core modules
+//
+// Does in the harness what the component boundary normally does: lowers
+// typed component values into a core module's linear memory through its
+// `cabi_realloc`, calls the flattened core export, lifts the results back and
+// calls `cabi_post_*`. That tests the MoonBit-generated core ABI without
+// `wasm-tools component new`, so a misbehaving component can be pinned on the
+// core module or on componentization.
+//
+// Function types come from the WIT world: a dummy component is encoded from
+// it with wit-component, and wasmtime reports its export types, so arguments
+// are parsed into the same `Val`s (and text form) the other modes use. Memory
+// layout and flattening are wit-parser's (`SizeAlign`, `push_flat`), applied
+// to the world's own WIT types. Resources are not supported.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasmtime::component::{Component, Type, Val};
+use wasmtime::{AsContextMut, Engine, Instance, Memory, StoreContextMut, TypedFunc, ValType};
+use wit_component::{ComponentEncoder, StringEncoding};
+use wit_parser::abi::{AbiVariant, WasmType};
+use wit_parser::{Function, Int, Mangling, Resolve, SizeAlign, TypeDefKind, WorldItem};
+
+type WitType = wit_parser::Type;
+
+/// A world export, by its core name (`iface#func` or `func`).
+pub struct Signature {
+    pub name: String,
+    pub params: Vec<Type>,
+    func: Function,
+    wit: Rc<Wit>,
+}
+
+/// Every function the world at `wit_path` exports, with component-level
+/// parameter types for parsing arguments.
+pub fn signatures(engine: &Engine, wit_path: &str, world: Option<&str>) -> Result<Vec<Signature>> {
+    let mut resolve = Resolve::default();
+    let (package, _) = resolve
+        .push_path(wit_path)
+        .with_context(|| format!("failed to parse WIT {}", wit_path))?;
+    let world = resolve.select_world(package, world)?;
+    let mut module = wit_component::dummy_module(&resolve, world, Mangling::Legacy);
+    wit_component::embed_component_metadata(&mut module, &resolve, world, StringEncoding::UTF8)?;
+    let bytes = ComponentEncoder::default()
+        .module(&module)?
+        .validate(true)
+        .encode()
+        .context("failed to encode a component for the world")?;
+    let component = Component::new(engine, &bytes)?;
+
+    let mut funcs = HashMap::new();
+    for (key, item) in &resolve.worlds[world].exports {
+        match item {
+            WorldItem::Function(func) => {
+                funcs.insert(func.name.clone(), func.clone());
+            }
+            WorldItem::Interface { id, .. } => {
+                let iface = resolve.name_world_key(key);
+                for func in resolve.interfaces[*id].functions.values() {
+                    funcs.insert(format!("{}#{}", iface, func.name), func.clone());
+                }
+            }
+            WorldItem::Type(_) => {}
+        }
+    }
+    let wit = Rc::new(Wit::new(resolve));
+
+    let mut out = Vec::new();
+    let mut push = |name: String, ty: wasmtime::component::types::ComponentFunc| -> Result<()> {
+        let func = funcs
+            .remove(&name)
+            .with_context(|| format!("{} is not in the world's exports", name))?;
+        out.push(Signature {
+            name,
+            params: ty.params().map(|(_, ty)| ty).collect(),
+            func,
+            wit: wit.clone(),
+        });
+        Ok(())
+    };
+    use wasmtime::component::types::ComponentItem;
+    for (name, item) in component.component_type().exports(engine) {
+        match item {
+            ComponentItem::ComponentFunc(func) => push(name.to_string(), func)?,
+            ComponentItem::ComponentInstance(instance) => {
+                for (func_name, item) in instance.exports(engine) {
+                    if let ComponentItem::ComponentFunc(func) = item {
+                        push(format!("{}#{}", name, func_name), func)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Core value types of the flattened ABI.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Flat {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl Flat {
+    /// wasm32 lowering of wit-parser's flat types.
+    fn of(ty: WasmType) -> Flat {
+        match ty {
+            WasmType::I32 | WasmType::Pointer | WasmType::Length => Flat::I32,
+            WasmType::I64 | WasmType::PointerOrI64 => Flat::I64,
+            WasmType::F32 => Flat::F32,
+            WasmType::F64 => Flat::F64,
+        }
+    }
+
+    fn from_core(ty: &ValType) -> Option<Flat> {
+        Some(match ty {
+            ValType::I32 => Flat::I32,
+            ValType::I64 => Flat::I64,
+            ValType::F32 => Flat::F32,
+            ValType::F64 => Flat::F64,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Flat::I32 => "i32",
+            Flat::I64 => "i64",
+            Flat::F32 => "f32",
+            Flat::F64 => "f64",
+        }
+    }
+}
+
+/// `(a b) -> (c)` in core type names.
+fn show_signature(params: &[Option<Flat>], results: &[Option<Flat>]) -> String {
+    let list = |types: &[Option<Flat>]| {
+        types
+            .iter()
+            .map(|ty| ty.map_or("<ref>", Flat::name))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    format!("({}) -> ({})", list(params), list(results))
+}
+
+/// The world's WIT types with their canonical ABI sizes and alignments.
+struct Wit {
+    resolve: Resolve,
+    sizes: SizeAlign,
+}
+
+/// Cases of a variant-like type (`variant`, `enum`, `option`, `result`):
+/// the discriminant and each case's payload type.
+struct Cases {
+    tag: Int,
+    payloads: Vec<Option<WitType>>,
+}
+
+impl Wit {
+    fn new(resolve: Resolve) -> Self {
+        let mut sizes = SizeAlign::default();
+        sizes.fill(&resolve);
+        Wit { resolve, sizes }
+    }
+
+    /// `ty` with type aliases followed.
+    fn unalias<'a>(&'a self, mut ty: &'a WitType) -> &'a WitType {
+        while let WitType::Id(id) = ty {
+            match &self.resolve.types[*id].kind {
+                TypeDefKind::Type(inner) => ty = inner,
+                _ => break,
+            }
+        }
+        ty
+    }
+
+    /// The definition behind `ty`; `None` for primitive types.
+    fn kind(&self, ty: &WitType) -> Option<&TypeDefKind> {
+        match self.unalias(ty) {
+            WitType::Id(id) => Some(&self.resolve.types[*id].kind),
+            _ => None,
+        }
+    }
+
+    fn size(&self, ty: &WitType) -> u32 {
+        self.sizes.size(ty).size_wasm32() as u32
+    }
+
+    fn align(&self, ty: &WitType) -> u32 {
+        self.sizes.align(ty).align_wasm32() as u32
+    }
+
+    /// Offsets of consecutive fields laid out as a record.
+    fn offsets(&self, types: &[WitType]) -> Vec<u32> {
+        self.sizes
+            .field_offsets(types)
+            .into_iter()
+            .map(|(offset, _)| offset.size_wasm32() as u32)
+            .collect()
+    }
+
+    fn flat(&self, ty: &WitType) -> Vec<Flat> {
+        let mut out = Vec::new();
+        self.resolve.push_flat(ty, &mut out);
+        out.into_iter().map(Flat::of).collect()
+    }
+
+    /// Field types of records and tuples.
+    fn fields(kind: &TypeDefKind) -> Option<Vec<WitType>> {
+        Some(match kind {
+            TypeDefKind::Record(r) => r.fields.iter().map(|f| f.ty).collect(),
+            TypeDefKind::Tuple(t) => t.types.clone(),
+            _ => return None,
+        })
+    }
+
+    fn cases(kind: &TypeDefKind) -> Option<Cases> {
+        Some(match kind {
+            TypeDefKind::Variant(v) => Cases {
+                tag: v.tag(),
+                payloads: v.cases.iter().map(|case| case.ty).collect(),
+            },
+            TypeDefKind::Enum(e) => Cases {
+                tag: e.tag(),
+                payloads: vec![None; e.cases.len()],
+            },
+            TypeDefKind::Option(t) => Cases {
+                tag: Int::U8,
+                payloads: vec![None, Some(*t)],
+            },
+            TypeDefKind::Result(r) => Cases {
+                tag: Int::U8,
+                payloads: vec![r.ok, r.err],
+            },
+            _ => return None,
+        })
+    }
+
+    fn payload_offset(&self, cases: &Cases) -> u32 {
+        self.sizes
+            .payload_offset(cases.tag, cases.payloads.iter().map(Option::as_ref))
+            .size_wasm32() as u32
+    }
+}
+
+fn tag_size(tag: Int) -> usize {
+    match tag {
+        Int::U8 => 1,
+        Int::U16 => 2,
+        Int::U32 => 4,
+        Int::U64 => 8,
+    }
+}
+
+fn zero(ty: Flat) -> wasmtime::Val {
+    match ty {
+        Flat::I32 => wasmtime::Val::I32(0),
+        Flat::I64 => wasmtime::Val::I64(0),
+        Flat::F32 => wasmtime::Val::F32(0),
+        Flat::F64 => wasmtime::Val::F64(0),
+    }
+}
+
+/// Reinterpret a case's flat value as the variant's joined type.
+fn widen(val: wasmtime::Val, to: Flat) -> wasmtime::Val {
+    use wasmtime::Val::*;
+    match (val, to) {
+        (F32(bits), Flat::I32) => I32(bits as i32),
+        (I32(v), Flat::I64) => I64(i64::from(v as u32)),
+        (F32(bits), Flat::I64) => I64(i64::from(bits)),
+        (F64(bits), Flat::I64) => I64(bits as i64),
+        (val, _) => val,
+    }
+}
+
+/// Undo `widen` for a case whose own flat type is `to`.
+fn narrow(val: wasmtime::Val, to: Flat) -> wasmtime::Val {
+    use wasmtime::Val::*;
+    match (val, to) {
+        (I32(v), Flat::F32) => F32(v as u32),
+        (I64(v), Flat::I32) => I32(v as i32),
+        (I64(v), Flat::F32) => F32(v as u32),
+        (I64(v), Flat::F64) => F64(v as u64),
+        (val, _) => val,
+    }
+}
+
+fn mismatch(val: &Val, ty: &WitType) -> anyhow::Error {
+    anyhow!("value {:?} does not match type {:?}", val, ty)
+}
+
+fn case_index(kind: &TypeDefKind, val: &Val) -> Result<(usize, Option<Val>)> {
+    Ok(match (kind, val) {
+        (TypeDefKind::Variant(v), Val::Variant(name, payload)) => (
+            v.cases
+                .iter()
+                .position(|case| &case.name == name)
+                .ok_or_else(|| anyhow!("unknown case {}", name))?,
+            payload.as_deref().cloned(),
+        ),
+        (TypeDefKind::Enum(e), Val::Enum(name)) => (
+            e.cases
+                .iter()
+                .position(|case| &case.name == name)
+                .ok_or_else(|| anyhow!("unknown enum case {}", name))?,
+            None,
+        ),
+        (TypeDefKind::Option(_), Val::Option(None)) => (0, None),
+        (TypeDefKind::Option(_), Val::Option(Some(v))) => (1, Some((**v).clone())),
+        (TypeDefKind::Result(_), Val::Result(Ok(v))) => (0, v.as_deref().cloned()),
+        (TypeDefKind::Result(_), Val::Result(Err(v))) => (1, v.as_deref().cloned()),
+        _ => bail!("value {:?} does not match the variant type", val),
+    })
+}
+
+fn make_case(kind: &TypeDefKind, index: usize, payload: Option<Val>) -> Result<Val> {
+    let payload = payload.map(Box::new);
+    let invalid = || anyhow!("invalid discriminant {}", index);
+    Ok(match kind {
+        TypeDefKind::Variant(v) => Val::Variant(
+            v.cases.get(index).ok_or_else(invalid)?.name.clone(),
+            payload,
+        ),
+        TypeDefKind::Enum(e) => Val::Enum(e.cases.get(index).ok_or_else(invalid)?.name.clone()),
+        TypeDefKind::Option(_) => match index {
+            0 => Val::Option(None),
+            1 => Val::Option(payload),
+            _ => return Err(invalid()),
+        },
+        TypeDefKind::Result(_) => match index {
+            0 => Val::Result(Ok(payload)),
+            1 => Val::Result(Err(payload)),
+            _ => return Err(invalid()),
+        },
+        _ => bail!("not a variant type"),
+    })
+}
+
+fn flag_bits(names: &[String], val: &Val) -> Result<Vec<u32>> {
+    let Val::Flags(set) = val else {
+        bail!("value {:?} is not flags", val);
+    };
+    let mut words = vec![0u32; names.len().div_ceil(32)];
+    for name in set {
+        let bit = names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| anyhow!("unknown flag {}", name))?;
+        words[bit / 32] |= 1 << (bit % 32);
+    }
+    Ok(words)
+}
+
+fn flags_from_bits(names: &[String], words: &[u32]) -> Val {
+    Val::Flags(
+        names
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| words[bit / 32] & (1 << (bit % 32)) != 0)
+            .map(|(_, name)| name.clone())
+            .collect(),
+    )
+}
+
+fn flag_names(kind: &TypeDefKind) -> Option<Vec<String>> {
+    match kind {
+        TypeDefKind::Flags(f) => Some(f.flags.iter().map(|flag| flag.name.clone()).collect()),
+        _ => None,
+    }
+}
+
+fn next(flat: &mut dyn Iterator<Item = wasmtime::Val>) -> Result<wasmtime::Val> {
+    flat.next().context("too few core values")
+}
+
+fn next_i32(flat: &mut dyn Iterator<Item = wasmtime::Val>) -> Result<i32> {
+    match next(flat)? {
+        wasmtime::Val::I32(v) => Ok(v),
+        val => bail!("expected an i32 core value, got {:?}", val),
+    }
+}
+
+fn next_i64(flat: &mut dyn Iterator<Item = wasmtime::Val>) -> Result<i64> {
+    match next(flat)? {
+        wasmtime::Val::I64(v) => Ok(v),
+        val => bail!("expected an i64 core value, got {:?}", val),
+    }
+}
+
+/// A core module instance driven through the canonical ABI.
+/// `ptr + offset`, or an error when the address does not fit in 32 bits.
+fn at(ptr: u32, offset: u32) -> Result<u32> {
+    ptr.checked_add(offset)
+        .with_context(|| format!("address {} + {} overflows", ptr, offset))
+}
+
+/// Address of element `index` of an array of `stride`-byte elements at `ptr`.
+fn element(ptr: u32, stride: u32, index: u32) -> Result<u32> {
+    stride
+        .checked_mul(index)
+        .and_then(|offset| ptr.checked_add(offset))
+        .with_context(|| format!("element {} of size {} at {} overflows", index, stride, ptr))
+}
+
+pub struct CoreAbi {
+    instance: Instance,
+    memory: Memory,
+    realloc: TypedFunc<(i32, i32, i32, i32), i32>,
+}
+
+impl CoreAbi {
+    /// Requires the module to export `memory` and `cabi_realloc`.
+    pub fn new(instance: Instance, mut store: impl AsContextMut) -> Result<Self> {
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("core module does not export `memory`")?;
+        let realloc = instance
+            .get_typed_func(&mut store, "cabi_realloc")
+            .context("core module does not export `cabi_realloc`")?;
+        Ok(CoreAbi {
+            instance,
+            memory,
+            realloc,
+        })
+    }
+
+    /// Call the core export for `sig` with `args`: lower, call, lift, then
+    /// `cabi_post_*`. Returns the lifted results and the raw core results.
+    pub fn call<T>(
+        &self,
+        mut store: impl AsContextMut<Data = T>,
+        sig: &Signature,
+        args: &[Val],
+    ) -> Result<(Vec<Val>, Vec<wasmtime::Val>)> {
+        let mut store = store.as_context_mut();
+        let wit = &*sig.wit;
+        if args.len() != sig.params.len() {
+            bail!(
+                "{} takes {} argument(s), got {}",
+                sig.name,
+                sig.params.len(),
+                args.len()
+            );
+        }
+        let func = self
+            .instance
+            .get_func(&mut store, &sig.name)
+            .with_context(|| format!("core module does not export {}", sig.name))?;
+
+        // The core export must have the flattened signature, or the values
+        // below would be read as the wrong types.
+        let expected = wit
+            .resolve
+            .wasm_signature(AbiVariant::GuestExport, &sig.func);
+        let flats = |types: Vec<WasmType>| -> Vec<_> {
+            types.into_iter().map(|ty| Some(Flat::of(ty))).collect()
+        };
+        let (expected_params, expected_results) = (flats(expected.params), flats(expected.results));
+        let ty = func.ty(&store);
+        let params: Vec<_> = ty.params().map(|ty| Flat::from_core(&ty)).collect();
+        let results: Vec<_> = ty.results().map(|ty| Flat::from_core(&ty)).collect();
+        if params != expected_params || results != expected_results {
+            bail!(
+                "core export {} has type {}, but its WIT signature flattens to {}",
+                sig.name,
+                show_signature(&params, &results),
+                show_signature(&expected_params, &expected_results)
+            );
+        }
+
+        let param_types: Vec<WitType> = sig.func.params.iter().map(|(_, ty)| *ty).collect();
+        let mut core_args = Vec::new();
+        if expected.indirect_params {
+            let info = wit.sizes.params(&param_types);
+            let ptr = self.alloc(
+                &mut store,
+                info.align.align_wasm32() as u32,
+                info.size.size_wasm32() as u32,
+            )?;
+            let offsets = wit.offsets(&param_types);
+            for ((val, ty), offset) in args.iter().zip(&param_types).zip(offsets) {
+                self.store(&mut store, wit, val, ty, at(ptr, offset)?)?;
+            }
+            core_args.push(wasmtime::Val::I32(ptr as i32));
+        } else {
+            for (val, ty) in args.iter().zip(&param_types) {
+                self.lower(&mut store, wit, val, ty, &mut core_args)?;
+            }
+        }
+
+        let mut core_results: Vec<_> = results.iter().flatten().map(|ty| zero(*ty)).collect();
+        func.call(&mut store, &core_args, &mut core_results)
+            .with_context(|| format!("{} trapped", sig.name))?;
+
+        let result_types: Vec<WitType> = sig.func.results.iter_types().copied().collect();
+        let results = if expected.retptr {
+            let mut flat = core_results.iter().copied();
+            let ptr = next_i32(&mut flat)? as u32;
+            let offsets = wit.offsets(&result_types);
+            result_types
+                .iter()
+                .zip(offsets)
+                .map(|(ty, offset)| self.load(&mut store, wit, ty, at(ptr, offset)?))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            let mut flat = core_results.iter().copied();
+            result_types
+                .iter()
+                .map(|ty| self.lift(&mut store, wit, ty, &mut flat))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let post = format!("cabi_post_{}", sig.name);
+        if let Some(post) = self.instance.get_func(&mut store, &post) {
+            post.call(&mut store, &core_results, &mut [])
+                .with_context(|| format!("cabi_post_{} trapped", sig.name))?;
+        }
+        Ok((results, core_results))
+    }
+
+    fn alloc<T>(&self, store: &mut StoreContextMut<'_, T>, align: u32, size: u32) -> Result<u32> {
+        let ptr = self
+            .realloc
+            .call(&mut *store, (0, 0, align as i32, size as i32))
+            .context("cabi_realloc trapped")? as u32;
+        if !ptr.is_multiple_of(align) {
+            bail!("cabi_realloc returned {} for alignment {}", ptr, align);
+        }
+        Ok(ptr)
+    }
+
+    fn write<T>(&self, store: &mut StoreContextMut<'_, T>, ptr: u32, bytes: &[u8]) -> Result<()> {
+        self.memory
+            .write(&mut *store, ptr as usize, bytes)
+            .map_err(|_| anyhow!("write of {} bytes at {} is out of bounds", bytes.len(), ptr))
+    }
+
+    fn read<const N: usize, T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        ptr: u32,
+    ) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.memory
+            .read(&mut *store, ptr as usize, &mut buf)
+            .map_err(|_| anyhow!("read of {} bytes at {} is out of bounds", N, ptr))?;
+        Ok(buf)
+    }
+
+    /// Fail unless `len` bytes at `ptr` are inside memory.
+    fn check_range<T>(&self, store: &mut StoreContextMut<'_, T>, ptr: u32, len: u64) -> Result<()> {
+        if u64::from(ptr) + len > self.memory.data_size(&mut *store) as u64 {
+            bail!("{} bytes at {} are out of bounds", len, ptr);
+        }
+        Ok(())
+    }
+
+    fn read_bytes<T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        ptr: u32,
+        len: u32,
+    ) -> Result<Vec<u8>> {
+        self.check_range(&mut *store, ptr, u64::from(len))?;
+        let mut buf = vec![0; len as usize];
+        self.memory
+            .read(&mut *store, ptr as usize, &mut buf)
+            .map_err(|_| anyhow!("read of {} bytes at {} is out of bounds", len, ptr))?;
+        Ok(buf)
+    }
+
+    /// Copy a string or list into freshly allocated memory: `(ptr, len)`.
+    fn store_sequence<T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        wit: &Wit,
+        val: &Val,
+        ty: &WitType,
+    ) -> Result<(u32, u32)> {
+        match (wit.kind(ty), val) {
+            (None, Val::String(s)) if *wit.unalias(ty) == WitType::String => {
+                let len = u32::try_from(s.len()).context("string is too long")?;
+                let ptr = self.alloc(&mut *store, 1, len)?;
+                self.write(&mut *store, ptr, s.as_bytes())?;
+                Ok((ptr, len))
+            }
+            (Some(TypeDefKind::List(elem)), Val::List(items)) => {
+                let stride = wit.size(elem);
+                let len = u32::try_from(items.len()).context("list is too long")?;
+                let size = stride.checked_mul(len).with_context(|| {
+                    format!("list of {} elements of size {} is too large", len, stride)
+                })?;
+                let ptr = self.alloc(&mut *store, wit.align(elem), size)?;
+                for (i, item) in (0..).zip(items) {
+                    self.store(&mut *store, wit, item, elem, element(ptr, stride, i)?)?;
+                }
+                Ok((ptr, len))
+            }
+            _ => Err(mismatch(val, ty)),
+        }
+    }
+
+    fn load_sequence<T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        wit: &Wit,
+        ty: &WitType,
+        ptr: u32,
+        len: u32,
+    ) -> Result<Val> {
+        match wit.kind(ty) {
+            Some(TypeDefKind::List(elem)) => {
+                let stride = wit.size(elem);
+                self.check_range(&mut *store, ptr, u64::from(stride) * u64::from(len))?;
+                Ok(Val::List(
+                    (0..len)
+                        .map(|i| self.load(&mut *store, wit, elem, element(ptr, stride, i)?))
+                        .collect::<Result<_>>()?,
+                ))
+            }
+            _ => {
+                let bytes = self.read_bytes(&mut *store, ptr, len)?;
+                Ok(Val::String(String::from_utf8(bytes).with_context(
+                    || format!("string at {} (len {}) is not UTF-8", ptr, len),
+                )?))
+            }
+        }
+    }
+
+    /// Write `val` at `ptr` in its memory representation.
+    fn store<T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        wit: &Wit,
+        val: &Val,
+        ty: &WitType,
+        ptr: u32,
+    ) -> Result<()> {
+        let Some(kind) = wit.kind(ty) else {
+            return match (wit.unalias(ty), val) {
+                (WitType::Bool, Val::Bool(b)) => self.write(&mut *store, ptr, &[*b as u8]),
+                (WitType::S8, Val::S8(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::U8, Val::U8(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::S16, Val::S16(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::U16, Val::U16(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::S32, Val::S32(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::U32, Val::U32(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::S64, Val::S64(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::U64, Val::U64(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::F32, Val::Float32(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::F64, Val::Float64(v)) => self.write(&mut *store, ptr, &v.to_le_bytes()),
+                (WitType::Char, Val::Char(c)) => {
+                    self.write(&mut *store, ptr, &(*c as u32).to_le_bytes())
+                }
+                (WitType::String, _) => {
+                    let (data, len) = self.store_sequence(&mut *store, wit, val, ty)?;
+                    self.write(&mut *store, ptr, &data.to_le_bytes())?;
+                    self.write(&mut *store, at(ptr, 4)?, &len.to_le_bytes())
+                }
+                _ => Err(mismatch(val, ty)),
+            };
+        };
+        if let TypeDefKind::List(_) = kind {
+            let (data, len) = self.store_sequence(&mut *store, wit, val, ty)?;
+            self.write(&mut *store, ptr, &data.to_le_bytes())?;
+            return self.write(&mut *store, at(ptr, 4)?, &len.to_le_bytes());
+        }
+        if let Some(names) = flag_names(kind) {
+            let words = flag_bits(&names, val)?;
+            return match wit.size(ty) {
+                0 => Ok(()),
+                1 => self.write(&mut *store, ptr, &[words[0] as u8]),
+                2 => self.write(&mut *store, ptr, &(words[0] as u16).to_le_bytes()),
+                _ => {
+                    for (i, word) in (0..).zip(&words) {
+                        self.write(&mut *store, element(ptr, 4, i)?, &word.to_le_bytes())?;
+                    }
+                    Ok(())
+                }
+            };
+        }
+        if let Some(types) = Wit::fields(kind) {
+            let vals: Vec<&Val> = match val {
+                Val::Record(fields) => fields.iter().map(|(_, v)| v).collect(),
+                Val::Tuple(items) => items.iter().collect(),
+                _ => return Err(mismatch(val, ty)),
+            };
+            let offsets = wit.offsets(&types);
+            for ((val, ty), offset) in vals.into_iter().zip(&types).zip(offsets) {
+                self.store(&mut *store, wit, val, ty, at(ptr, offset)?)?;
+            }
+            return Ok(());
+        }
+        let Some(cases) = Wit::cases(kind) else {
+            bail!("resources are not supported");
+        };
+        let (index, payload) = case_index(kind, val)?;
+        let disc = (index as u32).to_le_bytes();
+        self.write(&mut *store, ptr, &disc[..tag_size(cases.tag)])?;
+        if let (Some(payload), Some(payload_ty)) = (payload, &cases.payloads[index]) {
+            let payload_ptr = at(ptr, wit.payload_offset(&cases))?;
+            self.store(&mut *store, wit, &payload, payload_ty, payload_ptr)?;
+        }
+        Ok(())
+    }
+
+    /// Read a value of type `ty` from its memory representation at `ptr`.
+    fn load<T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        wit: &Wit,
+        ty: &WitType,
+        ptr: u32,
+    ) -> Result<Val> {
+        let Some(kind) = wit.kind(ty) else {
+            return Ok(match wit.unalias(ty) {
+                WitType::Bool => Val::Bool(self.read::<1, T>(&mut *store, ptr)?[0] != 0),
+                WitType::S8 => Val::S8(i8::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::U8 => Val::U8(u8::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::S16 => Val::S16(i16::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::U16 => Val::U16(u16::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::S32 => Val::S32(i32::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::U32 => Val::U32(u32::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::S64 => Val::S64(i64::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::U64 => Val::U64(u64::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::F32 => Val::Float32(f32::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::F64 => Val::Float64(f64::from_le_bytes(self.read(&mut *store, ptr)?)),
+                WitType::Char => {
+                    let code = u32::from_le_bytes(self.read(&mut *store, ptr)?);
+                    Val::Char(
+                        char::from_u32(code).with_context(|| format!("invalid char {}", code))?,
+                    )
+                }
+                _ => {
+                    let data = u32::from_le_bytes(self.read(&mut *store, ptr)?);
+                    let len = u32::from_le_bytes(self.read(&mut *store, at(ptr, 4)?)?);
+                    self.load_sequence(&mut *store, wit, ty, data, len)?
+                }
+            });
+        };
+        if let TypeDefKind::List(_) = kind {
+            let data = u32::from_le_bytes(self.read(&mut *store, ptr)?);
+            let len = u32::from_le_bytes(self.read(&mut *store, at(ptr, 4)?)?);
+            return self.load_sequence(&mut *store, wit, ty, data, len);
+        }
+        if let Some(names) = flag_names(kind) {
+            let words = match wit.size(ty) {
+                0 => vec![],
+                1 => vec![u32::from(self.read::<1, T>(&mut *store, ptr)?[0])],
+                2 => vec![u32::from(u16::from_le_bytes(self.read(&mut *store, ptr)?))],
+                n => (0..n / 4)
+                    .map(|i| {
+                        Ok(u32::from_le_bytes(
+                            self.read(&mut *store, element(ptr, 4, i)?)?,
+                        ))
+                    })
+                    .collect::<Result<_>>()?,
+            };
+            return Ok(flags_from_bits(&names, &words));
+        }
+        if let Some(types) = Wit::fields(kind) {
+            let offsets = wit.offsets(&types);
+            let vals = types
+                .iter()
+                .zip(offsets)
+                .map(|(ty, offset)| self.load(&mut *store, wit, ty, at(ptr, offset)?))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(match kind {
+                TypeDefKind::Record(r) => {
+                    Val::Record(r.fields.iter().map(|f| f.name.clone()).zip(vals).collect())
+                }
+                _ => Val::Tuple(vals),
+            });
+        }
+        let Some(cases) = Wit::cases(kind) else {
+            bail!("resources are not supported");
+        };
+        let mut disc = [0u8; 4];
+        self.memory
+            .read(&mut *store, ptr as usize, &mut disc[..tag_size(cases.tag)])
+            .map_err(|_| anyhow!("read at {} is out of bounds", ptr))?;
+        let index = u32::from_le_bytes(disc) as usize;
+        let payload = match cases.payloads.get(index) {
+            Some(Some(payload_ty)) => {
+                let payload_ptr = at(ptr, wit.payload_offset(&cases))?;
+                Some(self.load(&mut *store, wit, payload_ty, payload_ptr)?)
+            }
+            Some(None) => None,
+            None => bail!("invalid discriminant {} at {}", index, ptr),
+        };
+        make_case(kind, index, payload)
+    }
+
+    /// Append the flat core values of `val`.
+    fn lower<T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        wit: &Wit,
+        val: &Val,
+        ty: &WitType,
+        out: &mut Vec<wasmtime::Val>,
+    ) -> Result<()> {
+        use wasmtime::Val as Core;
+        let Some(kind) = wit.kind(ty) else {
+            match (wit.unalias(ty), val) {
+                (WitType::Bool, Val::Bool(b)) => out.push(Core::I32(*b as i32)),
+                (WitType::S8, Val::S8(v)) => out.push(Core::I32(i32::from(*v))),
+                (WitType::U8, Val::U8(v)) => out.push(Core::I32(i32::from(*v))),
+                (WitType::S16, Val::S16(v)) => out.push(Core::I32(i32::from(*v))),
+                (WitType::U16, Val::U16(v)) => out.push(Core::I32(i32::from(*v))),
+                (WitType::S32, Val::S32(v)) => out.push(Core::I32(*v)),
+                (WitType::U32, Val::U32(v)) => out.push(Core::I32(*v as i32)),
+                (WitType::S64, Val::S64(v)) => out.push(Core::I64(*v)),
+                (WitType::U64, Val::U64(v)) => out.push(Core::I64(*v as i64)),
+                (WitType::F32, Val::Float32(v)) => out.push(Core::F32(v.to_bits())),
+                (WitType::F64, Val::Float64(v)) => out.push(Core::F64(v.to_bits())),
+                (WitType::Char, Val::Char(c)) => out.push(Core::I32(*c as i32)),
+                (WitType::String, _) => {
+                    let (ptr, len) = self.store_sequence(&mut *store, wit, val, ty)?;
+                    out.extend([Core::I32(ptr as i32), Core::I32(len as i32)]);
+                }
+                _ => return Err(mismatch(val, ty)),
+            }
+            return Ok(());
+        };
+        if let TypeDefKind::List(_) = kind {
+            let (ptr, len) = self.store_sequence(&mut *store, wit, val, ty)?;
+            out.extend([Core::I32(ptr as i32), Core::I32(len as i32)]);
+            return Ok(());
+        }
+        if let Some(names) = flag_names(kind) {
+            let words = flag_bits(&names, val)?;
+            out.extend(words.into_iter().map(|w| Core::I32(w as i32)));
+            return Ok(());
+        }
+        if let Some(types) = Wit::fields(kind) {
+            let vals: Vec<&Val> = match val {
+                Val::Record(fields) => fields.iter().map(|(_, v)| v).collect(),
+                Val::Tuple(items) => items.iter().collect(),
+                _ => return Err(mismatch(val, ty)),
+            };
+            for (val, ty) in vals.into_iter().zip(&types) {
+                self.lower(&mut *store, wit, val, ty, out)?;
+            }
+            return Ok(());
+        }
+        let Some(cases) = Wit::cases(kind) else {
+            bail!("resources are not supported");
+        };
+        let (index, payload) = case_index(kind, val)?;
+        let flat = wit.flat(ty);
+        out.push(Core::I32(index as i32));
+        let mut payload_vals = Vec::new();
+        if let (Some(payload), Some(payload_ty)) = (payload, &cases.payloads[index]) {
+            self.lower(&mut *store, wit, &payload, payload_ty, &mut payload_vals)?;
+        }
+        for (i, joined) in flat[1..].iter().enumerate() {
+            out.push(match payload_vals.get(i) {
+                Some(val) => widen(*val, *joined),
+                None => zero(*joined),
+            });
+        }
+        Ok(())
+    }
+
+    /// Lift a value of type `ty` from the flat core values in `flat`.
+    fn lift<T>(
+        &self,
+        store: &mut StoreContextMut<'_, T>,
+        wit: &Wit,
+        ty: &WitType,
+        flat: &mut dyn Iterator<Item = wasmtime::Val>,
+    ) -> Result<Val> {
+        let Some(kind) = wit.kind(ty) else {
+            return Ok(match wit.unalias(ty) {
+                WitType::Bool => Val::Bool(next_i32(flat)? != 0),
+                WitType::S8 => Val::S8(next_i32(flat)? as i8),
+                WitType::U8 => Val::U8(next_i32(flat)? as u8),
+                WitType::S16 => Val::S16(next_i32(flat)? as i16),
+                WitType::U16 => Val::U16(next_i32(flat)? as u16),
+                WitType::S32 => Val::S32(next_i32(flat)?),
+                WitType::U32 => Val::U32(next_i32(flat)? as u32),
+                WitType::S64 => Val::S64(next_i64(flat)?),
+                WitType::U64 => Val::U64(next_i64(flat)? as u64),
+                WitType::F32 => match next(flat)? {
+                    wasmtime::Val::F32(bits) => Val::Float32(f32::from_bits(bits)),
+                    val => bail!("expected an f32 core value, got {:?}", val),
+                },
+                WitType::F64 => match next(flat)? {
+                    wasmtime::Val::F64(bits) => Val::Float64(f64::from_bits(bits)),
+                    val => bail!("expected an f64 core value, got {:?}", val),
+                },
+                WitType::Char => {
+                    let code = next_i32(flat)? as u32;
+                    Val::Char(
+                        char::from_u32(code).with_context(|| format!("invalid char {}", code))?,
+                    )
+                }
+                _ => {
+                    let ptr = next_i32(flat)? as u32;
+                    let len = next_i32(flat)? as u32;
+                    self.load_sequence(&mut *store, wit, ty, ptr, len)?
+                }
+            });
+        };
+        if let TypeDefKind::List(_) = kind {
+            let ptr = next_i32(flat)? as u32;
+            let len = next_i32(flat)? as u32;
+            return self.load_sequence(&mut *store, wit, ty, ptr, len);
+        }
+        if let Some(names) = flag_names(kind) {
+            let words = (0..names.len().div_ceil(32))
+                .map(|_| Ok(next_i32(flat)? as u32))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(flags_from_bits(&names, &words));
+        }
+        if let Some(types) = Wit::fields(kind) {
+            let vals = types
+                .iter()
+                .map(|ty| self.lift(&mut *store, wit, ty, flat))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(match kind {
+                TypeDefKind::Record(r) => {
+                    Val::Record(r.fields.iter().map(|f| f.name.clone()).zip(vals).collect())
+                }
+                _ => Val::Tuple(vals),
+            });
+        }
+        let Some(cases) = Wit::cases(kind) else {
+            bail!("resources are not supported");
+        };
+        let joined = wit.flat(ty);
+        let index = next_i32(flat)? as usize;
+        let slots = (1..joined.len())
+            .map(|_| next(flat))
+            .collect::<Result<Vec<_>>>()?;
+        let payload = match cases.payloads.get(index) {
+            Some(Some(payload_ty)) => {
+                let own = wit.flat(payload_ty);
+                let mut vals = slots.into_iter().zip(own).map(|(val, ty)| narrow(val, ty));
+                Some(self.lift(&mut *store, wit, payload_ty, &mut vals)?)
+            }
+            Some(None) => None,
+            None => bail!("invalid discriminant {}", index),
+        };
+        make_case(kind, index, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Module, Store};
+
+    /// Bump allocator behind `cabi_realloc`, enough for lowering.
+    const CORE: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 16))
+          (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr
+              (i32.and
+                (i32.add (global.get $next) (i32.sub (local.get 2) (i32.const 1)))
+                (i32.sub (i32.const 0) (local.get 2))))
+            (global.set $next (i32.add (local.get $ptr) (local.get 3)))
+            (local.get $ptr))
+          (func (export "add") (param i32 i32) (result i32)
+            (i32.add (local.get 0) (local.get 1)))
+          (func (export "wrong") (param i32) (result i32)
+            (local.get 0)))
+    "#;
+
+    fn wit() -> Wit {
+        let cases = |n: usize| {
+            (0..n)
+                .map(|i| format!("c{}", i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let flags = (0..33)
+            .map(|i| format!("b{}", i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let src = format!(
+            "package test:cabi;
+            interface types {{
+                record point {{ x: u8, y: u32, z: u16 }}
+                variant shape {{ none, circle(f32), rect(tuple<u64, u8>), name(string) }}
+                flags small {{ a, b, c }}
+                flags wide {{ {flags} }}
+                enum byte-tag {{ {byte} }}
+                enum short-tag {{ {short} }}
+                type points = list<point>;
+                type text = string;
+            }}",
+            flags = flags,
+            byte = cases(256),
+            short = cases(257),
+        );
+        let mut resolve = Resolve::default();
+        resolve.push_str("test.wit", &src).unwrap();
+        Wit::new(resolve)
+    }
+
+    fn ty(wit: &Wit, name: &str) -> WitType {
+        let iface = wit.resolve.interfaces.iter().next().unwrap().1;
+        WitType::Id(iface.types[name])
+    }
+
+    fn instance() -> (Store<()>, CoreAbi) {
+        let engine = Engine::default();
+        let module = Module::new(&engine, CORE).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let abi = CoreAbi::new(instance, &mut store).unwrap();
+        (store, abi)
+    }
+
+    fn flat_of(val: &wasmtime::Val) -> Flat {
+        match val {
+            wasmtime::Val::I32(_) => Flat::I32,
+            wasmtime::Val::I64(_) => Flat::I64,
+            wasmtime::Val::F32(_) => Flat::F32,
+            wasmtime::Val::F64(_) => Flat::F64,
+            val => panic!("not a flat value: {:?}", val),
+        }
+    }
+
+    /// Lower then lift `val` through core values, and store then load it
+    /// through memory.
+    fn round_trip(wit: &Wit, name: &str, val: Val) {
+        let ty = ty(wit, name);
+        let (mut store, abi) = instance();
+        let mut cx = store.as_context_mut();
+
+        let mut flat = Vec::new();
+        abi.lower(&mut cx, wit, &val, &ty, &mut flat).unwrap();
+        assert_eq!(
+            flat.iter().map(flat_of).collect::<Vec<_>>(),
+            wit.flat(&ty),
+            "{}",
+            name
+        );
+        let lifted = abi.lift(&mut cx, wit, &ty, &mut flat.into_iter()).unwrap();
+        assert_eq!(lifted, val, "{} through core values", name);
+
+        let ptr = abi.alloc(&mut cx, wit.align(&ty), wit.size(&ty)).unwrap();
+        abi.store(&mut cx, wit, &val, &ty, ptr).unwrap();
+        let loaded = abi.load(&mut cx, wit, &ty, ptr).unwrap();
+        assert_eq!(loaded, val, "{} through memory", name);
+    }
+
+    #[test]
+    fn layout_matches_the_canonical_abi() {
+        let wit = wit();
+        let point = ty(&wit, "point");
+        assert_eq!((wit.size(&point), wit.align(&point)), (12, 4));
+        let Some(TypeDefKind::Record(record)) = wit.kind(&point) else {
+            panic!("point is not a record");
+        };
+        let fields: Vec<_> = record.fields.iter().map(|f| f.ty).collect();
+        assert_eq!(wit.offsets(&fields), [0, 4, 8]);
+
+        let shape = ty(&wit, "shape");
+        assert_eq!((wit.size(&shape), wit.align(&shape)), (24, 8));
+        let cases = Wit::cases(wit.kind(&shape).unwrap()).unwrap();
+        assert_eq!(wit.payload_offset(&cases), 8);
+
+        assert_eq!(wit.size(&ty(&wit, "small")), 1);
+        assert_eq!(wit.size(&ty(&wit, "wide")), 8);
+        // 256 cases still fit a one-byte discriminant; 257 do not.
+        assert_eq!(wit.size(&ty(&wit, "byte-tag")), 1);
+        assert_eq!(wit.size(&ty(&wit, "short-tag")), 2);
+        assert_eq!(
+            (
+                wit.size(&ty(&wit, "points")),
+                wit.align(&ty(&wit, "points"))
+            ),
+            (8, 4)
+        );
+    }
+
+    #[test]
+    fn flat_types_join_variant_payloads() {
+        let wit = wit();
+        assert_eq!(wit.flat(&ty(&wit, "point")), [Flat::I32; 3]);
+        assert_eq!(
+            wit.flat(&ty(&wit, "shape")),
+            [Flat::I32, Flat::I64, Flat::I32]
+        );
+        assert_eq!(wit.flat(&ty(&wit, "small")), [Flat::I32]);
+        assert_eq!(wit.flat(&ty(&wit, "wide")), [Flat::I32, Flat::I32]);
+        assert_eq!(wit.flat(&ty(&wit, "points")), [Flat::I32, Flat::I32]);
+        assert_eq!(wit.flat(&WitType::F64), [Flat::F64]);
+    }
+
+    #[test]
+    fn round_trips_records_variants_flags_lists_and_strings() {
+        let wit = wit();
+        let point = |x, y, z| {
+            Val::Record(vec![
+                ("x".to_string(), Val::U8(x)),
+                ("y".to_string(), Val::U32(y)),
+                ("z".to_string(), Val::U16(z)),
+            ])
+        };
+        round_trip(&wit, "point", point(1, 0xdead_beef, 3));
+
+        round_trip(&wit, "shape", Val::Variant("none".to_string(), None));
+        round_trip(
+            &wit,
+            "shape",
+            Val::Variant("circle".to_string(), Some(Box::new(Val::Float32(1.5)))),
+        );
+        round_trip(
+            &wit,
+            "shape",
+            Val::Variant(
+                "rect".to_string(),
+                Some(Box::new(Val::Tuple(vec![Val::U64(u64::MAX), Val::U8(7)]))),
+            ),
+        );
+        round_trip(
+            &wit,
+            "shape",
+            Val::Variant(
+                "name".to_string(),
+                Some(Box::new(Val::String("hé".to_string()))),
+            ),
+        );
+
+        round_trip(
+            &wit,
+            "small",
+            Val::Flags(vec!["a".to_string(), "c".to_string()]),
+        );
+        round_trip(
+            &wit,
+            "wide",
+            Val::Flags(vec!["b0".to_string(), "b32".to_string()]),
+        );
+        round_trip(&wit, "byte-tag", Val::Enum("c255".to_string()));
+        round_trip(&wit, "short-tag", Val::Enum("c256".to_string()));
+
+        round_trip(
+            &wit,
+            "points",
+            Val::List(vec![point(1, 2, 3), point(4, 5, 6)]),
+        );
+        round_trip(&wit, "points", Val::List(Vec::new()));
+        round_trip(&wit, "text", Val::String("MoonBit".to_string()));
+    }
+
+    #[test]
+    fn guest_pointers_and_lengths_are_checked() {
+        let wit = wit();
+        let (mut store, abi) = instance();
+        let mut cx = store.as_context_mut();
+        let lift = |cx: &mut StoreContextMut<'_, ()>, name: &str, ptr: u32, len: u32| {
+            let flat = [
+                wasmtime::Val::I32(ptr as i32),
+                wasmtime::Val::I32(len as i32),
+            ];
+            abi.lift(cx, &wit, &ty(&wit, name), &mut flat.into_iter())
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            lift(&mut cx, "text", 16, u32::MAX),
+            "4294967295 bytes at 16 are out of bounds"
+        );
+        assert_eq!(
+            lift(&mut cx, "points", u32::MAX - 4, 2),
+            "24 bytes at 4294967291 are out of bounds"
+        );
+
+        assert_eq!(
+            at(u32::MAX - 2, 4).err().unwrap().to_string(),
+            "address 4294967293 + 4 overflows"
+        );
+        assert!(element(16, 12, u32::MAX).is_err());
+        let point = ty(&wit, "point");
+        assert!(abi.load(&mut cx, &wit, &point, u32::MAX - 2).is_err());
+        let text = ty(&wit, "text");
+        let val = Val::String("x".to_string());
+        assert!(abi.store(&mut cx, &wit, &val, &text, u32::MAX - 2).is_err());
+    }
+
+    #[test]
+    fn call_checks_the_core_signature() {
+        let dir = std::env::temp_dir().join(format!("cabi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wit_path = dir.join("world.wit");
+        std::fs::write(
+            &wit_path,
+            "package test:sig;
+            world w {
+                export add: func(a: u32, b: u32) -> u32;
+                export wrong: func(a: u64) -> u32;
+            }",
+        )
+        .unwrap();
+        let (mut store, abi) = instance();
+        let engine = store.engine().clone();
+        let sigs = signatures(&engine, wit_path.to_str().unwrap(), None).unwrap();
+        let sig = |name: &str| sigs.iter().find(|sig| sig.name == name).unwrap();
+
+        let (results, _) = abi
+            .call(&mut store, sig("add"), &[Val::U32(2), Val::U32(3)])
+            .unwrap();
+        assert_eq!(results, [Val::U32(5)]);
+
+        let err = abi
+            .call(&mut store, sig("wrong"), &[Val::U64(1)])
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            err,
+            "core export wrong has type (i32) -> (i32), but its WIT signature flattens to (i64) -> (i32)"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,566 @@
+// Differential componentize (`componentize-diff`)
+//
+// Builds the component `moon-component componentize` makes from a core module
+// and a WIT directory, and a reference from the same inputs with
+// wit-component's ComponentEncoder (after the same retptr import patch), then
+// compares them by structure rather than bytes:
+//
+//   - top-level import and export names
+//   - component types: every item of every import and export, as the WIT
+//     type it decodes to
+//   - canonical options of every lift and lower: string encoding, memory,
+//     realloc and post-return, by the core export they refer to
+//
+// With `--suite`, the rust-host test suites also run against both. Mistakes
+// here still validate; they show up as a wrong encoding or a missing
+// post-return long after componentize has succeeded.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wasm_encoder::reencode::{self, utils, Reencode};
+use wasmparser::{
+    CanonicalFunction, CanonicalOption, ComponentAlias, ComponentExternalKind, ComponentTypeRef,
+    CompositeInnerType, ExternalKind, Operator, Parser, Payload, TypeRef, ValType,
+};
+use wit_component::{ComponentEncoder, StringEncoding};
+use wit_parser::Resolve;
+
+use crate::abi_check;
+use crate::wit_surface::Surface;
+
+const USAGE: &str = "usage: rust-host componentize-diff <core.wasm> --wit-dir DIR [--world NAME] \
+[--actual COMPONENT] [--out DIR] [--suite types|import|guest]...";
+
+struct Plan {
+    core: String,
+    wit: String,
+    world: Option<String>,
+    /// An existing `moon-component componentize` output, instead of running it.
+    actual: Option<String>,
+    out: Option<PathBuf>,
+    suites: Vec<String>,
+}
+
+fn parse_plan(args: &[String]) -> Result<Plan> {
+    let mut core = None;
+    let mut plan = Plan {
+        core: String::new(),
+        wit: String::new(),
+        world: None,
+        actual: None,
+        out: None,
+        suites: Vec::new(),
+    };
+    let mut wit = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .with_context(|| format!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "--wit" | "--wit-dir" => wit = Some(value(arg)?),
+            "--world" => plan.world = Some(value(arg)?),
+            "--actual" => plan.actual = Some(value(arg)?),
+            "--out" => plan.out = Some(value(arg)?.into()),
+            "--suite" => {
+                let suite = value(arg)?;
+                if !matches!(suite.as_str(), "types" | "import" | "guest") {
+                    bail!(
+                        "unknown suite {:?} (expected types, import or guest)",
+                        suite
+                    );
+                }
+                plan.suites.push(suite);
+            }
+            _ if arg.starts_with("--") => bail!("unexpected argument: {}\n{}", arg, USAGE),
+            _ if core.is_none() => core = Some(arg.clone()),
+            _ => bail!("unexpected argument: {}\n{}", arg, USAGE),
+        }
+    }
+    plan.core = core.context(USAGE)?;
+    plan.wit = wit.context(USAGE)?;
+    Ok(plan)
+}
+
+fn moon_component_bin() -> Result<PathBuf> {
+    if let Some(bin) = std::env::var_os("MOON_COMPONENT_BIN") {
+        return Ok(bin.into());
+    }
+    std::env::var_os("PATH")
+        .into_iter()
+        .flat_map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .map(|dir| dir.join("moon-component"))
+        .find(|bin| bin.is_file())
+        .or_else(|| {
+            // The repository's native release build, as the test harnesses use.
+            let bin = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../../_build/native/release/build/src/cmd/moon-component/moon-component.exe");
+            bin.is_file().then_some(bin)
+        })
+        .context("moon-component not found; set MOON_COMPONENT_BIN or pass --actual")
+}
+
+/// `moon-component componentize`, writing to `out`.
+fn componentize(plan: &Plan, out: &Path) -> Result<()> {
+    let bin = moon_component_bin()?;
+    let mut cmd = Command::new(&bin);
+    cmd.arg("componentize")
+        .arg(&plan.core)
+        .arg("--wit-dir")
+        .arg(&plan.wit);
+    if let Some(world) = &plan.world {
+        cmd.arg("--world").arg(world);
+    }
+    let output = cmd
+        .arg("-o")
+        .arg(out)
+        .output()
+        .with_context(|| format!("failed to run {}", bin.display()))?;
+    if !output.status.success() {
+        bail!(
+            "moon-component componentize failed:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// The retptr import patch componentize applies to the `moon build` output:
+/// patched imports keep their parameters and lose their result, and the
+/// `drop` after each call to them goes away.
+struct RetptrPatch {
+    /// New type index of each patched import.
+    imports: HashMap<(String, String), u32>,
+    /// Parameters of the types appended after the module's own.
+    types: Vec<Vec<wasm_encoder::ValType>>,
+    funcs: HashSet<u32>,
+}
+
+fn encoder_type(ty: ValType) -> Result<wasm_encoder::ValType> {
+    Ok(match ty {
+        ValType::I32 => wasm_encoder::ValType::I32,
+        ValType::I64 => wasm_encoder::ValType::I64,
+        ValType::F32 => wasm_encoder::ValType::F32,
+        ValType::F64 => wasm_encoder::ValType::F64,
+        other => bail!("unexpected {} parameter on a retptr import", other),
+    })
+}
+
+impl RetptrPatch {
+    fn new(bytes: &[u8], patched: &[(String, String)]) -> Result<Self> {
+        let mut params: Vec<Option<Vec<ValType>>> = Vec::new();
+        let mut patch = RetptrPatch {
+            imports: HashMap::new(),
+            types: Vec::new(),
+            funcs: HashSet::new(),
+        };
+        let mut func = 0;
+        for payload in Parser::new(0).parse_all(bytes) {
+            match payload? {
+                Payload::TypeSection(reader) => {
+                    for group in reader {
+                        for sub in group?.into_types() {
+                            params.push(match sub.composite_type.inner {
+                                CompositeInnerType::Func(f) => Some(f.params().to_vec()),
+                                _ => None,
+                            });
+                        }
+                    }
+                }
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        let import = import?;
+                        let TypeRef::Func(ty) = import.ty else {
+                            continue;
+                        };
+                        let name = (import.module.to_string(), import.name.to_string());
+                        if patched.contains(&name) {
+                            let ty = params
+                                .get(ty as usize)
+                                .cloned()
+                                .flatten()
+                                .with_context(|| format!("type {} is not a function type", ty))?;
+                            let index = (params.len() + patch.types.len()) as u32;
+                            patch.imports.insert(name, index);
+                            patch
+                                .types
+                                .push(ty.into_iter().map(encoder_type).collect::<Result<_>>()?);
+                            patch.funcs.insert(func);
+                        }
+                        func += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(patch)
+    }
+
+    fn apply(mut self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut module = wasm_encoder::Module::new();
+        self.parse_core_module(&mut module, Parser::new(0), bytes)?;
+        Ok(module.finish())
+    }
+}
+
+impl Reencode for RetptrPatch {
+    type Error = std::convert::Infallible;
+
+    fn parse_type_section(
+        &mut self,
+        types: &mut wasm_encoder::TypeSection,
+        section: wasmparser::TypeSectionReader<'_>,
+    ) -> Result<(), reencode::Error<Self::Error>> {
+        utils::parse_type_section(self, types, section)?;
+        for params in &self.types {
+            types.ty().function(params.iter().copied(), []);
+        }
+        Ok(())
+    }
+
+    fn parse_import(
+        &mut self,
+        imports: &mut wasm_encoder::ImportSection,
+        import: wasmparser::Import<'_>,
+    ) -> Result<(), reencode::Error<Self::Error>> {
+        let name = (import.module.to_string(), import.name.to_string());
+        match self.imports.get(&name) {
+            Some(ty) => {
+                imports.import(
+                    import.module,
+                    import.name,
+                    wasm_encoder::EntityType::Function(*ty),
+                );
+                Ok(())
+            }
+            None => utils::parse_import(self, imports, import),
+        }
+    }
+
+    fn parse_function_body(
+        &mut self,
+        code: &mut wasm_encoder::CodeSection,
+        func: wasmparser::FunctionBody<'_>,
+    ) -> Result<(), reencode::Error<Self::Error>> {
+        let mut f = self.new_function_with_parsed_locals(&func)?;
+        let mut reader = func.get_operators_reader()?;
+        let mut after_patched_call = false;
+        while !reader.eof() {
+            let op = reader.read()?;
+            if after_patched_call && matches!(op, Operator::Drop) {
+                after_patched_call = false;
+                continue;
+            }
+            after_patched_call = matches!(op, Operator::Call { function_index }
+                if self.funcs.contains(&function_index));
+            f.instruction(&self.instruction(op)?);
+        }
+        code.function(&f);
+        Ok(())
+    }
+}
+
+/// The ComponentEncoder build of `plan`'s core module.
+fn reference(plan: &Plan) -> Result<Vec<u8>> {
+    let mut resolve = Resolve::default();
+    let (package, _) = resolve
+        .push_path(&plan.wit)
+        .with_context(|| format!("failed to parse WIT {}", plan.wit))?;
+    let world = resolve.select_world(package, plan.world.as_deref())?;
+    let bytes =
+        std::fs::read(&plan.core).with_context(|| format!("failed to read {}", plan.core))?;
+
+    let patched = abi_check::retptr_imports(&resolve, world, &bytes)?;
+    let mut module = if patched.is_empty() {
+        bytes
+    } else {
+        println!("Patching {} retptr import(s)", patched.len());
+        RetptrPatch::new(&bytes, &patched)?.apply(&bytes)?
+    };
+    wit_component::embed_component_metadata(&mut module, &resolve, world, StringEncoding::UTF8)?;
+    ComponentEncoder::default()
+        .module(&module)?
+        .validate(true)
+        .encode()
+        .context("wit-component failed to encode the reference component")
+}
+
+/// What the comparison looks at in one component.
+#[derive(Default)]
+struct Structure {
+    imports: BTreeSet<String>,
+    exports: BTreeSet<String>,
+    /// Canonical options by `lift CORE-EXPORT` or `lower IMPORT#FUNC`.
+    canon: BTreeMap<String, Vec<String>>,
+}
+
+fn read_structure(bytes: &[u8]) -> Result<Structure> {
+    let mut out = Structure::default();
+    // Names in the top-level index spaces the options refer to.
+    let mut instances: Vec<String> = Vec::new();
+    let mut funcs: Vec<String> = Vec::new();
+    let mut core_funcs: Vec<String> = Vec::new();
+    let mut core_memories: Vec<String> = Vec::new();
+    let mut depth = 0;
+
+    for payload in Parser::new(0).parse_all(bytes) {
+        let payload = payload?;
+        match &payload {
+            Payload::ModuleSection { .. } | Payload::ComponentSection { .. } => {
+                depth += 1;
+                continue;
+            }
+            Payload::End(_) if depth > 0 => {
+                depth -= 1;
+                continue;
+            }
+            _ if depth > 0 => continue,
+            _ => {}
+        }
+        match payload {
+            Payload::ComponentImportSection(reader) => {
+                for import in reader {
+                    let import = import?;
+                    let name = import.name.0.to_string();
+                    match import.ty {
+                        ComponentTypeRef::Instance(_) => instances.push(name.clone()),
+                        ComponentTypeRef::Func(_) => funcs.push(name.clone()),
+                        _ => {}
+                    }
+                    out.imports.insert(name);
+                }
+            }
+            Payload::ComponentExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    let name = export.name.0.to_string();
+                    match export.kind {
+                        ComponentExternalKind::Instance => instances.push(name.clone()),
+                        ComponentExternalKind::Func => funcs.push(name.clone()),
+                        _ => {}
+                    }
+                    out.exports.insert(name);
+                }
+            }
+            Payload::ComponentInstanceSection(reader) => {
+                for instance in reader {
+                    instance?;
+                    instances.push(format!("instance {}", instances.len()));
+                }
+            }
+            Payload::ComponentAliasSection(reader) => {
+                for alias in reader {
+                    match alias? {
+                        ComponentAlias::InstanceExport {
+                            kind,
+                            instance_index,
+                            name,
+                        } => {
+                            let instance = instances
+                                .get(instance_index as usize)
+                                .cloned()
+                                .unwrap_or_default();
+                            let name = format!("{}#{}", instance, name);
+                            match kind {
+                                ComponentExternalKind::Func => funcs.push(name),
+                                ComponentExternalKind::Instance => instances.push(name),
+                                _ => {}
+                            }
+                        }
+                        ComponentAlias::CoreInstanceExport { kind, name, .. } => match kind {
+                            ExternalKind::Func => core_funcs.push(name.to_string()),
+                            ExternalKind::Memory => core_memories.push(name.to_string()),
+                            _ => {}
+                        },
+                        ComponentAlias::Outer { .. } => {}
+                    }
+                }
+            }
+            Payload::ComponentCanonicalSection(reader) => {
+                for canon in reader {
+                    let name = |list: &[String], index: u32| {
+                        list.get(index as usize)
+                            .cloned()
+                            .unwrap_or_else(|| format!("#{}", index))
+                    };
+                    let describe =
+                        |options: &[CanonicalOption]| {
+                            let mut encoding = "utf8";
+                            let mut described = Vec::new();
+                            for option in options {
+                                match option {
+                                    CanonicalOption::UTF8 => encoding = "utf8",
+                                    CanonicalOption::UTF16 => encoding = "utf16",
+                                    CanonicalOption::CompactUTF16 => encoding = "latin1+utf16",
+                                    CanonicalOption::Memory(i) => described
+                                        .push(format!("memory={}", name(&core_memories, *i))),
+                                    CanonicalOption::Realloc(i) => {
+                                        described.push(format!("realloc={}", name(&core_funcs, *i)))
+                                    }
+                                    CanonicalOption::PostReturn(i) => described
+                                        .push(format!("post-return={}", name(&core_funcs, *i))),
+                                }
+                            }
+                            described.insert(0, format!("string-encoding={}", encoding));
+                            described.join(" ")
+                        };
+                    match canon? {
+                        CanonicalFunction::Lift {
+                            core_func_index,
+                            options,
+                            ..
+                        } => {
+                            let key = format!("lift {}", name(&core_funcs, core_func_index));
+                            let options = describe(&options);
+                            out.canon.entry(key.clone()).or_default().push(options);
+                            funcs.push(key);
+                        }
+                        CanonicalFunction::Lower {
+                            func_index,
+                            options,
+                        } => {
+                            let key = format!("lower {}", name(&funcs, func_index));
+                            let options = describe(&options);
+                            out.canon.entry(key.clone()).or_default().push(options);
+                            core_funcs.push(key);
+                        }
+                        _ => core_funcs.push(format!("canon {}", core_funcs.len())),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Print the differences between `reference` and `actual`; returns how many.
+fn compare(reference: &[u8], actual: &[u8]) -> Result<usize> {
+    let expected = read_structure(reference)?;
+    let found = read_structure(actual)?;
+    let mut differences = 0;
+
+    for (label, expected, found) in [
+        ("Imports", &expected.imports, &found.imports),
+        ("Exports", &expected.exports, &found.exports),
+    ] {
+        println!("\n{}:", label);
+        for name in expected.union(found) {
+            match (expected.contains(name), found.contains(name)) {
+                (true, true) => println!("  ✓ {}", name),
+                (true, false) => println!("  ✗ {} (missing from moon-component's)", name),
+                _ => println!("  ✗ {} (only in moon-component's)", name),
+            }
+        }
+        differences += expected.symmetric_difference(found).count();
+    }
+
+    println!("\nCanonical options:");
+    let keys: BTreeSet<&String> = expected.canon.keys().chain(found.canon.keys()).collect();
+    for key in keys {
+        let want = expected.canon.get(key);
+        let got = found.canon.get(key);
+        if want == got {
+            for options in want.into_iter().flatten() {
+                println!("  ✓ {:<48} {}", key, options);
+            }
+            continue;
+        }
+        differences += 1;
+        println!("  ✗ {}", key);
+        println!(
+            "      wit-component:  {}",
+            want.map_or("(none)".to_string(), |o| o.join(" | "))
+        );
+        println!(
+            "      moon-component: {}",
+            got.map_or("(none)".to_string(), |o| o.join(" | "))
+        );
+    }
+
+    println!("\nComponent types:");
+    let want = Surface::decode(reference).context("failed to decode the reference component")?;
+    let got = Surface::decode(actual).context("failed to decode moon-component's component")?;
+    let diff = want.diff(&got);
+    if diff.is_empty() {
+        println!("  ✓ both decode to the same WIT");
+    } else {
+        differences += diff.len();
+        for line in diff {
+            println!("  {}", line);
+        }
+    }
+    Ok(differences)
+}
+
+fn run_suite(suite: &str, path: &Path) -> Result<()> {
+    let path = path.to_str().context("component path is not UTF-8")?;
+    match suite {
+        "types" => crate::types_test::run_types_test(path),
+        "import" => crate::import_test::run_import_test(path),
+        _ => crate::run_guest_test(path),
+    }
+}
+
+pub fn run_componentize_diff(args: &[String]) -> Result<()> {
+    let plan = parse_plan(args)?;
+    let out = plan.out.clone().unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("componentize-diff-{}", std::process::id()))
+    });
+    std::fs::create_dir_all(&out)?;
+
+    let reference_path = out.join("reference.component.wasm");
+    let reference = reference(&plan)?;
+    std::fs::write(&reference_path, &reference)?;
+    println!("wit-component:  {}", reference_path.display());
+
+    let actual_path = match &plan.actual {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = out.join("moon.component.wasm");
+            componentize(&plan, &path)?;
+            path
+        }
+    };
+    let actual = std::fs::read(&actual_path)
+        .with_context(|| format!("failed to read {}", actual_path.display()))?;
+    println!("moon-component: {}", actual_path.display());
+
+    let differences = compare(&reference, &actual)?;
+
+    let mut suite_failures = Vec::new();
+    for suite in &plan.suites {
+        for (label, path) in [
+            ("wit-component", &reference_path),
+            ("moon-component", &actual_path),
+        ] {
+            println!("\n=== {} suite: {} ===", suite, label);
+            if let Err(err) = run_suite(suite, path) {
+                println!("{} suite FAILED on {}'s component: {:#}", suite, label, err);
+                suite_failures.push(format!("{} ({})", suite, label));
+            }
+        }
+    }
+
+    println!();
+    if differences > 0 || !suite_failures.is_empty() {
+        let mut problems = Vec::new();
+        if differences > 0 {
+            problems.push(format!("{} structural difference(s)", differences));
+        }
+        if !suite_failures.is_empty() {
+            problems.push(format!("failing suites: {}", suite_failures.join(", ")));
+        }
+        bail!(
+            "componentize differs from wit-component: {}",
+            problems.join("; ")
+        );
+    }
+    println!("moon-component's component matches wit-component's");
+    Ok(())
+}
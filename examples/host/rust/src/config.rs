@@ -0,0 +1,116 @@
+// Local wasi:config (`--config`)
+//
+// Serves `wasi:config/store@0.2.0-draft` from a TOML file. Nested tables are
+// flattened into dotted keys (`[db] url = ".."` becomes `db.url`) and
+// non-string values are passed as their TOML text, since the interface only
+// deals in strings.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use wasmtime::component::Linker;
+
+use crate::host::{self, HostState};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        inline: r#"
+            package wasi:config@0.2.0-draft;
+
+            interface store {
+                variant error {
+                    upstream(string),
+                    io(string),
+                }
+
+                get: func(key: string) -> result<option<string>, error>;
+                get-all: func() -> result<list<tuple<string, string>>, error>;
+            }
+
+            world imports {
+                import store;
+            }
+        "#,
+    });
+}
+
+use bindings::wasi::config::store;
+
+static CONFIG: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+fn flatten(prefix: &str, table: toml::Table, out: &mut BTreeMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, out),
+            toml::Value::String(s) => {
+                out.insert(key, s);
+            }
+            other => {
+                out.insert(key, other.to_string());
+            }
+        }
+    }
+}
+
+/// Load `--config`, if given.
+pub fn enable() -> Result<()> {
+    let Some(file) = &host::options().config else {
+        return Ok(());
+    };
+    let text = std::fs::read_to_string(file).with_context(|| format!("failed to read {}", file))?;
+    let table: toml::Table = text
+        .parse()
+        .with_context(|| format!("failed to parse {}", file))?;
+    let mut values = BTreeMap::new();
+    flatten("", table, &mut values);
+    let _ = CONFIG.set(values);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    CONFIG.get().is_some()
+}
+
+pub fn add_to_linker(linker: &mut Linker<HostState>) -> Result<()> {
+    bindings::Imports::add_to_linker(linker, |state| state)
+}
+
+impl store::Host for HostState {
+    fn get(&mut self, key: String) -> Result<Option<String>, store::Error> {
+        Ok(CONFIG.get().and_then(|values| values.get(&key).cloned()))
+    }
+
+    fn get_all(&mut self) -> Result<Vec<(String, String)>, store::Error> {
+        Ok(CONFIG
+            .get()
+            .map(|values| values.clone().into_iter().collect())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use store::Host as _;
+    use wasmtime_wasi::WasiCtxBuilder;
+
+    #[test]
+    fn lookups_use_flattened_keys() {
+        let table: toml::Table = "name = \"app\"\n[db]\nport = 5432\n".parse().unwrap();
+        let mut values = BTreeMap::new();
+        flatten("", table, &mut values);
+        let _ = CONFIG.set(values);
+
+        let mut state = HostState::new(WasiCtxBuilder::new().build());
+        let mut get = |key: &str| state.get(key.to_string()).unwrap();
+        assert_eq!(get("name"), Some("app".to_string()));
+        assert_eq!(get("db.port"), Some("5432".to_string()));
+        assert_eq!(get("db"), None);
+        assert_eq!(get("missing"), None);
+    }
+}
@@ -0,0 +1,324 @@
+// Core module runner (`core`)
+//
+// Loads the core `impl.wasm` that `moon build` produces, before
+// `moon-component componentize` wraps it, and runs it under WASI preview1.
+// Lists imports and exports with their core signatures, and calls exports
+// with raw arguments, so a broken component can be narrowed down to the core
+// module or to componentization:
+//
+//   rust-host core impl.wasm
+//   rust-host core impl.wasm --call cabi_realloc 0 0 1 16 --read '$1' 16
+//   rust-host core impl.wasm --call 'local:greet/greeter#greet' '$1' 5 \
+//       --call 'cabi_post_local:greet/greeter#greet' '$2'
+//
+// Arguments are parsed by the export's parameter types; `$N` is the first
+// result of the Nth call. Component-level imports (`wasi:cli/...@0.2.x`,
+// `$root`, ...) have no core implementation and trap when called.
+//
+// With `--wit`, `--invoke` calls an export with typed values instead, going
+// through the canonical ABI in the harness (see cabi.rs):
+//
+//   rust-host core impl.wasm --wit wit --invoke 'local:greet/greeter#greet' '"MoonBit"'
+
+use anyhow::{bail, Context, Result};
+use wasmtime::{
+    Engine, ExternType, FuncType, Instance, Linker, Module, Store, Val, ValType,
+};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+
+use crate::cabi::{self, CoreAbi};
+use crate::host;
+use crate::trace;
+use crate::vals;
+
+const USAGE: &str = "usage: rust-host core <impl.wasm> [--wit PATH [--world NAME]] \
+[--call EXPORT [ARG]...]... [--invoke EXPORT [VAL]...]... [--read ADDR LEN]...";
+
+enum Step {
+    Call { name: String, args: Vec<String> },
+    Invoke { name: String, args: Vec<String> },
+    Read { addr: String, len: String },
+}
+
+#[derive(Default)]
+struct Plan {
+    wit: Option<String>,
+    world: Option<String>,
+    steps: Vec<Step>,
+}
+
+fn parse_plan(args: &[String]) -> Result<Plan> {
+    let mut plan = Plan::default();
+    let steps = &mut plan.steps;
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--wit" => plan.wit = Some(iter.next().context("--wit needs a path")?.clone()),
+            "--world" => plan.world = Some(iter.next().context("--world needs a name")?.clone()),
+            "--call" | "--invoke" => {
+                let name = iter
+                    .next()
+                    .with_context(|| format!("{} needs an export name", arg))?
+                    .clone();
+                let mut args = Vec::new();
+                while let Some(arg) = iter.next_if(|a| !a.starts_with("--")) {
+                    args.push(arg.clone());
+                }
+                steps.push(match arg.as_str() {
+                    "--call" => Step::Call { name, args },
+                    _ => Step::Invoke { name, args },
+                });
+            }
+            "--read" => {
+                let addr = iter.next().context("--read needs ADDR LEN")?.clone();
+                let len = iter.next().context("--read needs ADDR LEN")?.clone();
+                steps.push(Step::Read { addr, len });
+            }
+            other => bail!("unexpected argument: {}\n{}", other, USAGE),
+        }
+    }
+    if plan.wit.is_none() && plan.steps.iter().any(|s| matches!(s, Step::Invoke { .. })) {
+        bail!("--invoke needs --wit");
+    }
+    Ok(plan)
+}
+
+/// `(param i32 i32) (result i32)`, as in the text format.
+fn signature(ty: &FuncType) -> String {
+    let mut out = String::new();
+    if ty.params().len() > 0 {
+        let params: Vec<_> = ty.params().map(|p| p.to_string()).collect();
+        out.push_str(&format!("(param {})", params.join(" ")));
+    }
+    if ty.results().len() > 0 {
+        let results: Vec<_> = ty.results().map(|r| r.to_string()).collect();
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(&format!("(result {})", results.join(" ")));
+    }
+    out
+}
+
+fn describe(ty: &ExternType) -> String {
+    match ty {
+        ExternType::Func(ty) => format!("func {}", signature(ty)),
+        ExternType::Global(ty) => format!("global {:?} {}", ty.mutability(), ty.content()),
+        ExternType::Table(ty) => format!(
+            "table {}{} {}",
+            ty.minimum(),
+            ty.maximum().map_or(String::new(), |max| format!(" {}", max)),
+            ty.element()
+        ),
+        ExternType::Memory(ty) => format!(
+            "memory {}{}{}",
+            if ty.is_64() { "i64 " } else { "" },
+            ty.minimum(),
+            ty.maximum().map_or(String::new(), |max| format!(" {}", max))
+        ),
+    }
+}
+
+/// An integer argument: decimal, `0x` hex, negative, or `$N`.
+fn integer(arg: &str, results: &[Option<Val>]) -> Result<i64> {
+    if let Some(index) = arg.strip_prefix('$') {
+        let index: usize = index.parse().with_context(|| format!("bad reference {}", arg))?;
+        return match results.get(index.wrapping_sub(1)) {
+            Some(Some(Val::I32(v))) => Ok(i64::from(*v)),
+            Some(Some(Val::I64(v))) => Ok(*v),
+            Some(_) => bail!("call {} did not return an integer", index),
+            None => bail!("no call {} before {}", index, arg),
+        };
+    }
+    let parsed = match arg.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).map(|v| v as i64),
+        None => arg
+            .parse::<i64>()
+            .or_else(|_| arg.parse::<u64>().map(|v| v as i64)),
+    };
+    parsed.with_context(|| format!("invalid integer argument: {}", arg))
+}
+
+fn parse_arg(arg: &str, ty: &ValType, results: &[Option<Val>]) -> Result<Val> {
+    Ok(match ty {
+        ValType::I32 => Val::I32(integer(arg, results)? as i32),
+        ValType::I64 => Val::I64(integer(arg, results)?),
+        ValType::F32 => Val::F32(
+            arg.parse::<f32>()
+                .with_context(|| format!("invalid f32 argument: {}", arg))?
+                .to_bits(),
+        ),
+        ValType::F64 => Val::F64(
+            arg.parse::<f64>()
+                .with_context(|| format!("invalid f64 argument: {}", arg))?
+                .to_bits(),
+        ),
+        other => bail!("cannot pass a {} argument from the command line", other),
+    })
+}
+
+fn show(val: &Val) -> String {
+    match val {
+        Val::I32(v) => format!("{} (0x{:x})", v, v),
+        Val::I64(v) => format!("{} (0x{:x})", v, v),
+        Val::F32(bits) => f32::from_bits(*bits).to_string(),
+        Val::F64(bits) => f64::from_bits(*bits).to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn call(
+    instance: &Instance,
+    store: &mut Store<WasiP1Ctx>,
+    name: &str,
+    args: &[String],
+    results: &[Option<Val>],
+) -> Result<Vec<Val>> {
+    let func = instance
+        .get_func(&mut *store, name)
+        .with_context(|| format!("no exported function {}", name))?;
+    let ty = func.ty(&*store);
+    if args.len() != ty.params().len() {
+        bail!(
+            "{} takes {} argument(s): {}",
+            name,
+            ty.params().len(),
+            signature(&ty)
+        );
+    }
+    let params = args
+        .iter()
+        .zip(ty.params())
+        .map(|(arg, ty)| parse_arg(arg, &ty, results))
+        .collect::<Result<Vec<_>>>()?;
+    // `Func::call` overwrites every slot, so the placeholders need not match
+    // the result types (non-nullable references have no default value).
+    let mut out = vec![Val::I32(0); ty.results().len()];
+    func.call(&mut *store, &params, &mut out)
+        .with_context(|| format!("{} trapped", name))?;
+    let shown: Vec<_> = params.iter().map(show).collect();
+    let returned: Vec<_> = out.iter().map(show).collect();
+    println!(
+        "[{}] {}({}) -> {}",
+        results.len() + 1,
+        name,
+        shown.join(", "),
+        if returned.is_empty() {
+            "()".to_string()
+        } else {
+            returned.join(", ")
+        }
+    );
+    Ok(out)
+}
+
+/// Hex dump of `len` bytes of the exported memory at `addr`.
+fn read(
+    instance: &Instance,
+    store: &mut Store<WasiP1Ctx>,
+    addr: u64,
+    len: u64,
+) -> Result<()> {
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .context("module does not export `memory`")?;
+    let data = memory.data(&*store);
+    let bytes = usize::try_from(addr)
+        .ok()
+        .zip(usize::try_from(len).ok())
+        .and_then(|(addr, len)| data.get(addr..addr.checked_add(len)?))
+        .with_context(|| format!("{}+{} is outside memory ({} bytes)", addr, len, data.len()))?;
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<_> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        println!("  {:08x}  {:<47}  {}", addr as usize + i * 16, hex.join(" "), text);
+    }
+    Ok(())
+}
+
+pub fn run_core(args: &[String]) -> Result<()> {
+    let module_path = args.first().context(USAGE)?;
+    let plan = parse_plan(&args[1..])?;
+
+    let engine = Engine::new(&host::component_config())?;
+    println!("Loading core module: {}", module_path);
+    let module = Module::from_file(&engine, module_path)?;
+
+    println!("\nImports ({}):", module.imports().len());
+    for import in module.imports() {
+        println!(
+            "  {:<48} {}",
+            format!("{}::{}", import.module(), import.name()),
+            describe(&import.ty())
+        );
+    }
+    println!("\nExports ({}):", module.exports().len());
+    for export in module.exports() {
+        println!("  {:<48} {}", export.name(), describe(&export.ty()));
+    }
+    if plan.steps.is_empty() {
+        return Ok(());
+    }
+    let signatures = match &plan.wit {
+        Some(wit) => cabi::signatures(&engine, wit, plan.world.as_deref())?,
+        None => Vec::new(),
+    };
+
+    let mut linker = Linker::<WasiP1Ctx>::new(&engine);
+    preview1::add_to_linker_sync(&mut linker, |ctx| ctx)?;
+    linker.define_unknown_imports_as_traps(&module)?;
+    let mut builder = host::wasi_builder();
+    builder.inherit_stdio();
+    let mut store = Store::new(&engine, builder.build_p1());
+    trace::attach(&mut store);
+    let instance = linker.instantiate(&mut store, &module)?;
+
+    println!();
+    let mut results: Vec<Option<Val>> = Vec::new();
+    if let Some(init) = instance.get_func(&mut store, "_initialize") {
+        init.call(&mut store, &[], &mut [])
+            .context("_initialize trapped")?;
+        println!("Called _initialize");
+    }
+    let mut abi = None;
+    for step in &plan.steps {
+        match step {
+            Step::Call { name, args } => {
+                let out = call(&instance, &mut store, name, args, &results)?;
+                results.push(out.into_iter().next());
+            }
+            Step::Invoke { name, args } => {
+                let sig = signatures
+                    .iter()
+                    .find(|sig| &sig.name == name)
+                    .with_context(|| format!("{} is not a function the world exports", name))?;
+                let abi = match &abi {
+                    Some(abi) => abi,
+                    None => abi.insert(CoreAbi::new(instance, &mut store)?),
+                };
+                let vals = vals::parse_args(&sig.params, args)?;
+                let (lifted, core) = abi.call(&mut store, sig, &vals)?;
+                let format = |vals: &[_]| vals.iter().map(vals::format_val).collect::<Vec<_>>();
+                println!(
+                    "[{}] {}({}) -> {}",
+                    results.len() + 1,
+                    name,
+                    format(&vals).join(", "),
+                    format(&lifted).join(", ")
+                );
+                let core_results: Vec<_> = core.iter().map(show).collect();
+                println!("    core results: ({})", core_results.join(", "));
+                results.push(core.into_iter().next());
+            }
+            Step::Read { addr, len } => {
+                let addr = integer(addr, &results)? as u64;
+                let len = integer(len, &results)? as u64;
+                read(&instance, &mut store, addr, len)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+// Export coverage report
+//
+// With `--coverage FILE`, every export called through `host::call` is
+// recorded: call count, distinct argument tuples, and which variant/enum
+// cases, option and result arms and flags bits were seen in arguments and
+// results. Comparing that with the component's own export types shows the
+// exports and cases a suite never exercises.
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use wasmtime::component::types::{ComponentFunc, ComponentItem};
+use wasmtime::component::{Component, Type, Val};
+use wasmtime::Engine;
+
+use crate::host;
+use crate::vals;
+
+static COVERAGE: Mutex<Option<Coverage>> = Mutex::new(None);
+
+/// Choice points of a type, keyed by path (e.g. `result::ok.items[]`), with
+/// every label that can appear there.
+type Choices = BTreeMap<String, BTreeSet<String>>;
+
+#[derive(Default)]
+struct FuncCoverage {
+    params: Vec<String>,
+    calls: u64,
+    inputs: BTreeSet<String>,
+    expected: Choices,
+    observed: Choices,
+}
+
+#[derive(Default)]
+struct ComponentCoverage {
+    funcs: BTreeMap<String, FuncCoverage>,
+}
+
+#[derive(Default)]
+struct Coverage {
+    components: BTreeMap<String, ComponentCoverage>,
+    current: String,
+}
+
+/// Start collecting if `--coverage` was given.
+pub fn enable() {
+    if host::options().coverage.is_some() {
+        *COVERAGE.lock().unwrap() = Some(Coverage::default());
+    }
+}
+
+/// Register a loaded component's exports; later calls are attributed to it.
+pub fn register(engine: &Engine, name: &str, component: &Component) {
+    let mut guard = COVERAGE.lock().unwrap();
+    let Some(coverage) = guard.as_mut() else {
+        return;
+    };
+    coverage.current = name.to_string();
+    let entry = coverage.components.entry(name.to_string()).or_default();
+    for (export, item) in component.component_type().exports(engine) {
+        match item {
+            ComponentItem::ComponentFunc(func) => add_func(entry, export.to_string(), &func),
+            ComponentItem::ComponentInstance(instance) => {
+                for (name, item) in instance.exports(engine) {
+                    if let ComponentItem::ComponentFunc(func) = item {
+                        add_func(entry, format!("{}#{}", export, name), &func);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn add_func(entry: &mut ComponentCoverage, name: String, func: &ComponentFunc) {
+    if entry.funcs.contains_key(&name) {
+        return;
+    }
+    let cov = entry.funcs.entry(name).or_default();
+    for (param, ty) in func.params() {
+        cov.params.push(param.to_string());
+        choices(&ty, &format!("param {}", param), &mut cov.expected);
+    }
+    for (i, ty) in func.results().enumerate() {
+        choices(&ty, &result_path(i), &mut cov.expected);
+    }
+}
+
+fn result_path(i: usize) -> String {
+    if i == 0 {
+        "result".to_string()
+    } else {
+        format!("result {}", i)
+    }
+}
+
+fn add<'a>(out: &mut Choices, path: &str, labels: impl IntoIterator<Item = &'a str>) {
+    out.entry(path.to_string())
+        .or_default()
+        .extend(labels.into_iter().map(String::from));
+}
+
+fn choices(ty: &Type, path: &str, out: &mut Choices) {
+    match ty {
+        Type::Variant(v) => {
+            add(out, path, v.cases().map(|c| c.name));
+            for case in v.cases() {
+                if let Some(ty) = &case.ty {
+                    choices(ty, &format!("{}::{}", path, case.name), out);
+                }
+            }
+        }
+        Type::Enum(e) => add(out, path, e.names()),
+        Type::Flags(f) => add(out, path, f.names()),
+        Type::Option(o) => {
+            add(out, path, ["none", "some"]);
+            choices(&o.ty(), &format!("{}::some", path), out);
+        }
+        Type::Result(r) => {
+            add(out, path, ["ok", "err"]);
+            if let Some(ty) = r.ok() {
+                choices(&ty, &format!("{}::ok", path), out);
+            }
+            if let Some(ty) = r.err() {
+                choices(&ty, &format!("{}::err", path), out);
+            }
+        }
+        Type::Record(r) => {
+            for field in r.fields() {
+                choices(&field.ty, &format!("{}.{}", path, field.name), out);
+            }
+        }
+        Type::Tuple(t) => {
+            for (i, ty) in t.types().enumerate() {
+                choices(&ty, &format!("{}.{}", path, i), out);
+            }
+        }
+        Type::List(l) => choices(&l.ty(), &format!("{}[]", path), out),
+        _ => {}
+    }
+}
+
+fn observe(val: &Val, path: &str, out: &mut Choices) {
+    match val {
+        Val::Variant(case, payload) => {
+            add(out, path, [case.as_str()]);
+            if let Some(v) = payload {
+                observe(v, &format!("{}::{}", path, case), out);
+            }
+        }
+        Val::Enum(case) => add(out, path, [case.as_str()]),
+        Val::Flags(names) => add(out, path, names.iter().map(String::as_str)),
+        Val::Option(None) => add(out, path, ["none"]),
+        Val::Option(Some(v)) => {
+            add(out, path, ["some"]);
+            observe(v, &format!("{}::some", path), out);
+        }
+        Val::Result(r) => {
+            let (arm, payload) = match r {
+                Ok(v) => ("ok", v),
+                Err(v) => ("err", v),
+            };
+            add(out, path, [arm]);
+            if let Some(v) = payload {
+                observe(v, &format!("{}::{}", path, arm), out);
+            }
+        }
+        Val::Record(fields) => {
+            for (name, v) in fields {
+                observe(v, &format!("{}.{}", path, name), out);
+            }
+        }
+        Val::Tuple(items) => {
+            for (i, v) in items.iter().enumerate() {
+                observe(v, &format!("{}.{}", path, i), out);
+            }
+        }
+        Val::List(items) => {
+            for v in items {
+                observe(v, &format!("{}[]", path), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Record one export call; `params` and `results` as passed to `Func::call`.
+pub fn record(name: &str, params: &[Val], results: &[Val]) {
+    let mut guard = COVERAGE.lock().unwrap();
+    let Some(coverage) = guard.as_mut() else {
+        return;
+    };
+    let current = coverage.current.clone();
+    let cov = coverage
+        .components
+        .entry(current)
+        .or_default()
+        .funcs
+        .entry(name.to_string())
+        .or_default();
+    cov.calls += 1;
+    cov.inputs.insert(
+        params
+            .iter()
+            .map(vals::format_val)
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    for (i, val) in params.iter().enumerate() {
+        let path = match cov.params.get(i) {
+            Some(name) => format!("param {}", name),
+            None => format!("param #{}", i),
+        };
+        observe(val, &path, &mut cov.observed);
+    }
+    for (i, val) in results.iter().enumerate() {
+        observe(val, &result_path(i), &mut cov.observed);
+    }
+}
+
+/// Labels the type allows at each choice point that no call produced.
+fn missing_cases(func: &FuncCoverage) -> Choices {
+    let empty = BTreeSet::new();
+    func.expected
+        .iter()
+        .filter_map(|(path, labels)| {
+            let seen = func.observed.get(path).unwrap_or(&empty);
+            let missing: BTreeSet<String> = labels.difference(seen).cloned().collect();
+            (!missing.is_empty()).then(|| (path.clone(), missing))
+        })
+        .collect()
+}
+
+/// Print the summary and write the `--coverage` report.
+pub fn finish() -> Result<()> {
+    let (Some(path), Some(coverage)) = (&host::options().coverage, COVERAGE.lock().unwrap().take())
+    else {
+        return Ok(());
+    };
+
+    let mut report = Vec::new();
+    for (component, comp) in &coverage.components {
+        println!("\nCoverage: {}", component);
+        let called = comp.funcs.values().filter(|f| f.calls > 0).count();
+        println!("  exports called: {}/{}", called, comp.funcs.len());
+        let mut funcs = Vec::new();
+        for (name, func) in &comp.funcs {
+            let missing = missing_cases(func);
+            if func.calls == 0 {
+                println!("  NOT CALLED  {}", name);
+            } else {
+                println!(
+                    "  {:>5} call(s) {:>4} input(s)  {}",
+                    func.calls,
+                    func.inputs.len(),
+                    name
+                );
+            }
+            for (path, labels) in &missing {
+                println!(
+                    "              missing at {}: {}",
+                    path,
+                    labels.iter().cloned().collect::<Vec<_>>().join(", ")
+                );
+            }
+            funcs.push(json!({
+                "name": name,
+                "calls": func.calls,
+                "distinct_inputs": func.inputs.len(),
+                "observed": func.observed,
+                "missing": missing,
+            }));
+        }
+        report.push(json!({ "component": component, "exports": funcs }));
+    }
+
+    std::fs::write(path, serde_json::to_string_pretty(&json!({ "components": report }))?)
+        .with_context(|| format!("failed to write {}", path))?;
+    println!("\nCoverage report -> {}", path);
+    Ok(())
+}
@@ -0,0 +1,73 @@
+// Deterministic WASI profile (`--deterministic`)
+//
+// Replaces the sources of run-to-run variation in a WASI context: both
+// clocks read from one virtual counter that starts at a fixed instant and
+// only moves by `--clock-step` per read (frozen by default), and
+// `wasi:random` (secure and insecure) is derived from `--seed`. The guest
+// environment is never inherited from the host, so it only holds what a mode
+// sets explicitly.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock, WasiCtxBuilder};
+
+/// Wall-clock reading at virtual time zero: 2024-01-01T00:00:00Z.
+const WALL_CLOCK_START: Duration = Duration::from_secs(1_704_067_200);
+
+#[derive(Clone, Copy, Default)]
+pub struct DeterministicOptions {
+    /// Seed for `wasi:random`.
+    pub seed: u64,
+    /// Nanoseconds the virtual clock advances on every read.
+    pub clock_step: u64,
+}
+
+/// Virtual time in nanoseconds, shared by the wall and monotonic clocks.
+#[derive(Clone)]
+struct VirtualClock {
+    nanos: Arc<AtomicU64>,
+    step: u64,
+}
+
+impl VirtualClock {
+    fn read(&self) -> u64 {
+        self.nanos.fetch_add(self.step, Ordering::Relaxed)
+    }
+}
+
+impl HostMonotonicClock for VirtualClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.read()
+    }
+}
+
+impl HostWallClock for VirtualClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        WALL_CLOCK_START + Duration::from_nanos(self.read())
+    }
+}
+
+/// Install the virtual clocks and seeded randomness on a builder.
+pub fn apply(builder: &mut WasiCtxBuilder, opts: &DeterministicOptions) {
+    let clock = VirtualClock {
+        nanos: Arc::new(AtomicU64::new(0)),
+        step: opts.clock_step,
+    };
+    builder
+        .wall_clock(clock.clone())
+        .monotonic_clock(clock)
+        .secure_random(StdRng::seed_from_u64(opts.seed))
+        .insecure_random(StdRng::seed_from_u64(!opts.seed))
+        .insecure_random_seed(opts.seed as u128);
+}
@@ -0,0 +1,75 @@
+// Echo test for wasi:sockets guests
+//
+// Starts a TCP echo server on an ephemeral loopback port, allows exactly that
+// address in the socket policy, and runs a wasi:cli/command component as
+// `<component> <port> <message>`. The component is expected to connect, send
+// the message, read it back and exit 0; the server reports what it echoed.
+
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::run;
+use crate::sockets;
+
+const DEFAULT_MESSAGE: &str = "hello from moon-component";
+
+pub fn run_echo(args: &[String]) -> Result<()> {
+    let component_path = args
+        .first()
+        .context("usage: rust-host echo <component> [--message TEXT]")?;
+    let message = match args.get(1).map(|s| s.as_str()) {
+        Some("--message") => args.get(2).context("--message needs a value")?.as_str(),
+        Some(other) => bail!("unexpected argument: {}", other),
+        None => DEFAULT_MESSAGE,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let echoed = Arc::new(AtomicUsize::new(0));
+    let counter = echoed.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let counter = counter.clone();
+            std::thread::spawn(move || {
+                let mut buf = [0u8; 4096];
+                while let Ok(n) = stream.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                    counter.fetch_add(n, Ordering::Relaxed);
+                    if stream.write_all(&buf[..n]).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    println!("Echo server listening on {}", addr);
+    sockets::allow(addr);
+
+    let run_args = [
+        component_path.clone(),
+        "--expect-exit".to_string(),
+        "0".to_string(),
+        "--".to_string(),
+        addr.port().to_string(),
+        message.to_string(),
+    ];
+    run::run_command(&run_args)?;
+
+    let echoed = echoed.load(Ordering::Relaxed);
+    println!("Echo server echoed {} bytes", echoed);
+    if echoed != message.len() {
+        bail!(
+            "expected {} bytes to be echoed, got {}",
+            message.len(),
+            echoed
+        );
+    }
+    println!("\nEcho test PASSED!");
+    Ok(())
+}
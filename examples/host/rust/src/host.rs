@@ -0,0 +1,323 @@
+// Shared host setup for rust-host modes
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::sync::OnceLock;
+use wasmtime::component::{Func, Instance, Linker, Val};
+use wasmtime::{AsContextMut, Config, Engine, Store};
+use wasmtime_wasi::{ResourceTable, WasiCtx, WasiCtxBuilder, WasiView};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::{
+    default_send_request, HostFutureIncomingResponse, OutgoingRequestConfig,
+};
+use wasmtime_wasi_http::{HttpResult, WasiHttpCtx, WasiHttpView};
+
+use crate::config;
+use crate::coverage;
+use crate::deterministic::{self, DeterministicOptions};
+use crate::http_fixtures;
+use crate::keyvalue;
+use crate::logging;
+use crate::memfs::{self, Failure, MountSpec};
+use crate::sockets;
+use crate::trace;
+
+/// Flags accepted by every mode, e.g. `rust-host types --trace trace.json`.
+#[derive(Default)]
+pub struct HostOptions {
+    /// Write a Chrome trace of host/guest call transitions to this file.
+    pub trace: Option<String>,
+    /// Write an export coverage report to this file.
+    pub coverage: Option<String>,
+    /// Virtual clocks and seeded randomness for every WASI context.
+    pub deterministic: Option<DeterministicOptions>,
+    /// In-memory filesystem mounts replacing `wasi:filesystem`.
+    pub memfs: Vec<MountSpec>,
+    /// Injected memfs errors.
+    pub memfs_fail: Vec<Failure>,
+    /// Write the memfs tree to this directory at exit.
+    pub memfs_dump: Option<String>,
+    /// Largest memfs file a guest may write, in bytes.
+    pub memfs_max_size: Option<u64>,
+    /// Serve `wasi:keyvalue` from memory.
+    pub keyvalue: bool,
+    /// Load and persist the keyvalue buckets in this JSON file.
+    pub keyvalue_file: Option<String>,
+    /// Serve `wasi:config` from this TOML file.
+    pub config: Option<String>,
+    /// Serve and capture `wasi:logging`.
+    pub logging: bool,
+    /// Write captured log records to this file.
+    pub logging_file: Option<String>,
+    /// Messages that must have been logged by exit.
+    pub expect_log: Vec<String>,
+    /// Answer outgoing `wasi:http` requests from this fixture directory.
+    pub http_fixtures: Option<String>,
+    /// Proxy outgoing requests to this loopback origin and record them.
+    pub http_record: Option<String>,
+    /// Fail requests that have no fixture.
+    pub http_strict: bool,
+    /// Socket policy file allowing loopback addresses.
+    pub sockets: Option<String>,
+    /// Import allow-list; disallowed imports trap.
+    pub policy: Option<String>,
+}
+
+static OPTIONS: OnceLock<HostOptions> = OnceLock::new();
+
+/// Strip the global flags out of `args` and record them; the remaining
+/// arguments are returned for the mode itself.
+pub fn init_options(args: Vec<String>) -> Result<Vec<String>> {
+    let mut opts = HostOptions::default();
+    let mut rest = Vec::new();
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // Everything after `--` belongs to the mode (e.g. guest argv).
+            "--" => {
+                rest.push(arg);
+                rest.extend(iter.by_ref());
+            }
+            "--trace" => opts.trace = Some(iter.next().context("--trace needs a file")?),
+            "--coverage" => {
+                opts.coverage = Some(iter.next().context("--coverage needs a file")?)
+            }
+            "--deterministic" => {
+                opts.deterministic.get_or_insert_with(Default::default);
+            }
+            // --seed and --clock-step imply --deterministic.
+            "--seed" => {
+                opts.deterministic.get_or_insert_with(Default::default).seed =
+                    iter.next().context("--seed needs a value")?.parse()?
+            }
+            "--clock-step" => {
+                opts.deterministic.get_or_insert_with(Default::default).clock_step =
+                    iter.next().context("--clock-step needs nanoseconds")?.parse()?
+            }
+            "--memfs" | "--memfs-ro" => {
+                let spec = iter.next().context("--memfs needs GUEST[=SOURCE]")?;
+                opts.memfs.push(MountSpec::parse(&spec, arg == "--memfs-ro")?);
+            }
+            "--memfs-fail" => opts
+                .memfs_fail
+                .push(Failure::parse(&iter.next().context("--memfs-fail needs OP:PATH[=CODE]")?)?),
+            "--memfs-dump" => {
+                opts.memfs_dump = Some(iter.next().context("--memfs-dump needs a directory")?)
+            }
+            "--memfs-max-size" => {
+                opts.memfs_max_size =
+                    Some(iter.next().context("--memfs-max-size needs a byte count")?.parse()?)
+            }
+            "--keyvalue" => opts.keyvalue = true,
+            // --keyvalue-file implies --keyvalue.
+            "--keyvalue-file" => {
+                opts.keyvalue = true;
+                opts.keyvalue_file = Some(iter.next().context("--keyvalue-file needs a file")?)
+            }
+            "--config" => opts.config = Some(iter.next().context("--config needs a TOML file")?),
+            "--logging" => opts.logging = true,
+            // --logging-file and --expect-log imply --logging.
+            "--logging-file" => {
+                opts.logging = true;
+                opts.logging_file = Some(iter.next().context("--logging-file needs a file")?)
+            }
+            "--expect-log" => {
+                opts.logging = true;
+                opts.expect_log
+                    .push(iter.next().context("--expect-log needs [LEVEL:]TEXT")?)
+            }
+            "--http-fixtures" => {
+                opts.http_fixtures = Some(iter.next().context("--http-fixtures needs a directory")?)
+            }
+            "--http-record" => {
+                opts.http_record = Some(iter.next().context("--http-record needs an origin")?)
+            }
+            "--http-strict" => opts.http_strict = true,
+            "--sockets" => opts.sockets = Some(iter.next().context("--sockets needs a policy file")?),
+            "--policy" => opts.policy = Some(iter.next().context("--policy needs a file")?),
+            _ => rest.push(arg),
+        }
+    }
+    if opts.http_fixtures.is_none() && (opts.http_record.is_some() || opts.http_strict) {
+        return Err(anyhow!("--http-record and --http-strict need --http-fixtures DIR"));
+    }
+    OPTIONS
+        .set(opts)
+        .map_err(|_| anyhow!("host options already initialized"))?;
+    Ok(rest)
+}
+
+pub fn options() -> &'static HostOptions {
+    OPTIONS.get_or_init(HostOptions::default)
+}
+
+/// Reject global flags whose output is only written when the mode returns,
+/// for modes such as `serve` and `wagi` that run until interrupted.
+pub fn reject_exit_time_flags(mode: &str) -> Result<()> {
+    let opts = options();
+    let flags = [
+        ("--trace", opts.trace.is_some()),
+        ("--coverage", opts.coverage.is_some()),
+        ("--memfs-dump", opts.memfs_dump.is_some()),
+        ("--keyvalue-file", opts.keyvalue_file.is_some()),
+        ("--logging-file", opts.logging_file.is_some()),
+        ("--expect-log", !opts.expect_log.is_empty()),
+    ];
+    let given: Vec<_> = flags.iter().filter(|(_, set)| *set).map(|(flag, _)| *flag).collect();
+    if !given.is_empty() {
+        bail!("{} runs until interrupted, so {} would never be written", mode, given.join(", "));
+    }
+    Ok(())
+}
+
+pub struct HostState {
+    pub wasi: WasiCtx,
+    pub http: WasiHttpCtx,
+    pub table: ResourceTable,
+}
+
+impl WasiView for HostState {
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+}
+
+impl WasiHttpView for HostState {
+    fn ctx(&mut self) -> &mut WasiHttpCtx {
+        &mut self.http
+    }
+
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        if http_fixtures::enabled() {
+            return Ok(http_fixtures::send_request(request, config));
+        }
+        Ok(default_send_request(request, config))
+    }
+}
+
+impl HostState {
+    pub fn new(wasi: WasiCtx) -> Self {
+        HostState {
+            wasi,
+            http: WasiHttpCtx::new(),
+            table: ResourceTable::new(),
+        }
+    }
+}
+
+/// Config with the proposals MoonBit components may use (both `wasm` and
+/// `wasm-gc` targets).
+pub fn component_config() -> Config {
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    config.wasm_gc(true);
+    config.wasm_function_references(true);
+    config
+}
+
+/// Linker with the full WASI p2 surface; `wasi:filesystem` is served from
+/// memory when `--memfs` is given, and the local `wasi:keyvalue`,
+/// `wasi:config`, `wasi:logging` and fixture-backed `wasi:http` are added
+/// when selected.
+pub fn wasi_linker(engine: &Engine) -> Result<Linker<HostState>> {
+    let mut linker = Linker::<HostState>::new(engine);
+    wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+    add_local_interfaces(&mut linker)?;
+    if http_fixtures::enabled() {
+        wasmtime_wasi_http::add_only_http_to_linker_sync(&mut linker)?;
+    }
+    Ok(linker)
+}
+
+/// `wasi_linker` for engines with async support (`serve`): WASI and
+/// `wasi:http` use their async bindings, and the local interfaces are the
+/// same synchronous host functions, which async stores call as well.
+pub fn wasi_linker_async(engine: &Engine) -> Result<Linker<HostState>> {
+    let mut linker = Linker::<HostState>::new(engine);
+    wasmtime_wasi::add_to_linker_async(&mut linker)?;
+    wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)?;
+    add_local_interfaces(&mut linker)?;
+    Ok(linker)
+}
+
+/// The interfaces served by this host rather than wasmtime-wasi, as selected
+/// by the global flags.
+fn add_local_interfaces(linker: &mut Linker<HostState>) -> Result<()> {
+    if memfs::enabled() {
+        memfs::add_to_linker(linker)?;
+    }
+    if keyvalue::enabled() {
+        keyvalue::add_to_linker(linker)?;
+    }
+    if config::enabled() {
+        config::add_to_linker(linker)?;
+    }
+    if logging::enabled() {
+        logging::add_to_linker(linker)?;
+    }
+    Ok(())
+}
+
+/// Starting point for every mode's WASI context.
+pub fn wasi_builder() -> WasiCtxBuilder {
+    let mut builder = WasiCtxBuilder::new();
+    if let Some(opts) = &options().deterministic {
+        deterministic::apply(&mut builder, opts);
+    }
+    sockets::apply(&mut builder);
+    builder
+}
+
+pub fn wasi_store(engine: &Engine, wasi: WasiCtx) -> Store<HostState> {
+    let mut store = Store::new(engine, HostState::new(wasi));
+    trace::attach(&mut store);
+    store
+}
+
+/// Look up an exported function by `interface#func` (e.g.
+/// `local:types-test/primitives#echo-s32`) or by a bare world-level name.
+pub fn find_func(instance: &Instance, mut store: impl AsContextMut, name: &str) -> Result<Func> {
+    let (iface, func) = match name.split_once('#') {
+        Some((iface, func)) => (Some(iface), func),
+        None => (None, name),
+    };
+    let parent = match iface {
+        Some(iface) => Some(
+            instance
+                .get_export(&mut store, None, iface)
+                .ok_or_else(|| anyhow!("interface not found: {}", iface))?,
+        ),
+        None => None,
+    };
+    let func_export = instance
+        .get_export(&mut store, parent.as_ref(), func)
+        .ok_or_else(|| anyhow!("function not found: {}", name))?;
+    instance
+        .get_func(&mut store, func_export)
+        .ok_or_else(|| anyhow!("export is not a function: {}", name))
+}
+
+/// Call an export by name, then its post-return. Every mode calls exports
+/// through here so `--trace` and `--coverage` see them.
+pub fn call(
+    func: &Func,
+    mut store: impl AsContextMut,
+    name: &str,
+    params: &[Val],
+    results: &mut [Val],
+) -> Result<()> {
+    trace::call(func, &mut store, name, params, results)?;
+    coverage::record(name, params, results);
+    Ok(())
+}
@@ -0,0 +1,497 @@
+// Outbound wasi:http from fixtures (`--http-fixtures`)
+//
+// Answers `wasi:http/outgoing-handler` requests from a directory of recorded
+// exchanges instead of the network. A fixture is keyed by method, URL and the
+// SHA-256 of the request body, and stored as one JSON file:
+//
+//   { "request":  { "method": "GET", "url": "http://api.test/users?id=1",
+//                   "body_sha256": "e3b0..." },
+//     "response": { "status": 200, "headers": [["content-type", "text/plain"]],
+//                   "body": "..." } }
+//
+// With `--http-record ORIGIN` every request is sent to ORIGIN (a loopback
+// server, keeping path and query) and the exchange is written to the
+// directory. An unmatched request gets a 404; with `--http-strict` it fails
+// with `HTTP-request-denied` and the host exits with an error.
+
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::{
+    default_send_request_handler, HostFutureIncomingResponse, IncomingResponse,
+    OutgoingRequestConfig,
+};
+
+use crate::host;
+
+static FIXTURES: Mutex<Option<Fixtures>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    method: String,
+    url: String,
+    body_sha256: String,
+}
+
+impl Key {
+    fn new(method: &str, url: &str, body: &[u8]) -> Self {
+        Key {
+            method: method.to_string(),
+            url: url.to_string(),
+            body_sha256: hex(&Sha256::digest(body)),
+        }
+    }
+
+    fn file_name(&self) -> String {
+        let id = hex(&Sha256::digest(
+            format!("{} {} {}", self.method, self.url, self.body_sha256).as_bytes(),
+        ));
+        format!("{}-{}.json", self.method, &id[..16])
+    }
+}
+
+#[derive(Clone)]
+struct Fixture {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+struct Fixtures {
+    dir: PathBuf,
+    entries: HashMap<Key, Fixture>,
+    /// `--http-record` origin; requests are rewritten to it.
+    record: Option<hyper::Uri>,
+    strict: bool,
+    /// Requests that had no fixture, as `METHOD URL`.
+    unmatched: Vec<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn body_to_json(body: &[u8]) -> Value {
+    match std::str::from_utf8(body) {
+        Ok(s) => Value::String(s.to_string()),
+        Err(_) => body.iter().copied().map(Value::from).collect(),
+    }
+}
+
+fn body_from_json(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::String(s) => Some(s.clone().into_bytes()),
+        Value::Array(items) => items
+            .iter()
+            .map(|v| v.as_u64().and_then(|b| u8::try_from(b).ok()))
+            .collect(),
+        _ => None,
+    }
+}
+
+fn load(path: &Path) -> Result<(Key, Fixture)> {
+    let value: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let field = |section: &str, name: &str| -> Result<&Value> {
+        value
+            .get(section)
+            .and_then(|s| s.get(name))
+            .with_context(|| format!("missing {}.{}", section, name))
+    };
+    let string = |section: &str, name: &str| -> Result<String> {
+        field(section, name)?
+            .as_str()
+            .map(str::to_string)
+            .with_context(|| format!("{}.{} is not a string", section, name))
+    };
+    let key = Key {
+        method: string("request", "method")?,
+        url: string("request", "url")?,
+        body_sha256: string("request", "body_sha256")?,
+    };
+    let headers = field("response", "headers")?
+        .as_array()
+        .context("response.headers is not an array")?
+        .iter()
+        .map(|pair| match pair.as_array().map(|p| p.as_slice()) {
+            Some([Value::String(name), Value::String(value)]) => Ok((name.clone(), value.clone())),
+            _ => Err(anyhow!("response.headers entries must be [name, value]")),
+        })
+        .collect::<Result<_>>()?;
+    let fixture = Fixture {
+        status: field("response", "status")?
+            .as_u64()
+            .and_then(|s| u16::try_from(s).ok())
+            .context("response.status is not a status code")?,
+        headers,
+        body: body_from_json(field("response", "body")?)
+            .context("response.body must be a string or byte array")?,
+    };
+    Ok((key, fixture))
+}
+
+fn save(dir: &Path, key: &Key, fixture: &Fixture) -> Result<()> {
+    let value = json!({
+        "request": {
+            "method": key.method,
+            "url": key.url,
+            "body_sha256": key.body_sha256,
+        },
+        "response": {
+            "status": fixture.status,
+            "headers": fixture.headers,
+            "body": body_to_json(&fixture.body),
+        },
+    });
+    std::fs::write(
+        dir.join(key.file_name()),
+        serde_json::to_string_pretty(&value)?,
+    )?;
+    Ok(())
+}
+
+fn parse_origin(origin: &str) -> Result<hyper::Uri> {
+    let uri: hyper::Uri = origin
+        .parse()
+        .with_context(|| format!("invalid --http-record origin: {}", origin))?;
+    if uri.scheme_str() != Some("http") {
+        bail!("--http-record origin must be http://: {}", origin);
+    }
+    let host = uri.host().unwrap_or_default();
+    let loopback = host == "localhost"
+        || host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback());
+    if !loopback {
+        bail!("--http-record only proxies to loopback servers: {}", origin);
+    }
+    Ok(uri)
+}
+
+/// Load the fixture directory given with `--http-fixtures`, if any.
+pub fn enable() -> Result<()> {
+    let opts = host::options();
+    let Some(dir) = &opts.http_fixtures else {
+        return Ok(());
+    };
+    let dir = PathBuf::from(dir);
+    let record = opts.http_record.as_deref().map(parse_origin).transpose()?;
+    let mut entries = HashMap::new();
+    if record.is_some() {
+        std::fs::create_dir_all(&dir)?;
+    }
+    for entry in
+        std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let (key, fixture) =
+                load(&path).with_context(|| format!("invalid fixture {}", path.display()))?;
+            entries.insert(key, fixture);
+        }
+    }
+    *FIXTURES.lock().unwrap() = Some(Fixtures {
+        dir,
+        entries,
+        record,
+        strict: opts.http_strict,
+        unmatched: Vec::new(),
+    });
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    FIXTURES.lock().unwrap().is_some()
+}
+
+/// Under `--http-strict`, fail if any request went unanswered.
+pub fn finish() -> Result<()> {
+    let Some(fixtures) = FIXTURES.lock().unwrap().take() else {
+        return Ok(());
+    };
+    if fixtures.strict && !fixtures.unmatched.is_empty() {
+        bail!("requests without a fixture: {:?}", fixtures.unmatched);
+    }
+    Ok(())
+}
+
+/// `WasiHttpView::send_request` while fixtures are enabled.
+pub fn send_request(
+    request: hyper::Request<HyperOutgoingBody>,
+    config: OutgoingRequestConfig,
+) -> HostFutureIncomingResponse {
+    let handle = wasmtime_wasi::runtime::spawn(async move { Ok(exchange(request, config).await) });
+    HostFutureIncomingResponse::pending(handle)
+}
+
+fn with_fixtures<R>(f: impl FnOnce(&mut Fixtures) -> R) -> R {
+    f(FIXTURES
+        .lock()
+        .unwrap()
+        .as_mut()
+        .expect("http fixtures not enabled"))
+}
+
+fn internal(e: impl std::fmt::Display) -> ErrorCode {
+    ErrorCode::InternalError(Some(e.to_string()))
+}
+
+async fn exchange(
+    request: hyper::Request<HyperOutgoingBody>,
+    config: OutgoingRequestConfig,
+) -> Result<IncomingResponse, ErrorCode> {
+    let between_bytes_timeout = config.between_bytes_timeout;
+    let (parts, body) = request.into_parts();
+    let body = body.collect().await?.to_bytes();
+    let key = Key::new(parts.method.as_str(), &parts.uri.to_string(), &body);
+
+    let (fixture, source) = match with_fixtures(|f| f.record.clone()) {
+        Some(origin) => (
+            record(&origin, parts, body, &key, config).await?,
+            "recorded",
+        ),
+        None => match with_fixtures(|f| f.entries.get(&key).cloned()) {
+            Some(fixture) => (fixture, "fixture"),
+            None => {
+                let strict = with_fixtures(|f| {
+                    f.unmatched.push(format!("{} {}", key.method, key.url));
+                    f.strict
+                });
+                eprintln!(
+                    "[http] no fixture for {} {} (body sha256 {})",
+                    key.method, key.url, key.body_sha256
+                );
+                if strict {
+                    return Err(ErrorCode::HttpRequestDenied);
+                }
+                let body = format!("no fixture for {} {}\n", key.method, key.url);
+                (
+                    Fixture {
+                        status: 404,
+                        headers: vec![("content-type".to_string(), "text/plain".to_string())],
+                        body: body.into_bytes(),
+                    },
+                    "unmatched",
+                )
+            }
+        },
+    };
+    println!(
+        "[http] {} {} -> {} ({})",
+        key.method, key.url, fixture.status, source
+    );
+
+    let mut builder = hyper::Response::builder().status(fixture.status);
+    for (name, value) in &fixture.headers {
+        builder = builder.header(name, value);
+    }
+    let resp = builder
+        .body(
+            Full::new(Bytes::from(fixture.body))
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+        .map_err(internal)?;
+    Ok(IncomingResponse {
+        resp,
+        worker: None,
+        between_bytes_timeout,
+    })
+}
+
+/// Send the request to the `--http-record` origin and store the exchange.
+async fn record(
+    origin: &hyper::Uri,
+    mut parts: hyper::http::request::Parts,
+    body: Bytes,
+    key: &Key,
+    mut config: OutgoingRequestConfig,
+) -> Result<Fixture, ErrorCode> {
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    parts.uri = format!("http://{}{}", origin.authority().unwrap(), path)
+        .parse()
+        .map_err(internal)?;
+    config.use_tls = false;
+    let request = hyper::Request::from_parts(
+        parts,
+        Full::new(body).map_err(|never| match never {}).boxed(),
+    );
+    let resp = default_send_request_handler(request, config).await?.resp;
+    let status = resp.status().as_u16();
+    let headers = resp
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    let body = resp.into_body().collect().await?.to_bytes().to_vec();
+    let fixture = Fixture {
+        status,
+        headers,
+        body,
+    };
+    with_fixtures(|f| {
+        save(&f.dir, key, &fixture).map_err(internal)?;
+        f.entries.insert(key.clone(), fixture.clone());
+        Ok(fixture)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use wasmtime_wasi_http::io::TokioIo;
+
+    /// The fixtures are process-wide, so tests that install them take turns.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    const URL: &str = "http://api.test/users?id=1";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("http-fixtures-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn install(dir: PathBuf, record: Option<hyper::Uri>, strict: bool) {
+        *FIXTURES.lock().unwrap() = Some(Fixtures {
+            dir,
+            entries: HashMap::new(),
+            record,
+            strict,
+            unmatched: Vec::new(),
+        });
+    }
+
+    fn request(method: &str, url: &str, body: &str) -> hyper::Request<HyperOutgoingBody> {
+        hyper::Request::builder()
+            .method(method)
+            .uri(url)
+            .body(
+                Full::new(Bytes::from(body.to_string()))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+
+    fn config() -> OutgoingRequestConfig {
+        OutgoingRequestConfig {
+            use_tls: false,
+            connect_timeout: Duration::from_secs(5),
+            first_byte_timeout: Duration::from_secs(5),
+            between_bytes_timeout: Duration::from_secs(5),
+        }
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn keys_hash_method_url_and_body() {
+        let key = Key::new("GET", URL, b"");
+        assert_eq!(
+            key.body_sha256,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let name = key.file_name();
+        assert!(name.starts_with("GET-") && name.ends_with(".json"));
+        assert_eq!(Key::new("GET", URL, b"").file_name(), name);
+        for other in [
+            Key::new("POST", URL, b""),
+            Key::new("GET", "http://api.test/users?id=2", b""),
+            Key::new("GET", URL, b"x"),
+        ] {
+            assert_ne!(other, key);
+            assert_ne!(other.file_name(), name);
+        }
+    }
+
+    #[test]
+    fn record_mode_writes_a_fixture() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = temp_dir("record");
+        let resp = block_on(async {
+            // A loopback origin that echoes the body and reports the path it saw.
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let origin = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
+                    let path = req.uri().to_string();
+                    let body = req.into_body().collect().await?.to_bytes();
+                    let mut resp = hyper::Response::new(Full::new(body));
+                    *resp.status_mut() = hyper::StatusCode::CREATED;
+                    resp.headers_mut().insert("x-path", path.parse().unwrap());
+                    Ok::<_, hyper::Error>(resp)
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+            install(dir.clone(), Some(parse_origin(&origin).unwrap()), true);
+            exchange(request("POST", URL, "ping"), config())
+                .await
+                .unwrap()
+                .resp
+        });
+        assert_eq!(resp.status(), 201);
+
+        let key = Key::new("POST", URL, b"ping");
+        let (saved, fixture) = load(&dir.join(key.file_name())).unwrap();
+        assert_eq!(saved, key);
+        assert_eq!(fixture.status, 201);
+        assert_eq!(fixture.body, b"ping");
+        assert!(fixture
+            .headers
+            .contains(&("x-path".to_string(), "/users?id=1".to_string())));
+        assert!(with_fixtures(|f| f.entries.contains_key(&key)));
+        finish().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn strict_mode_rejects_unrecorded_requests() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = temp_dir("strict");
+
+        install(dir.clone(), None, false);
+        let resp = block_on(exchange(request("GET", URL, ""), config())).unwrap();
+        assert_eq!(resp.resp.status(), 404);
+        finish().unwrap();
+
+        install(dir.clone(), None, true);
+        let result = block_on(exchange(request("GET", URL, ""), config()));
+        assert!(matches!(result, Err(ErrorCode::HttpRequestDenied)));
+        let err = finish().unwrap_err().to_string();
+        assert!(err.contains(&format!("GET {}", URL)), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -4,6 +4,10 @@ use anyhow::Result;
 use wasmtime::component::{Component, Linker, Val};
 use wasmtime::{Config, Engine, Store};
 
+use crate::coverage;
+use crate::host;
+use crate::trace;
+
 pub fn run_import_test(component_path: &str) -> Result<()> {
     // Enable component model
     let mut config = Config::new();
@@ -12,6 +16,7 @@ pub fn run_import_test(component_path: &str) -> Result<()> {
 
     println!("Loading component: {}", component_path);
     let component = Component::from_file(&engine, component_path)?;
+    coverage::register(&engine, component_path, &component);
 
     // Create linker and store
     let mut linker = Linker::<()>::new(&engine);
@@ -21,15 +26,18 @@ pub fn run_import_test(component_path: &str) -> Result<()> {
     provider.func_new(
         "get-greeting",
         |mut _store, params: &[Val], results: &mut [Val]| {
+            trace::host_call("local:import-test/greet-provider#get-greeting", params);
             if let Val::String(name) = &params[0] {
                 let greeting = format!("Hello from Rust, {}!", name);
                 results[0] = Val::String(greeting.into());
             }
+            trace::host_results(results);
             Ok(())
         },
     )?;
 
     let mut store = Store::new(&engine, ());
+    trace::attach(&mut store);
 
     // Instantiate
     let instance = linker.instantiate(&mut store, &component)?;
@@ -49,17 +57,20 @@ pub fn run_import_test(component_path: &str) -> Result<()> {
 
     // Call run
     let mut results = vec![Val::String("".into())];
-    run_func.call(&mut store, &[], &mut results)?;
+    host::call(
+        &run_func,
+        &mut store,
+        "local:import-test/greet-consumer#run",
+        &[],
+        &mut results,
+    )?;
 
-    // Extract result before post_return
     let result_str = if let Val::String(result) = &results[0] {
         result.to_string()
     } else {
         anyhow::bail!("Unexpected result type");
     };
 
-    run_func.post_return(&mut store)?;
-
     println!("Result: {}", result_str);
     assert_eq!(result_str, "Hello from Rust, MoonBit!");
     println!("Import test PASSED!");
@@ -0,0 +1,411 @@
+// Local wasi:keyvalue (`--keyvalue`, `--keyvalue-file`)
+//
+// Serves `wasi:keyvalue/{store,atomics,batch}@0.2.0-draft` from buckets held
+// in memory. With `--keyvalue-file` the buckets are loaded from a JSON file
+// when the host starts and written back at exit, so state survives between
+// runs and tests can assert on what a component stored. Values are written as
+// strings when they are UTF-8 and as byte arrays otherwise.
+//
+// Every store in the process sees the same buckets.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use wasmtime::component::{Linker, Resource};
+
+use crate::host::{self, HostState};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        inline: r#"
+            package wasi:keyvalue@0.2.0-draft;
+
+            interface store {
+                variant error {
+                    no-such-store,
+                    access-denied,
+                    other(string),
+                }
+
+                record key-response {
+                    keys: list<string>,
+                    cursor: option<u64>,
+                }
+
+                open: func(identifier: string) -> result<bucket, error>;
+
+                resource bucket {
+                    get: func(key: string) -> result<option<list<u8>>, error>;
+                    set: func(key: string, value: list<u8>) -> result<_, error>;
+                    delete: func(key: string) -> result<_, error>;
+                    exists: func(key: string) -> result<bool, error>;
+                    list-keys: func(cursor: option<u64>) -> result<key-response, error>;
+                }
+            }
+
+            interface atomics {
+                use store.{bucket, error};
+
+                increment: func(bucket: borrow<bucket>, key: string, delta: u64) -> result<u64, error>;
+            }
+
+            interface batch {
+                use store.{bucket, error};
+
+                get-many: func(bucket: borrow<bucket>, keys: list<string>) -> result<list<option<tuple<string, list<u8>>>>, error>;
+                set-many: func(bucket: borrow<bucket>, key-values: list<tuple<string, list<u8>>>) -> result<_, error>;
+                delete-many: func(bucket: borrow<bucket>, keys: list<string>) -> result<_, error>;
+            }
+
+            world imports {
+                import store;
+                import atomics;
+                import batch;
+            }
+        "#,
+        with: {
+            "wasi:keyvalue/store/bucket": crate::keyvalue::Bucket,
+        },
+    });
+}
+
+use bindings::wasi::keyvalue::{atomics, batch, store};
+
+/// Bucket name -> key -> value.
+type Buckets = BTreeMap<String, BTreeMap<String, Vec<u8>>>;
+
+static KEYVALUE: Mutex<Option<Buckets>> = Mutex::new(None);
+
+/// Keys returned per `list-keys` call; `cursor` is the offset of the next page.
+const LIST_PAGE: usize = 256;
+
+/// What a `bucket` resource holds: the identifier passed to `open`.
+pub struct Bucket(String);
+
+fn with_bucket<R>(name: &str, f: impl FnOnce(&mut BTreeMap<String, Vec<u8>>) -> R) -> R {
+    let mut guard = KEYVALUE.lock().unwrap();
+    let buckets = guard.as_mut().expect("keyvalue not enabled");
+    f(buckets.entry(name.to_string()).or_default())
+}
+
+fn load(path: &Path) -> Result<Buckets> {
+    let text = std::fs::read_to_string(path)?;
+    let Value::Object(root) = serde_json::from_str(&text)? else {
+        return Err(anyhow!("expected an object of buckets"));
+    };
+    let mut buckets = BTreeMap::new();
+    for (name, entries) in root {
+        let Value::Object(entries) = entries else {
+            return Err(anyhow!("bucket {:?} is not an object", name));
+        };
+        let mut bucket = BTreeMap::new();
+        for (key, value) in entries {
+            let bytes = match value {
+                Value::String(s) => s.into_bytes(),
+                Value::Array(items) => items
+                    .iter()
+                    .map(|v| v.as_u64().and_then(|b| u8::try_from(b).ok()))
+                    .collect::<Option<Vec<u8>>>()
+                    .with_context(|| format!("{}/{}: expected bytes", name, key))?,
+                _ => return Err(anyhow!("{}/{}: expected a string or byte array", name, key)),
+            };
+            bucket.insert(key, bytes);
+        }
+        buckets.insert(name, bucket);
+    }
+    Ok(buckets)
+}
+
+/// Create the buckets, loading `--keyvalue-file` if it exists.
+pub fn enable() -> Result<()> {
+    let opts = host::options();
+    if !opts.keyvalue {
+        return Ok(());
+    }
+    let buckets = match &opts.keyvalue_file {
+        Some(file) if Path::new(file).exists() => {
+            load(Path::new(file)).with_context(|| format!("failed to load {}", file))?
+        }
+        _ => BTreeMap::new(),
+    };
+    *KEYVALUE.lock().unwrap() = Some(buckets);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    KEYVALUE.lock().unwrap().is_some()
+}
+
+fn save(path: &Path, buckets: Buckets) -> Result<()> {
+    let mut root = Map::new();
+    for (name, bucket) in buckets {
+        let entries = bucket
+            .into_iter()
+            .map(|(key, bytes)| {
+                let value = match String::from_utf8(bytes) {
+                    Ok(s) => Value::String(s),
+                    Err(e) => e.into_bytes().into_iter().map(Value::from).collect(),
+                };
+                (key, value)
+            })
+            .collect();
+        root.insert(name, Value::Object(entries));
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&Value::Object(root))?)?;
+    Ok(())
+}
+
+/// Write the buckets back to `--keyvalue-file`.
+pub fn finish() -> Result<()> {
+    let (Some(file), Some(buckets)) = (
+        &host::options().keyvalue_file,
+        KEYVALUE.lock().unwrap().take(),
+    ) else {
+        return Ok(());
+    };
+    save(Path::new(file), buckets)?;
+    println!("\nKeyvalue buckets written to: {}", file);
+    Ok(())
+}
+
+pub fn add_to_linker(linker: &mut Linker<HostState>) -> Result<()> {
+    bindings::Imports::add_to_linker(linker, |state| state)
+}
+
+impl HostState {
+    fn bucket(&self, bucket: &Resource<Bucket>) -> Result<String, store::Error> {
+        self.table
+            .get(bucket)
+            .map(|b| b.0.clone())
+            .map_err(|e| store::Error::Other(e.to_string()))
+    }
+}
+
+impl store::Host for HostState {
+    fn open(&mut self, identifier: String) -> Result<Resource<Bucket>, store::Error> {
+        with_bucket(&identifier, |_| ());
+        self.table
+            .push(Bucket(identifier))
+            .map_err(|e| store::Error::Other(e.to_string()))
+    }
+}
+
+impl store::HostBucket for HostState {
+    fn get(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+    ) -> Result<Option<Vec<u8>>, store::Error> {
+        let name = self.bucket(&bucket)?;
+        Ok(with_bucket(&name, |b| b.get(&key).cloned()))
+    }
+
+    fn set(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<(), store::Error> {
+        let name = self.bucket(&bucket)?;
+        with_bucket(&name, |b| b.insert(key, value));
+        Ok(())
+    }
+
+    fn delete(&mut self, bucket: Resource<Bucket>, key: String) -> Result<(), store::Error> {
+        let name = self.bucket(&bucket)?;
+        with_bucket(&name, |b| b.remove(&key));
+        Ok(())
+    }
+
+    fn exists(&mut self, bucket: Resource<Bucket>, key: String) -> Result<bool, store::Error> {
+        let name = self.bucket(&bucket)?;
+        Ok(with_bucket(&name, |b| b.contains_key(&key)))
+    }
+
+    fn list_keys(
+        &mut self,
+        bucket: Resource<Bucket>,
+        cursor: Option<u64>,
+    ) -> Result<store::KeyResponse, store::Error> {
+        let name = self.bucket(&bucket)?;
+        let start = cursor.unwrap_or(0) as usize;
+        Ok(with_bucket(&name, |b| {
+            let keys: Vec<String> = b.keys().skip(start).take(LIST_PAGE).cloned().collect();
+            let next = start + keys.len();
+            store::KeyResponse {
+                cursor: (next < b.len()).then_some(next as u64),
+                keys,
+            }
+        }))
+    }
+
+    fn drop(&mut self, bucket: Resource<Bucket>) -> wasmtime::Result<()> {
+        self.table.delete(bucket)?;
+        Ok(())
+    }
+}
+
+impl atomics::Host for HostState {
+    fn increment(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+        delta: u64,
+    ) -> Result<u64, store::Error> {
+        let name = self.bucket(&bucket)?;
+        with_bucket(&name, |b| {
+            // Counters are stored as decimal strings, which keeps the JSON
+            // file readable.
+            let current = match b.get(&key) {
+                Some(bytes) => std::str::from_utf8(bytes)
+                    .ok()
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .ok_or_else(|| store::Error::Other(format!("{} is not a counter", key)))?,
+                None => 0,
+            };
+            let value = current.wrapping_add(delta);
+            b.insert(key, value.to_string().into_bytes());
+            Ok(value)
+        })
+    }
+}
+
+impl batch::Host for HostState {
+    fn get_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        keys: Vec<String>,
+    ) -> Result<Vec<Option<(String, Vec<u8>)>>, store::Error> {
+        let name = self.bucket(&bucket)?;
+        Ok(with_bucket(&name, |b| {
+            keys.into_iter()
+                .map(|key| b.get(&key).cloned().map(|value| (key, value)))
+                .collect()
+        }))
+    }
+
+    fn set_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key_values: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), store::Error> {
+        let name = self.bucket(&bucket)?;
+        with_bucket(&name, |b| b.extend(key_values));
+        Ok(())
+    }
+
+    fn delete_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        keys: Vec<String>,
+    ) -> Result<(), store::Error> {
+        let name = self.bucket(&bucket)?;
+        with_bucket(&name, |b| {
+            for key in &keys {
+                b.remove(key);
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use store::{Host as _, HostBucket as _};
+    use wasmtime_wasi::WasiCtxBuilder;
+
+    /// The buckets are process-wide, so tests that install them take turns.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    /// Install empty buckets and run `f` with a fresh host state.
+    fn with_buckets(f: impl FnOnce(&mut HostState)) {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        *KEYVALUE.lock().unwrap() = Some(BTreeMap::new());
+        f(&mut HostState::new(WasiCtxBuilder::new().build()));
+        *KEYVALUE.lock().unwrap() = None;
+    }
+
+    fn borrow(bucket: &Resource<Bucket>) -> Resource<Bucket> {
+        Resource::new_borrow(bucket.rep())
+    }
+
+    #[test]
+    fn bucket_operations() {
+        with_buckets(|state| {
+            let bucket = state.open("b".to_string()).unwrap();
+            assert_eq!(state.get(borrow(&bucket), "k".to_string()).unwrap(), None);
+            assert!(!state.exists(borrow(&bucket), "k".to_string()).unwrap());
+
+            state
+                .set(borrow(&bucket), "k".to_string(), b"v".to_vec())
+                .unwrap();
+            assert_eq!(
+                state.get(borrow(&bucket), "k".to_string()).unwrap(),
+                Some(b"v".to_vec())
+            );
+            assert!(state.exists(borrow(&bucket), "k".to_string()).unwrap());
+
+            // Buckets are shared by name, not by resource.
+            let again = state.open("b".to_string()).unwrap();
+            assert_eq!(
+                state.get(borrow(&again), "k".to_string()).unwrap(),
+                Some(b"v".to_vec())
+            );
+
+            state.delete(borrow(&bucket), "k".to_string()).unwrap();
+            assert!(!state.exists(bucket, "k".to_string()).unwrap());
+        });
+    }
+
+    #[test]
+    fn list_keys_pages_with_a_cursor() {
+        with_buckets(|state| {
+            let bucket = state.open("b".to_string()).unwrap();
+            let total = LIST_PAGE + 10;
+            for i in 0..total {
+                state
+                    .set(borrow(&bucket), format!("key-{:04}", i), Vec::new())
+                    .unwrap();
+            }
+            let first = state.list_keys(borrow(&bucket), None).unwrap();
+            assert_eq!(first.keys.len(), LIST_PAGE);
+            assert_eq!(first.keys[0], "key-0000");
+            assert_eq!(first.cursor, Some(LIST_PAGE as u64));
+
+            let rest = state.list_keys(borrow(&bucket), first.cursor).unwrap();
+            assert_eq!(rest.keys.len(), 10);
+            assert_eq!(rest.keys[9], format!("key-{:04}", total - 1));
+            assert_eq!(rest.cursor, None);
+
+            let empty = state.open("empty".to_string()).unwrap();
+            let none = state.list_keys(empty, None).unwrap();
+            assert!(none.keys.is_empty());
+            assert_eq!(none.cursor, None);
+        });
+    }
+
+    #[test]
+    fn keyvalue_file_round_trips() {
+        let path = std::env::temp_dir().join(format!("keyvalue-{}.json", std::process::id()));
+        let buckets = Buckets::from([
+            (
+                "app".to_string(),
+                BTreeMap::from([
+                    ("text".to_string(), b"hello".to_vec()),
+                    ("bytes".to_string(), vec![0xff, 0x00, 0x80]),
+                ]),
+            ),
+            ("empty".to_string(), BTreeMap::new()),
+        ]);
+        save(&path, buckets.clone()).unwrap();
+
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["app"]["text"], "hello");
+        assert_eq!(json["app"]["bytes"], serde_json::json!([255, 0, 128]));
+        assert_eq!(load(&path).unwrap(), buckets);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+// Local wasi:logging (`--logging`, `--logging-file`, `--expect-log`)
+//
+// Serves `wasi:logging/logging@0.1.0-draft`. Every record is echoed to stderr
+// and kept; at exit the records are written to `--logging-file` as JSON and
+// checked against `--expect-log`, so a test can assert that a component
+// logged what it should without scraping its output.
+
+use anyhow::{bail, Result};
+use serde_json::json;
+use std::sync::Mutex;
+use wasmtime::component::Linker;
+
+use crate::host::{self, HostState};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        inline: r#"
+            package wasi:logging@0.1.0-draft;
+
+            interface logging {
+                enum level {
+                    trace,
+                    debug,
+                    info,
+                    warn,
+                    error,
+                    critical,
+                }
+
+                log: func(level: level, context: string, message: string);
+            }
+
+            world imports {
+                import logging;
+            }
+        "#,
+    });
+}
+
+use bindings::wasi::logging::logging::{self, Level};
+
+static LOGS: Mutex<Option<Vec<Record>>> = Mutex::new(None);
+
+struct Record {
+    level: &'static str,
+    context: String,
+    message: String,
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "trace",
+        Level::Debug => "debug",
+        Level::Info => "info",
+        Level::Warn => "warn",
+        Level::Error => "error",
+        Level::Critical => "critical",
+    }
+}
+
+/// `--expect-log [LEVEL:]TEXT`: some record at LEVEL (any level if omitted)
+/// has TEXT in its message.
+fn matches(expect: &str, record: &Record) -> bool {
+    match expect.split_once(':') {
+        Some((level, text)) if level == record.level => record.message.contains(text),
+        _ => record.message.contains(expect),
+    }
+}
+
+pub fn enable() {
+    if host::options().logging {
+        *LOGS.lock().unwrap() = Some(Vec::new());
+    }
+}
+
+pub fn enabled() -> bool {
+    LOGS.lock().unwrap().is_some()
+}
+
+/// Write `--logging-file` and check `--expect-log`.
+pub fn finish() -> Result<()> {
+    let Some(records) = LOGS.lock().unwrap().take() else {
+        return Ok(());
+    };
+    let opts = host::options();
+    if let Some(file) = &opts.logging_file {
+        let out: Vec<_> = records
+            .iter()
+            .map(|r| json!({ "level": r.level, "context": r.context, "message": r.message }))
+            .collect();
+        std::fs::write(file, serde_json::to_string_pretty(&out)?)?;
+        println!("\nLog records written to: {}", file);
+    }
+    check_expected(&opts.expect_log, &records)
+}
+
+/// Fail unless every `--expect-log` pattern matches some record.
+fn check_expected(expect_log: &[String], records: &[Record]) -> Result<()> {
+    let missing: Vec<_> = expect_log
+        .iter()
+        .filter(|expect| !records.iter().any(|r| matches(expect, r)))
+        .collect();
+    if !missing.is_empty() {
+        bail!("expected log records not found: {:?}", missing);
+    }
+    Ok(())
+}
+
+pub fn add_to_linker(linker: &mut Linker<HostState>) -> Result<()> {
+    bindings::Imports::add_to_linker(linker, |state| state)
+}
+
+impl logging::Host for HostState {
+    fn log(&mut self, level: Level, context: String, message: String) {
+        let level = level_name(level);
+        eprintln!("[{}] {}: {}", level, context, message);
+        if let Some(records) = LOGS.lock().unwrap().as_mut() {
+            records.push(Record {
+                level,
+                context,
+                message,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(level: &'static str, message: &str) -> Record {
+        Record {
+            level,
+            context: "test".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn expect_log_matches_level_and_text() {
+        let info = record("info", "server started on port 80");
+        assert!(matches("started", &info));
+        assert!(matches("info:port 80", &info));
+        assert!(!matches("error:started", &info));
+        assert!(!matches("stopped", &info));
+        // Only a level name before the colon selects a level.
+        assert!(matches("port 80: in", &record("warn", "port 80: in use")));
+    }
+
+    #[test]
+    fn missing_expectations_fail() {
+        let records = [record("info", "ready"), record("error", "disk full")];
+        let expect = |patterns: &[&str]| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            check_expected(&patterns, &records)
+        };
+        expect(&["ready", "error:disk"]).unwrap();
+        expect(&[]).unwrap();
+        let err = expect(&["ready", "info:disk", "gone"]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "expected log records not found: [\"info:disk\", \"gone\"]"
+        );
+    }
+}
@@ -1,25 +1,98 @@
 // Rust host for testing MoonBit guest component
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use wasmtime::component::{Component, Linker, Val};
 use wasmtime::{Config, Engine, Store};
 
+mod abi_check;
+mod audit;
+mod bridge;
+mod cabi;
+mod componentize_diff;
+mod config;
+mod core_module;
+mod coverage;
+mod deterministic;
+mod echo;
+mod host;
+mod http_fixtures;
 mod import_test;
+mod instantiate;
+mod keyvalue;
+mod logging;
+mod memfs;
+mod policy;
+mod profile;
+mod run;
+mod serve;
+mod sockets;
+mod trace;
 mod types_bench;
 mod types_test;
+mod vals;
+mod wagi;
+mod wit_surface;
 
 fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();
+    let args = host::init_options(std::env::args().collect())?;
 
     if args.len() < 2 {
         eprintln!("Usage: rust-host <test-type> [component-path]");
         eprintln!("  test-type: guest | import | types | bench");
+        eprintln!("       rust-host profile <component-path> <interface#func> [args...] [--iterations N] [--interval-us N] [--output FILE]");
+        eprintln!("       rust-host run <component-path> [--arg A]... [--env K=V]... [--dir HOST[::GUEST]]... [--stdin FILE] [--expect-stdout FILE] [--expect-exit N] [-- args...]");
+        eprintln!("       rust-host serve <component-path> [--addr HOST:PORT]");
+        eprintln!("       rust-host audit <component-path> [--policy FILE]");
+        eprintln!("       rust-host echo <component-path> [--message TEXT]");
+        eprintln!("       rust-host instantiate <component-path>");
+        eprintln!("       rust-host core <impl.wasm> [--wit PATH [--world NAME]] [--call EXPORT [ARG]...]... [--invoke EXPORT [VAL]...]... [--read ADDR LEN]...");
+        eprintln!("       rust-host abi-check <wit-path> <core.wasm> [--world NAME]");
+        eprintln!("       rust-host componentize-diff <core.wasm> --wit-dir DIR [--world NAME] [--actual COMPONENT] [--out DIR] [--suite types|import|guest]...");
+        eprintln!("       rust-host wagi <component-path> [--addr HOST:PORT] [--route /path/...] [--env K=V]...");
+        eprintln!("Options (all modes):");
+        eprintln!("  --trace FILE      write host/guest call transitions as a Chrome trace");
+        eprintln!("  --coverage FILE   report which exports and cases were exercised");
+        eprintln!("  --deterministic   virtual clocks, seeded wasi:random, no inherited environment");
+        eprintln!("  --seed N          seed for --deterministic randomness (default 0)");
+        eprintln!("  --clock-step NS   advance the virtual clock by NS per read (default 0: frozen)");
+        eprintln!("  --memfs GUEST[=SRC]      in-memory preopen, seeded from a directory or .tar");
+        eprintln!("  --memfs-ro GUEST=SRC     read-only in-memory preopen");
+        eprintln!("  --memfs-fail OP:PATH[=CODE]  fail OP (open|read|write|stat|readdir|mkdir|remove|rename|any) on PATH (trailing * matches a prefix)");
+        eprintln!("  --memfs-dump DIR         write the in-memory tree to DIR at exit");
+        eprintln!("  --memfs-max-size BYTES   largest file a guest may write to memfs (default 256 MiB)");
+        eprintln!("  --keyvalue               serve wasi:keyvalue from memory");
+        eprintln!("  --keyvalue-file FILE     load wasi:keyvalue buckets from FILE (JSON) and save them back at exit");
+        eprintln!("  --config FILE            serve wasi:config from FILE (TOML, nested tables become dotted keys)");
+        eprintln!("  --logging                serve wasi:logging, echoing records to stderr");
+        eprintln!("  --logging-file FILE      write captured log records to FILE (JSON)");
+        eprintln!("  --expect-log [LEVEL:]TEXT  fail unless a record containing TEXT was logged");
+        eprintln!("  --http-fixtures DIR      answer outgoing wasi:http requests from recorded fixtures");
+        eprintln!("  --http-record ORIGIN     proxy outgoing requests to a loopback ORIGIN and record them into DIR");
+        eprintln!("  --http-strict            fail requests (and the run) that have no fixture");
+        eprintln!("  --policy FILE            import allow-list (TOML); disallowed imports trap, and fail `audit`");
+        eprintln!("  --sockets FILE           enable wasi:sockets for the loopback addresses a TOML policy allows");
         std::process::exit(1);
     }
 
     let test_type = &args[1];
 
-    match test_type.as_str() {
+    trace::enable();
+    coverage::enable();
+    memfs::enable()?;
+    keyvalue::enable()?;
+    config::enable()?;
+    logging::enable();
+    http_fixtures::enable()?;
+    sockets::enable()?;
+    policy::enable()?;
+    // These modes link only their own test imports, not the shared WASI
+    // linker the allow-list is applied to.
+    if host::options().policy.is_some()
+        && matches!(test_type.as_str(), "guest" | "import" | "bench" | "core" | "abi-check" | "componentize-diff")
+    {
+        bail!("--policy is not supported by the {} mode", test_type);
+    }
+    let result = match test_type.as_str() {
         "guest" => {
             let component_path = args.get(2)
                 .map(|s| s.as_str())
@@ -44,11 +117,29 @@ fn main() -> Result<()> {
                 .unwrap_or("../../tests/types-test/types-test.component.wasm");
             types_bench::run_types_bench(component_path)
         }
+        "profile" => profile::run_profile(&args[2..]),
+        "run" => run::run_command(&args[2..]),
+        "serve" => serve::run_serve(&args[2..]),
+        "wagi" => wagi::run_wagi(&args[2..]),
+        "echo" => echo::run_echo(&args[2..]),
+        "instantiate" => instantiate::run_instantiate(&args[2..]),
+        "audit" => audit::run_audit(&args[2..]),
+        "core" => core_module::run_core(&args[2..]),
+        "abi-check" => abi_check::run_abi_check(&args[2..]),
+        "componentize-diff" => componentize_diff::run_componentize_diff(&args[2..]),
         _ => {
             eprintln!("Unknown test type: {}", test_type);
             std::process::exit(1);
         }
-    }
+    };
+    trace::finish()?;
+    coverage::finish()?;
+    memfs::finish()?;
+    keyvalue::finish()?;
+    logging::finish()?;
+    http_fixtures::finish()?;
+    sockets::finish()?;
+    result
 }
 
 fn run_guest_test(component_path: &str) -> Result<()> {
@@ -59,10 +150,12 @@ fn run_guest_test(component_path: &str) -> Result<()> {
 
     println!("Loading component: {}", component_path);
     let component = Component::from_file(&engine, component_path)?;
+    coverage::register(&engine, component_path, &component);
 
     // Create linker and store
     let linker = Linker::<()>::new(&engine);
     let mut store = Store::new(&engine, ());
+    trace::attach(&mut store);
 
     // Instantiate
     let instance = linker.instantiate(&mut store, &component)?;
@@ -82,18 +175,20 @@ fn run_guest_test(component_path: &str) -> Result<()> {
 
     // Call greet with "World"
     let mut results = vec![Val::String("".into())];
-    greet_func.call(&mut store, &[Val::String("World".into())], &mut results)?;
+    host::call(
+        &greet_func,
+        &mut store,
+        "local:hello/greet#greet",
+        &[Val::String("World".into())],
+        &mut results,
+    )?;
 
-    // Extract result before post_return
     let result_str = if let Val::String(result) = &results[0] {
         result.to_string()
     } else {
         anyhow::bail!("Unexpected result type");
     };
 
-    // Must call post_return after reading results
-    greet_func.post_return(&mut store)?;
-
     println!("Result: {}", result_str);
     assert_eq!(result_str, "Hello, World!");
     println!("Guest test PASSED!");
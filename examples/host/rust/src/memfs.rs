@@ -0,0 +1,1413 @@
+// In-memory filesystem for WASI (`--memfs`)
+//
+// Replaces `wasi:filesystem` with a tree held in memory. Each mount is seeded
+// from a host directory or a tar archive (or starts empty), shows up in the
+// guest as a preopen, and can be read-only. `--memfs-fail` makes chosen
+// operations on chosen paths fail with a WASI error code, so error paths in
+// guest file handling can be tested. At exit the tree is written to
+// `--memfs-dump` for tests to assert on the files a component wrote.
+//
+// All stores in the process share the one tree, like a real disk.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+use wasmtime::component::{Linker, Resource, ResourceTable};
+use wasmtime_wasi::bindings::filesystem::preopens;
+use wasmtime_wasi::bindings::filesystem::types::ErrorCode;
+use wasmtime_wasi::bindings::sync::filesystem::types as fs;
+use wasmtime_wasi::bindings::sync::io::streams;
+use wasmtime_wasi::{
+    FsError, FsResult, HostInputStream, HostOutputStream, StreamError, StreamResult, Subscribe,
+};
+
+use crate::host::{self, HostState};
+
+static MEMFS: Mutex<Option<MemFs>> = Mutex::new(None);
+
+/// Largest write a guest output stream accepts at once.
+const WRITE_BUDGET: usize = 1024 * 1024;
+
+/// Largest file a guest may grow, unless `--memfs-max-size` says otherwise.
+pub const DEFAULT_MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+/// `--memfs GUEST[=SOURCE]` / `--memfs-ro GUEST=SOURCE`.
+#[derive(Clone)]
+pub struct MountSpec {
+    guest: String,
+    source: Option<String>,
+    read_only: bool,
+}
+
+impl MountSpec {
+    pub fn parse(spec: &str, read_only: bool) -> Result<Self> {
+        let (guest, source) = match spec.split_once('=') {
+            Some((guest, source)) => (guest, Some(source.to_string())),
+            None => (spec, None),
+        };
+        if !guest.starts_with('/') {
+            bail!("memfs mount point must be absolute: {}", guest);
+        }
+        Ok(MountSpec {
+            guest: guest.trim_end_matches('/').to_string(),
+            source,
+            read_only,
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Open,
+    Read,
+    Write,
+    Stat,
+    Readdir,
+    Mkdir,
+    Remove,
+    Rename,
+    Any,
+}
+
+/// `--memfs-fail OP:PATH[=CODE]`, e.g. `write:/out/data.txt=insufficient-space`.
+#[derive(Clone)]
+pub struct Failure {
+    op: Op,
+    path: String,
+    code: ErrorCode,
+}
+
+impl Failure {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (op, rest) = spec
+            .split_once(':')
+            .with_context(|| format!("--memfs-fail needs OP:PATH[=CODE]: {}", spec))?;
+        let (path, code) = rest.split_once('=').unwrap_or((rest, "io"));
+        let op = match op {
+            "open" => Op::Open,
+            "read" => Op::Read,
+            "write" => Op::Write,
+            "stat" => Op::Stat,
+            "readdir" => Op::Readdir,
+            "mkdir" => Op::Mkdir,
+            "remove" => Op::Remove,
+            "rename" => Op::Rename,
+            "any" => Op::Any,
+            _ => bail!("unknown --memfs-fail operation: {}", op),
+        };
+        Ok(Failure {
+            op,
+            path: path.to_string(),
+            code: parse_error_code(code)?,
+        })
+    }
+
+    fn matches(&self, op: Op, path: &str) -> bool {
+        (self.op == Op::Any || self.op == op)
+            && match self.path.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == self.path,
+            }
+    }
+}
+
+/// Error codes by their WIT names.
+fn parse_error_code(name: &str) -> Result<ErrorCode> {
+    Ok(match name {
+        "access" => ErrorCode::Access,
+        "would-block" => ErrorCode::WouldBlock,
+        "busy" => ErrorCode::Busy,
+        "quota" => ErrorCode::Quota,
+        "exist" => ErrorCode::Exist,
+        "file-too-large" => ErrorCode::FileTooLarge,
+        "interrupted" => ErrorCode::Interrupted,
+        "invalid" => ErrorCode::Invalid,
+        "io" => ErrorCode::Io,
+        "is-directory" => ErrorCode::IsDirectory,
+        "name-too-long" => ErrorCode::NameTooLong,
+        "no-entry" => ErrorCode::NoEntry,
+        "insufficient-memory" => ErrorCode::InsufficientMemory,
+        "insufficient-space" => ErrorCode::InsufficientSpace,
+        "not-directory" => ErrorCode::NotDirectory,
+        "not-empty" => ErrorCode::NotEmpty,
+        "unsupported" => ErrorCode::Unsupported,
+        "not-permitted" => ErrorCode::NotPermitted,
+        "read-only" => ErrorCode::ReadOnly,
+        "cross-device" => ErrorCode::CrossDevice,
+        _ => bail!("unknown filesystem error code: {}", name),
+    })
+}
+
+enum NodeKind {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, usize>),
+}
+
+struct Node {
+    kind: NodeKind,
+    access: Option<fs::Datetime>,
+    modification: Option<fs::Datetime>,
+}
+
+struct Mount {
+    guest: String,
+    root: usize,
+    read_only: bool,
+}
+
+/// Nodes are never freed, so descriptors of removed files stay usable.
+struct MemFs {
+    nodes: Vec<Node>,
+    mounts: Vec<Mount>,
+    failures: Vec<Failure>,
+    /// Largest size a write or `set-size` may give a file.
+    max_size: u64,
+}
+
+impl MemFs {
+    /// A tree with one seeded root per mount.
+    fn new(specs: &[MountSpec], failures: Vec<Failure>) -> Result<Self> {
+        let mut memfs = MemFs {
+            nodes: Vec::new(),
+            mounts: Vec::new(),
+            failures,
+            max_size: DEFAULT_MAX_SIZE,
+        };
+        for spec in specs {
+            let root = memfs.add(NodeKind::Dir(BTreeMap::new()));
+            match &spec.source {
+                Some(src) if src.ends_with(".tar") => memfs.load_tar(root, Path::new(src))?,
+                Some(src) => memfs.load_dir(root, Path::new(src))?,
+                None => {}
+            }
+            memfs.mounts.push(Mount {
+                guest: spec.guest.clone(),
+                root,
+                read_only: spec.read_only,
+            });
+        }
+        Ok(memfs)
+    }
+
+    fn add(&mut self, kind: NodeKind) -> usize {
+        self.nodes.push(Node {
+            kind,
+            access: None,
+            modification: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn check(&self, op: Op, path: &str) -> FsResult<()> {
+        match self.failures.iter().find(|f| f.matches(op, path)) {
+            Some(f) => Err(f.code.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn dir(&self, inode: usize) -> FsResult<&BTreeMap<String, usize>> {
+        match &self.nodes[inode].kind {
+            NodeKind::Dir(entries) => Ok(entries),
+            NodeKind::File(_) => Err(ErrorCode::NotDirectory.into()),
+        }
+    }
+
+    fn dir_mut(&mut self, inode: usize) -> FsResult<&mut BTreeMap<String, usize>> {
+        match &mut self.nodes[inode].kind {
+            NodeKind::Dir(entries) => Ok(entries),
+            NodeKind::File(_) => Err(ErrorCode::NotDirectory.into()),
+        }
+    }
+
+    fn file_mut(&mut self, inode: usize) -> FsResult<&mut Vec<u8>> {
+        match &mut self.nodes[inode].kind {
+            NodeKind::File(data) => Ok(data),
+            NodeKind::Dir(_) => Err(ErrorCode::IsDirectory.into()),
+        }
+    }
+
+    /// Whether `inode` is `root` or somewhere in the tree below it.
+    fn contains(&self, root: usize, inode: usize) -> bool {
+        root == inode
+            || match &self.nodes[root].kind {
+                NodeKind::Dir(entries) => {
+                    entries.values().any(|&child| self.contains(child, inode))
+                }
+                NodeKind::File(_) => false,
+            }
+    }
+
+    /// Walk `path` from directory `start`. `..` may not leave `start`, as WASI
+    /// paths are confined to the descriptor they are relative to.
+    fn lookup(&self, start: usize, path: &str) -> FsResult<usize> {
+        let (parent, name) = self.parent(start, path)?;
+        match name {
+            Some(name) => self
+                .dir(parent)?
+                .get(name)
+                .copied()
+                .ok_or_else(|| ErrorCode::NoEntry.into()),
+            None => Ok(parent),
+        }
+    }
+
+    /// Resolve all but the last component of `path`; the last is returned as
+    /// is (`None` when the path names `start` itself or ends in `..`).
+    fn parent<'p>(&self, start: usize, path: &'p str) -> FsResult<(usize, Option<&'p str>)> {
+        if path.starts_with('/') {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let parts: Vec<&str> = path
+            .split('/')
+            .filter(|p| !p.is_empty() && *p != ".")
+            .collect();
+        let mut stack = vec![start];
+        for (i, part) in parts.iter().enumerate() {
+            let last = i + 1 == parts.len();
+            if *part == ".." {
+                if stack.len() == 1 {
+                    return Err(ErrorCode::NotPermitted.into());
+                }
+                stack.pop();
+                continue;
+            }
+            if last {
+                return Ok((*stack.last().unwrap(), Some(part)));
+            }
+            let dir = self.dir(*stack.last().unwrap())?;
+            stack.push(*dir.get(*part).ok_or(ErrorCode::NoEntry)?);
+        }
+        Ok((*stack.last().unwrap(), None))
+    }
+
+    fn load_dir(&mut self, inode: usize, dir: &Path) -> Result<()> {
+        for entry in
+            std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let child = if entry.file_type()?.is_dir() {
+                let child = self.add(NodeKind::Dir(BTreeMap::new()));
+                self.load_dir(child, &entry.path())?;
+                child
+            } else {
+                self.add(NodeKind::File(std::fs::read(entry.path())?))
+            };
+            self.dir_mut(inode)
+                .map_err(|_| anyhow!("not a directory"))?
+                .insert(name, child);
+        }
+        Ok(())
+    }
+
+    fn load_tar(&mut self, root: usize, path: &Path) -> Result<()> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let mut archive = tar::Archive::new(file);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.to_string_lossy().into_owned();
+            let is_dir = entry.header().entry_type().is_dir();
+            let mut dir = root;
+            let parts: Vec<&str> = entry_path
+                .split('/')
+                .filter(|p| !p.is_empty() && *p != ".")
+                .collect();
+            if parts.contains(&"..") {
+                bail!(
+                    "{}: tar entry {} leaves the mount",
+                    path.display(),
+                    entry_path
+                );
+            }
+            for (i, part) in parts.iter().enumerate() {
+                let last = i + 1 == parts.len();
+                let existing = self
+                    .dir(dir)
+                    .map_err(|_| anyhow!("{} is not a directory", entry_path))?
+                    .get(*part)
+                    .copied();
+                dir = match existing {
+                    Some(inode) => inode,
+                    None if last && !is_dir => {
+                        let mut data = Vec::new();
+                        std::io::Read::read_to_end(&mut entry, &mut data)?;
+                        let inode = self.add(NodeKind::File(data));
+                        self.dir_mut(dir).unwrap().insert(part.to_string(), inode);
+                        break;
+                    }
+                    None => {
+                        let inode = self.add(NodeKind::Dir(BTreeMap::new()));
+                        self.dir_mut(dir).unwrap().insert(part.to_string(), inode);
+                        inode
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Write every mount under `dir`, at its guest path.
+    fn dump_mounts(&self, dir: &Path) -> Result<()> {
+        for mount in &self.mounts {
+            let out = dir.join(mount.guest.trim_start_matches('/'));
+            self.dump(mount.root, &out, &mount.guest)?;
+        }
+        Ok(())
+    }
+
+    fn dump(&self, inode: usize, out: &Path, guest: &str) -> Result<()> {
+        match &self.nodes[inode].kind {
+            NodeKind::File(data) => {
+                std::fs::write(out, data)
+                    .with_context(|| format!("failed to write {}", out.display()))?;
+                println!("  {} ({} bytes)", guest, data.len());
+            }
+            NodeKind::Dir(entries) => {
+                std::fs::create_dir_all(out)?;
+                for (name, child) in entries {
+                    self.dump(*child, &out.join(name), &format!("{}/{}", guest, name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn with_fs<R>(f: impl FnOnce(&mut MemFs) -> R) -> R {
+    f(MEMFS.lock().unwrap().as_mut().expect("memfs not enabled"))
+}
+
+/// Build the tree from `--memfs` mounts, if any were given.
+pub fn enable() -> Result<()> {
+    let opts = host::options();
+    if opts.memfs.is_empty() {
+        return Ok(());
+    }
+    let mut memfs = MemFs::new(&opts.memfs, opts.memfs_fail.clone())?;
+    memfs.max_size = opts.memfs_max_size.unwrap_or(DEFAULT_MAX_SIZE);
+    *MEMFS.lock().unwrap() = Some(memfs);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    MEMFS.lock().unwrap().is_some()
+}
+
+/// Write the tree to `--memfs-dump`, one directory per mount point.
+pub fn finish() -> Result<()> {
+    let (Some(dir), Some(memfs)) = (&host::options().memfs_dump, MEMFS.lock().unwrap().take())
+    else {
+        return Ok(());
+    };
+    println!("\nmemfs dump -> {}", dir);
+    memfs.dump_mounts(Path::new(dir))
+}
+
+/// Replace the `wasi:filesystem` interfaces already in `linker` with memfs.
+pub fn add_to_linker(linker: &mut Linker<HostState>) -> Result<()> {
+    fn view(state: &mut HostState) -> MemFsView<'_> {
+        MemFsView {
+            table: &mut state.table,
+        }
+    }
+    linker.allow_shadowing(true);
+    fs::add_to_linker_get_host(linker, view)?;
+    preopens::add_to_linker_get_host(linker, view)?;
+    linker.allow_shadowing(false);
+    Ok(())
+}
+
+/// What a `descriptor` resource holds under memfs.
+struct MemDescriptor {
+    inode: usize,
+    /// Guest path, for `--memfs-fail` matching.
+    path: String,
+    flags: fs::DescriptorFlags,
+    read_only: bool,
+}
+
+struct MemDirStream(VecDeque<fs::DirectoryEntry>);
+
+struct MemFsView<'a> {
+    table: &'a mut ResourceTable,
+}
+
+impl MemFsView<'_> {
+    fn desc(&self, fd: &Resource<fs::Descriptor>) -> FsResult<&MemDescriptor> {
+        Ok(self
+            .table
+            .get(&Resource::<MemDescriptor>::new_borrow(fd.rep()))?)
+    }
+
+    fn push(&mut self, desc: MemDescriptor) -> FsResult<Resource<fs::Descriptor>> {
+        Ok(Resource::new_own(self.table.push(desc)?.rep()))
+    }
+
+    /// The descriptor, checked to allow changes to the tree under it.
+    fn mutable(&self, fd: &Resource<fs::Descriptor>) -> FsResult<&MemDescriptor> {
+        let desc = self.desc(fd)?;
+        if desc.read_only || !desc.flags.contains(fs::DescriptorFlags::MUTATE_DIRECTORY) {
+            return Err(ErrorCode::ReadOnly.into());
+        }
+        Ok(desc)
+    }
+}
+
+fn join(dir: &str, path: &str) -> String {
+    format!("{}/{}", dir, path.trim_start_matches("./"))
+}
+
+fn stat_of(memfs: &MemFs, inode: usize) -> fs::DescriptorStat {
+    let node = &memfs.nodes[inode];
+    let (type_, size) = match &node.kind {
+        NodeKind::File(data) => (fs::DescriptorType::RegularFile, data.len() as u64),
+        NodeKind::Dir(_) => (fs::DescriptorType::Directory, 0),
+    };
+    fs::DescriptorStat {
+        type_,
+        link_count: 1,
+        size,
+        data_access_timestamp: node.access,
+        data_modification_timestamp: node.modification,
+        status_change_timestamp: node.modification,
+    }
+}
+
+fn set_time(slot: &mut Option<fs::Datetime>, ts: fs::NewTimestamp) {
+    match ts {
+        fs::NewTimestamp::NoChange => {}
+        fs::NewTimestamp::Now => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            *slot = Some(fs::Datetime {
+                seconds: now.as_secs(),
+                nanoseconds: now.subsec_nanos(),
+            });
+        }
+        fs::NewTimestamp::Timestamp(t) => *slot = Some(t),
+    }
+}
+
+impl preopens::Host for MemFsView<'_> {
+    fn get_directories(&mut self) -> Result<Vec<(Resource<fs::Descriptor>, String)>> {
+        let mounts: Vec<_> = with_fs(|memfs| {
+            memfs
+                .mounts
+                .iter()
+                .map(|m| (m.root, m.guest.clone(), m.read_only))
+                .collect()
+        });
+        let mut dirs = Vec::new();
+        for (inode, guest, read_only) in mounts {
+            let mut flags = fs::DescriptorFlags::READ;
+            if !read_only {
+                flags |= fs::DescriptorFlags::MUTATE_DIRECTORY;
+            }
+            let fd = self
+                .push(MemDescriptor {
+                    inode,
+                    path: guest.clone(),
+                    flags,
+                    read_only,
+                })
+                .map_err(|e| anyhow!("failed to push preopen {}: {:?}", guest, e))?;
+            dirs.push((fd, guest));
+        }
+        Ok(dirs)
+    }
+}
+
+impl fs::Host for MemFsView<'_> {
+    fn convert_error_code(&mut self, err: FsError) -> Result<fs::ErrorCode> {
+        Ok(err.downcast()?.into())
+    }
+
+    fn filesystem_error_code(
+        &mut self,
+        err: Resource<streams::Error>,
+    ) -> Result<Option<fs::ErrorCode>> {
+        let err = self.table.get(&err)?;
+        Ok(err.downcast_ref::<ErrorCode>().map(|code| (*code).into()))
+    }
+}
+
+impl fs::HostDescriptor for MemFsView<'_> {
+    fn read_via_stream(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        offset: fs::Filesize,
+    ) -> FsResult<Resource<streams::InputStream>> {
+        let desc = self.desc(&fd)?;
+        if !desc.flags.contains(fs::DescriptorFlags::READ) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let reader = MemReader {
+            inode: desc.inode,
+            path: desc.path.clone(),
+            offset,
+        };
+        let stream: streams::InputStream = Box::new(reader);
+        Ok(self.table.push(stream)?)
+    }
+
+    fn write_via_stream(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        offset: fs::Filesize,
+    ) -> FsResult<Resource<streams::OutputStream>> {
+        let desc = self.desc(&fd)?;
+        if !desc.flags.contains(fs::DescriptorFlags::WRITE) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let writer = MemWriter {
+            inode: desc.inode,
+            path: desc.path.clone(),
+            offset: Some(offset),
+        };
+        let stream: streams::OutputStream = Box::new(writer);
+        Ok(self.table.push(stream)?)
+    }
+
+    fn append_via_stream(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+    ) -> FsResult<Resource<streams::OutputStream>> {
+        let desc = self.desc(&fd)?;
+        if !desc.flags.contains(fs::DescriptorFlags::WRITE) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let writer = MemWriter {
+            inode: desc.inode,
+            path: desc.path.clone(),
+            offset: None,
+        };
+        let stream: streams::OutputStream = Box::new(writer);
+        Ok(self.table.push(stream)?)
+    }
+
+    fn advise(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        _offset: fs::Filesize,
+        _len: fs::Filesize,
+        _advice: fs::Advice,
+    ) -> FsResult<()> {
+        self.desc(&fd)?;
+        Ok(())
+    }
+
+    fn sync_data(&mut self, fd: Resource<fs::Descriptor>) -> FsResult<()> {
+        self.desc(&fd)?;
+        Ok(())
+    }
+
+    fn get_flags(&mut self, fd: Resource<fs::Descriptor>) -> FsResult<fs::DescriptorFlags> {
+        Ok(self.desc(&fd)?.flags)
+    }
+
+    fn get_type(&mut self, fd: Resource<fs::Descriptor>) -> FsResult<fs::DescriptorType> {
+        let inode = self.desc(&fd)?.inode;
+        Ok(with_fs(|memfs| stat_of(memfs, inode).type_))
+    }
+
+    fn set_size(&mut self, fd: Resource<fs::Descriptor>, size: fs::Filesize) -> FsResult<()> {
+        let desc = self.desc(&fd)?;
+        if !desc.flags.contains(fs::DescriptorFlags::WRITE) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let (inode, path) = (desc.inode, desc.path.clone());
+        with_fs(|memfs| {
+            memfs.check(Op::Write, &path)?;
+            if size > memfs.max_size {
+                return Err(ErrorCode::FileTooLarge.into());
+            }
+            memfs.file_mut(inode)?.resize(size as usize, 0);
+            Ok(())
+        })
+    }
+
+    fn set_times(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        atim: fs::NewTimestamp,
+        mtim: fs::NewTimestamp,
+    ) -> FsResult<()> {
+        let desc = self.desc(&fd)?;
+        if desc.read_only {
+            return Err(ErrorCode::ReadOnly.into());
+        }
+        let inode = desc.inode;
+        with_fs(|memfs| {
+            let node = &mut memfs.nodes[inode];
+            set_time(&mut node.access, atim);
+            set_time(&mut node.modification, mtim);
+        });
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        len: fs::Filesize,
+        offset: fs::Filesize,
+    ) -> FsResult<(Vec<u8>, bool)> {
+        let desc = self.desc(&fd)?;
+        if !desc.flags.contains(fs::DescriptorFlags::READ) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let (inode, path) = (desc.inode, desc.path.clone());
+        with_fs(|memfs| {
+            memfs.check(Op::Read, &path)?;
+            let data = memfs.file_mut(inode)?;
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(len as usize).min(data.len());
+            Ok((data[start..end].to_vec(), end == data.len()))
+        })
+    }
+
+    fn write(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        buf: Vec<u8>,
+        offset: fs::Filesize,
+    ) -> FsResult<fs::Filesize> {
+        let desc = self.desc(&fd)?;
+        if !desc.flags.contains(fs::DescriptorFlags::WRITE) {
+            return Err(ErrorCode::BadDescriptor.into());
+        }
+        let (inode, path) = (desc.inode, desc.path.clone());
+        with_fs(|memfs| {
+            memfs.check(Op::Write, &path)?;
+            let max_size = memfs.max_size;
+            write_at(memfs.file_mut(inode)?, offset, &buf, max_size)?;
+            Ok(buf.len() as u64)
+        })
+    }
+
+    fn read_directory(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+    ) -> FsResult<Resource<fs::DirectoryEntryStream>> {
+        let desc = self.desc(&fd)?;
+        let (inode, path) = (desc.inode, desc.path.clone());
+        let entries = with_fs(|memfs| -> FsResult<VecDeque<fs::DirectoryEntry>> {
+            memfs.check(Op::Readdir, &path)?;
+            Ok(memfs
+                .dir(inode)?
+                .iter()
+                .map(|(name, child)| fs::DirectoryEntry {
+                    type_: stat_of(memfs, *child).type_,
+                    name: name.clone(),
+                })
+                .collect())
+        })?;
+        Ok(Resource::new_own(
+            self.table.push(MemDirStream(entries))?.rep(),
+        ))
+    }
+
+    fn sync(&mut self, fd: Resource<fs::Descriptor>) -> FsResult<()> {
+        self.desc(&fd)?;
+        Ok(())
+    }
+
+    fn create_directory_at(&mut self, fd: Resource<fs::Descriptor>, path: String) -> FsResult<()> {
+        let desc = self.mutable(&fd)?;
+        let (inode, full) = (desc.inode, join(&desc.path, &path));
+        with_fs(|memfs| {
+            memfs.check(Op::Mkdir, &full)?;
+            let (parent, name) = memfs.parent(inode, &path)?;
+            let name = name.ok_or(ErrorCode::Exist)?;
+            if memfs.dir(parent)?.contains_key(name) {
+                return Err(ErrorCode::Exist.into());
+            }
+            let child = memfs.add(NodeKind::Dir(BTreeMap::new()));
+            memfs.dir_mut(parent)?.insert(name.to_string(), child);
+            Ok(())
+        })
+    }
+
+    fn stat(&mut self, fd: Resource<fs::Descriptor>) -> FsResult<fs::DescriptorStat> {
+        let desc = self.desc(&fd)?;
+        let (inode, path) = (desc.inode, desc.path.clone());
+        with_fs(|memfs| {
+            memfs.check(Op::Stat, &path)?;
+            Ok(stat_of(memfs, inode))
+        })
+    }
+
+    fn stat_at(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        _path_flags: fs::PathFlags,
+        path: String,
+    ) -> FsResult<fs::DescriptorStat> {
+        let desc = self.desc(&fd)?;
+        let (inode, full) = (desc.inode, join(&desc.path, &path));
+        with_fs(|memfs| {
+            memfs.check(Op::Stat, &full)?;
+            Ok(stat_of(memfs, memfs.lookup(inode, &path)?))
+        })
+    }
+
+    fn set_times_at(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        _path_flags: fs::PathFlags,
+        path: String,
+        atim: fs::NewTimestamp,
+        mtim: fs::NewTimestamp,
+    ) -> FsResult<()> {
+        let inode = self.mutable(&fd)?.inode;
+        with_fs(|memfs| {
+            let target = memfs.lookup(inode, &path)?;
+            let node = &mut memfs.nodes[target];
+            set_time(&mut node.access, atim);
+            set_time(&mut node.modification, mtim);
+            Ok(())
+        })
+    }
+
+    fn link_at(
+        &mut self,
+        _fd: Resource<fs::Descriptor>,
+        _old_path_flags: fs::PathFlags,
+        _old_path: String,
+        _new_descriptor: Resource<fs::Descriptor>,
+        _new_path: String,
+    ) -> FsResult<()> {
+        Err(ErrorCode::Unsupported.into())
+    }
+
+    fn open_at(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        _path_flags: fs::PathFlags,
+        path: String,
+        oflags: fs::OpenFlags,
+        flags: fs::DescriptorFlags,
+    ) -> FsResult<Resource<fs::Descriptor>> {
+        let desc = self.desc(&fd)?;
+        let (dir, full, read_only) = (desc.inode, join(&desc.path, &path), desc.read_only);
+        let creating = oflags.intersects(fs::OpenFlags::CREATE | fs::OpenFlags::TRUNCATE);
+        if read_only
+            && (creating
+                || flags
+                    .intersects(fs::DescriptorFlags::WRITE | fs::DescriptorFlags::MUTATE_DIRECTORY))
+        {
+            return Err(ErrorCode::ReadOnly.into());
+        }
+        if creating && !desc.flags.contains(fs::DescriptorFlags::MUTATE_DIRECTORY) {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let inode = with_fs(|memfs| -> FsResult<usize> {
+            memfs.check(Op::Open, &full)?;
+            let (parent, name) = memfs.parent(dir, &path)?;
+            let existing = match name {
+                Some(name) => memfs.dir(parent)?.get(name).copied(),
+                None => Some(parent),
+            };
+            let inode = match existing {
+                Some(_) if oflags.contains(fs::OpenFlags::CREATE | fs::OpenFlags::EXCLUSIVE) => {
+                    return Err(ErrorCode::Exist.into())
+                }
+                Some(inode) => inode,
+                None if oflags.contains(fs::OpenFlags::CREATE) => {
+                    let inode = memfs.add(NodeKind::File(Vec::new()));
+                    memfs
+                        .dir_mut(parent)?
+                        .insert(name.unwrap().to_string(), inode);
+                    inode
+                }
+                None => return Err(ErrorCode::NoEntry.into()),
+            };
+            let is_dir = matches!(memfs.nodes[inode].kind, NodeKind::Dir(_));
+            if oflags.contains(fs::OpenFlags::DIRECTORY) && !is_dir {
+                return Err(ErrorCode::NotDirectory.into());
+            }
+            if is_dir && flags.contains(fs::DescriptorFlags::WRITE) {
+                return Err(ErrorCode::IsDirectory.into());
+            }
+            if oflags.contains(fs::OpenFlags::TRUNCATE) {
+                memfs.file_mut(inode)?.clear();
+            }
+            Ok(inode)
+        })?;
+        self.push(MemDescriptor {
+            inode,
+            path: full,
+            flags,
+            read_only,
+        })
+    }
+
+    fn drop(&mut self, fd: Resource<fs::Descriptor>) -> Result<()> {
+        self.table
+            .delete(Resource::<MemDescriptor>::new_own(fd.rep()))?;
+        Ok(())
+    }
+
+    fn readlink_at(&mut self, fd: Resource<fs::Descriptor>, path: String) -> FsResult<String> {
+        let inode = self.desc(&fd)?.inode;
+        with_fs(|memfs| memfs.lookup(inode, &path))?;
+        // memfs has no symlinks, so every existing path is "not a link".
+        Err(ErrorCode::Invalid.into())
+    }
+
+    fn remove_directory_at(&mut self, fd: Resource<fs::Descriptor>, path: String) -> FsResult<()> {
+        let desc = self.mutable(&fd)?;
+        let (inode, full) = (desc.inode, join(&desc.path, &path));
+        with_fs(|memfs| {
+            memfs.check(Op::Remove, &full)?;
+            let (parent, name) = memfs.parent(inode, &path)?;
+            let name = name.ok_or(ErrorCode::Busy)?;
+            let target = *memfs.dir(parent)?.get(name).ok_or(ErrorCode::NoEntry)?;
+            if !memfs.dir(target)?.is_empty() {
+                return Err(ErrorCode::NotEmpty.into());
+            }
+            memfs.dir_mut(parent)?.remove(name);
+            Ok(())
+        })
+    }
+
+    fn rename_at(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        old_path: String,
+        new_fd: Resource<fs::Descriptor>,
+        new_path: String,
+    ) -> FsResult<()> {
+        let old = self.mutable(&fd)?;
+        let (old_dir, old_full) = (old.inode, join(&old.path, &old_path));
+        let new_dir = self.mutable(&new_fd)?.inode;
+        with_fs(|memfs| {
+            memfs.check(Op::Rename, &old_full)?;
+            let (from, old_name) = memfs.parent(old_dir, &old_path)?;
+            let (to, new_name) = memfs.parent(new_dir, &new_path)?;
+            let (old_name, new_name) = (
+                old_name.ok_or(ErrorCode::Busy)?,
+                new_name.ok_or(ErrorCode::Busy)?,
+            );
+            let inode = *memfs.dir(from)?.get(old_name).ok_or(ErrorCode::NoEntry)?;
+            // A directory cannot be moved into itself or below itself.
+            if memfs.contains(inode, to) {
+                return Err(ErrorCode::Invalid.into());
+            }
+            // An existing target is replaced as POSIX `rename` does: only by
+            // the same kind of node, and a directory only while empty.
+            if let Some(&target) = memfs.dir(to)?.get(new_name) {
+                if target == inode {
+                    return Ok(());
+                }
+                match (&memfs.nodes[inode].kind, &memfs.nodes[target].kind) {
+                    (NodeKind::File(_), NodeKind::Dir(_)) => {
+                        return Err(ErrorCode::IsDirectory.into())
+                    }
+                    (NodeKind::Dir(_), NodeKind::File(_)) => {
+                        return Err(ErrorCode::NotDirectory.into())
+                    }
+                    (NodeKind::Dir(_), NodeKind::Dir(entries)) if !entries.is_empty() => {
+                        return Err(ErrorCode::NotEmpty.into())
+                    }
+                    _ => {}
+                }
+            }
+            memfs.dir_mut(from)?.remove(old_name);
+            memfs.dir_mut(to)?.insert(new_name.to_string(), inode);
+            Ok(())
+        })
+    }
+
+    fn symlink_at(
+        &mut self,
+        _fd: Resource<fs::Descriptor>,
+        _src_path: String,
+        _dest_path: String,
+    ) -> FsResult<()> {
+        Err(ErrorCode::Unsupported.into())
+    }
+
+    fn unlink_file_at(&mut self, fd: Resource<fs::Descriptor>, path: String) -> FsResult<()> {
+        let desc = self.mutable(&fd)?;
+        let (inode, full) = (desc.inode, join(&desc.path, &path));
+        with_fs(|memfs| {
+            memfs.check(Op::Remove, &full)?;
+            let (parent, name) = memfs.parent(inode, &path)?;
+            let name = name.ok_or(ErrorCode::IsDirectory)?;
+            let target = *memfs.dir(parent)?.get(name).ok_or(ErrorCode::NoEntry)?;
+            if matches!(memfs.nodes[target].kind, NodeKind::Dir(_)) {
+                return Err(ErrorCode::IsDirectory.into());
+            }
+            memfs.dir_mut(parent)?.remove(name);
+            Ok(())
+        })
+    }
+
+    fn is_same_object(
+        &mut self,
+        a: Resource<fs::Descriptor>,
+        b: Resource<fs::Descriptor>,
+    ) -> Result<bool> {
+        let a = self.desc(&a).map_err(|_| anyhow!("bad descriptor"))?.inode;
+        let b = self.desc(&b).map_err(|_| anyhow!("bad descriptor"))?.inode;
+        Ok(a == b)
+    }
+
+    fn metadata_hash(&mut self, fd: Resource<fs::Descriptor>) -> FsResult<fs::MetadataHashValue> {
+        let inode = self.desc(&fd)?.inode;
+        Ok(fs::MetadataHashValue {
+            lower: inode as u64,
+            upper: 0,
+        })
+    }
+
+    fn metadata_hash_at(
+        &mut self,
+        fd: Resource<fs::Descriptor>,
+        _path_flags: fs::PathFlags,
+        path: String,
+    ) -> FsResult<fs::MetadataHashValue> {
+        let inode = self.desc(&fd)?.inode;
+        let target = with_fs(|memfs| memfs.lookup(inode, &path))?;
+        Ok(fs::MetadataHashValue {
+            lower: target as u64,
+            upper: 0,
+        })
+    }
+}
+
+impl fs::HostDirectoryEntryStream for MemFsView<'_> {
+    fn read_directory_entry(
+        &mut self,
+        stream: Resource<fs::DirectoryEntryStream>,
+    ) -> FsResult<Option<fs::DirectoryEntry>> {
+        let stream = self
+            .table
+            .get_mut(&Resource::<MemDirStream>::new_borrow(stream.rep()))?;
+        Ok(stream.0.pop_front())
+    }
+
+    fn drop(&mut self, stream: Resource<fs::DirectoryEntryStream>) -> Result<()> {
+        self.table
+            .delete(Resource::<MemDirStream>::new_own(stream.rep()))?;
+        Ok(())
+    }
+}
+
+/// Write `bytes` at `offset`, growing the file with zeros as needed, but not
+/// past `max_size`.
+fn write_at(data: &mut Vec<u8>, offset: u64, bytes: &[u8], max_size: u64) -> Result<(), ErrorCode> {
+    let end = offset
+        .checked_add(bytes.len() as u64)
+        .ok_or(ErrorCode::Overflow)?;
+    if end > max_size {
+        return Err(ErrorCode::FileTooLarge);
+    }
+    let (offset, end) = (offset as usize, end as usize);
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    data[offset..end].copy_from_slice(bytes);
+    Ok(())
+}
+
+fn stream_error(code: ErrorCode) -> StreamError {
+    StreamError::LastOperationFailed(anyhow::Error::from(code))
+}
+
+/// `read-via-stream`: reads the file's current contents at each call.
+struct MemReader {
+    inode: usize,
+    path: String,
+    offset: u64,
+}
+
+#[wasmtime_wasi::async_trait]
+impl Subscribe for MemReader {
+    async fn ready(&mut self) {}
+}
+
+impl HostInputStream for MemReader {
+    fn read(&mut self, size: usize) -> StreamResult<bytes::Bytes> {
+        let chunk = with_fs(|memfs| {
+            if let Some(f) = memfs
+                .failures
+                .iter()
+                .find(|f| f.matches(Op::Read, &self.path))
+            {
+                return Err(stream_error(f.code));
+            }
+            let data = memfs
+                .file_mut(self.inode)
+                .map_err(|_| stream_error(ErrorCode::IsDirectory))?;
+            let start = (self.offset as usize).min(data.len());
+            let end = start.saturating_add(size).min(data.len());
+            Ok(data[start..end].to_vec())
+        })?;
+        if chunk.is_empty() && size > 0 {
+            return Err(StreamError::Closed);
+        }
+        self.offset += chunk.len() as u64;
+        Ok(chunk.into())
+    }
+}
+
+/// `write-via-stream` and `append-via-stream` (`offset: None`).
+struct MemWriter {
+    inode: usize,
+    path: String,
+    offset: Option<u64>,
+}
+
+#[wasmtime_wasi::async_trait]
+impl Subscribe for MemWriter {
+    async fn ready(&mut self) {}
+}
+
+impl HostOutputStream for MemWriter {
+    fn write(&mut self, bytes: bytes::Bytes) -> StreamResult<()> {
+        with_fs(|memfs| {
+            if let Some(f) = memfs
+                .failures
+                .iter()
+                .find(|f| f.matches(Op::Write, &self.path))
+            {
+                return Err(stream_error(f.code));
+            }
+            let max_size = memfs.max_size;
+            let data = memfs
+                .file_mut(self.inode)
+                .map_err(|_| stream_error(ErrorCode::IsDirectory))?;
+            let offset = self.offset.unwrap_or(data.len() as u64);
+            write_at(data, offset, &bytes, max_size).map_err(stream_error)?;
+            if let Some(o) = &mut self.offset {
+                *o += bytes.len() as u64;
+            }
+            Ok(())
+        })
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(WRITE_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::HostDescriptor as _;
+    use preopens::Host as _;
+    use std::path::PathBuf;
+
+    /// The tree is process-wide, so tests that install one take turns.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    /// A fresh scratch directory under the system temp dir.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("memfs-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// `a.txt` and `sub/b.txt` under `dir`.
+    fn seed(dir: &Path) {
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.txt"), "alpha").unwrap();
+        std::fs::write(dir.join("sub/b.txt"), "beta").unwrap();
+    }
+
+    fn mount(spec: &str, read_only: bool) -> MountSpec {
+        MountSpec::parse(spec, read_only).unwrap()
+    }
+
+    /// Every file and directory below `inode`, by path; directories map to `None`.
+    fn tree(memfs: &MemFs, inode: usize) -> BTreeMap<String, Option<Vec<u8>>> {
+        fn walk(
+            memfs: &MemFs,
+            inode: usize,
+            prefix: &str,
+            out: &mut BTreeMap<String, Option<Vec<u8>>>,
+        ) {
+            for (name, &child) in memfs.dir(inode).unwrap() {
+                let path = format!("{}{}", prefix, name);
+                match &memfs.nodes[child].kind {
+                    NodeKind::File(data) => {
+                        out.insert(path, Some(data.clone()));
+                    }
+                    NodeKind::Dir(_) => {
+                        out.insert(path.clone(), None);
+                        walk(memfs, child, &format!("{}/", path), out);
+                    }
+                }
+            }
+        }
+        let mut out = BTreeMap::new();
+        walk(memfs, inode, "", &mut out);
+        out
+    }
+
+    fn code<T>(result: FsResult<T>) -> ErrorCode {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.downcast().unwrap(),
+        }
+    }
+
+    fn borrow(fd: &Resource<fs::Descriptor>) -> Resource<fs::Descriptor> {
+        Resource::new_borrow(fd.rep())
+    }
+
+    /// Install `memfs` as the process tree and run `f` with its preopens.
+    fn with_mounts(memfs: MemFs, f: impl FnOnce(&mut MemFsView, Vec<Resource<fs::Descriptor>>)) {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        *MEMFS.lock().unwrap() = Some(memfs);
+        let mut table = ResourceTable::new();
+        let mut view = MemFsView { table: &mut table };
+        let preopens = view.get_directories().unwrap();
+        f(&mut view, preopens.into_iter().map(|(fd, _)| fd).collect());
+        *MEMFS.lock().unwrap() = None;
+    }
+
+    fn create(
+        view: &mut MemFsView,
+        dir: &Resource<fs::Descriptor>,
+        path: &str,
+    ) -> FsResult<Resource<fs::Descriptor>> {
+        view.open_at(
+            borrow(dir),
+            fs::PathFlags::empty(),
+            path.to_string(),
+            fs::OpenFlags::CREATE,
+            fs::DescriptorFlags::READ | fs::DescriptorFlags::WRITE,
+        )
+    }
+
+    #[test]
+    fn seeds_mounts_from_dir_and_tar() {
+        let dir = scratch("seed");
+        let src = dir.join("src");
+        seed(&src);
+        let tar_path = dir.join("seed.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(&tar_path).unwrap());
+        builder.append_dir_all(".", &src).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let memfs = MemFs::new(
+            &[
+                mount(&format!("/dir={}", src.display()), false),
+                mount(&format!("/tar={}", tar_path.display()), false),
+                mount("/empty", false),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        let expected = BTreeMap::from([
+            ("a.txt".to_string(), Some(b"alpha".to_vec())),
+            ("sub".to_string(), None),
+            ("sub/b.txt".to_string(), Some(b"beta".to_vec())),
+        ]);
+        assert_eq!(tree(&memfs, memfs.mounts[0].root), expected);
+        assert_eq!(tree(&memfs, memfs.mounts[1].root), expected);
+        assert!(tree(&memfs, memfs.mounts[2].root).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_only_mount_rejects_writes() {
+        let dir = scratch("ro");
+        seed(&dir);
+        let memfs = MemFs::new(
+            &[mount(&format!("/ro={}", dir.display()), true)],
+            Vec::new(),
+        )
+        .unwrap();
+        with_mounts(memfs, |view, preopens| {
+            let ro = &preopens[0];
+            assert_eq!(code(create(view, ro, "new.txt")), ErrorCode::ReadOnly);
+            assert_eq!(code(create(view, ro, "a.txt")), ErrorCode::ReadOnly);
+            assert_eq!(
+                code(view.create_directory_at(borrow(ro), "d".to_string())),
+                ErrorCode::ReadOnly
+            );
+            assert_eq!(
+                code(view.unlink_file_at(borrow(ro), "a.txt".to_string())),
+                ErrorCode::ReadOnly
+            );
+            assert_eq!(
+                code(view.rename_at(
+                    borrow(ro),
+                    "a.txt".to_string(),
+                    borrow(ro),
+                    "b.txt".to_string()
+                )),
+                ErrorCode::ReadOnly
+            );
+            let file = view
+                .open_at(
+                    borrow(ro),
+                    fs::PathFlags::empty(),
+                    "a.txt".to_string(),
+                    fs::OpenFlags::empty(),
+                    fs::DescriptorFlags::READ,
+                )
+                .unwrap();
+            assert_eq!(view.read(file, 16, 0).unwrap(), (b"alpha".to_vec(), true));
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn injected_failures_hit_only_their_op_and_path() {
+        let failures = vec![
+            Failure::parse("write:/out/full.txt=insufficient-space").unwrap(),
+            Failure::parse("open:/out/locked/*=access").unwrap(),
+        ];
+        let memfs = MemFs::new(&[mount("/out", false)], failures).unwrap();
+        with_mounts(memfs, |view, preopens| {
+            let out = &preopens[0];
+            let full = create(view, out, "full.txt").unwrap();
+            assert_eq!(
+                code(view.write(borrow(&full), b"data".to_vec(), 0)),
+                ErrorCode::InsufficientSpace
+            );
+            assert_eq!(view.read(full, 16, 0).unwrap(), (Vec::new(), true));
+
+            let fine = create(view, out, "fine.txt").unwrap();
+            assert_eq!(view.write(fine, b"data".to_vec(), 0).unwrap(), 4);
+
+            view.create_directory_at(borrow(out), "locked".to_string())
+                .unwrap();
+            assert_eq!(code(create(view, out, "locked/x.txt")), ErrorCode::Access);
+        });
+    }
+
+    #[test]
+    fn file_sizes_are_checked_and_capped() {
+        let mut memfs = MemFs::new(&[mount("/out", false)], Vec::new()).unwrap();
+        memfs.max_size = 8;
+        with_mounts(memfs, |view, preopens| {
+            let file = create(view, &preopens[0], "f.txt").unwrap();
+            assert_eq!(
+                code(view.write(borrow(&file), b"data".to_vec(), u64::MAX)),
+                ErrorCode::Overflow
+            );
+            assert_eq!(
+                code(view.write(borrow(&file), b"data".to_vec(), 5)),
+                ErrorCode::FileTooLarge
+            );
+            assert_eq!(view.write(borrow(&file), b"data".to_vec(), 4).unwrap(), 4);
+            assert_eq!(
+                code(view.set_size(borrow(&file), u64::MAX)),
+                ErrorCode::FileTooLarge
+            );
+            view.set_size(borrow(&file), 2).unwrap();
+            assert_eq!(view.read(file, 16, 0).unwrap(), (vec![0, 0], true));
+        });
+    }
+
+    #[test]
+    fn tar_seed_rejects_parent_components() {
+        let dir = scratch("dotdot");
+        let tar_path = dir.join("escape.tar");
+        let mut header = tar::Header::new_gnu();
+        // `set_path` refuses `..`, so write the name field directly.
+        let name = b"sub/../../escape.txt";
+        header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name);
+        header.set_size(1);
+        header.set_mode(0o644);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(std::fs::File::create(&tar_path).unwrap());
+        builder.append(&header, &b"x"[..]).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let spec = mount(&format!("/tar={}", tar_path.display()), false);
+        let err = MemFs::new(&[spec], Vec::new()).err().unwrap();
+        assert!(err.to_string().contains("leaves the mount"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rename_follows_posix() {
+        let dir = scratch("rename");
+        seed(&dir);
+        std::fs::create_dir_all(dir.join("empty")).unwrap();
+        let memfs = MemFs::new(
+            &[mount(&format!("/m={}", dir.display()), false)],
+            Vec::new(),
+        )
+        .unwrap();
+        with_mounts(memfs, |view, preopens| {
+            let m = &preopens[0];
+            let mut rename = |from: &str, to: &str| {
+                view.rename_at(borrow(m), from.to_string(), borrow(m), to.to_string())
+            };
+            assert_eq!(code(rename("sub", "sub/inner")), ErrorCode::Invalid);
+            assert_eq!(code(rename("a.txt", "sub")), ErrorCode::IsDirectory);
+            assert_eq!(code(rename("sub", "a.txt")), ErrorCode::NotDirectory);
+            assert_eq!(code(rename("empty", "sub")), ErrorCode::NotEmpty);
+            assert_eq!(code(rename("missing", "x")), ErrorCode::NoEntry);
+            rename("sub", "sub").unwrap();
+            rename("a.txt", "sub/b.txt").unwrap();
+            rename("sub", "empty").unwrap();
+            let after = with_fs(|memfs| tree(memfs, memfs.mounts[0].root));
+            assert_eq!(
+                after,
+                BTreeMap::from([
+                    ("empty".to_string(), None),
+                    ("empty/b.txt".to_string(), Some(b"alpha".to_vec())),
+                ])
+            );
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dump_round_trips() {
+        let dir = scratch("dump");
+        let src = dir.join("src");
+        seed(&src);
+        let mut memfs = MemFs::new(
+            &[
+                mount(&format!("/data={}", src.display()), false),
+                mount("/scratch", false),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        let written = memfs.add(NodeKind::File(b"written".to_vec()));
+        let scratch_root = memfs.mounts[1].root;
+        memfs
+            .dir_mut(scratch_root)
+            .unwrap()
+            .insert("new.txt".to_string(), written);
+        let out = dir.join("out");
+        memfs.dump_mounts(&out).unwrap();
+
+        let reloaded = MemFs::new(
+            &[
+                mount(&format!("/data={}", out.join("data").display()), false),
+                mount(
+                    &format!("/scratch={}", out.join("scratch").display()),
+                    false,
+                ),
+            ],
+            Vec::new(),
+        )
+        .unwrap();
+        for (a, b) in memfs.mounts.iter().zip(&reloaded.mounts) {
+            assert_eq!(tree(&memfs, a.root), tree(&reloaded, b.root));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
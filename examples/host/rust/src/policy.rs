@@ -0,0 +1,257 @@
+// Import policy (`--policy FILE`)
+//
+// Classifies a component's imports by capability and checks them against an
+// allow-list. With a policy in force, `audit` fails on any import the policy
+// does not allow, and the other modes link every disallowed import as a
+// function that traps when called, instead of the real WASI implementation.
+//
+//   # Capabilities the component may use.
+//   allow = ["stdio", "io", "clocks", "random", "env"]
+//   # Further interfaces by name, version optional (custom imports).
+//   imports = ["local:greet/greeter", "wasi:filesystem/preopens"]
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::sync::OnceLock;
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, Linker, ResourceType};
+use wasmtime::Engine;
+
+use crate::host::{self, HostState};
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Capability names accepted in `allow`.
+const CAPABILITIES: &[&str] = &[
+    "filesystem",
+    "network",
+    "clocks",
+    "random",
+    "env",
+    "stdio",
+    "io",
+    "keyvalue",
+    "config",
+    "logging",
+    "custom",
+];
+
+#[derive(Default)]
+pub struct Policy {
+    allow: Vec<String>,
+    imports: Vec<String>,
+}
+
+/// One import of a component, as `audit` lists it.
+pub struct Import {
+    /// `wasi:clocks/wall-clock@0.2.3`, or a bare name for world-level functions.
+    pub name: String,
+    pub capability: &'static str,
+    pub kind: ImportKind,
+}
+
+pub enum ImportKind {
+    /// An interface, with the functions and resources the component uses.
+    Instance {
+        funcs: Vec<String>,
+        resources: Vec<String>,
+    },
+    Func,
+    /// A type or resource imported at the world level.
+    Type,
+}
+
+/// Interface name without its `@version`.
+fn unversioned(name: &str) -> &str {
+    name.split_once('@').map_or(name, |(name, _)| name)
+}
+
+/// Capability an import needs; anything outside WASI is `custom`.
+pub fn capability(name: &str) -> &'static str {
+    let name = unversioned(name);
+    let (package, interface) = name.split_once('/').unwrap_or((name, ""));
+    match package {
+        "wasi:filesystem" => "filesystem",
+        "wasi:sockets" | "wasi:http" => "network",
+        "wasi:clocks" => "clocks",
+        "wasi:random" => "random",
+        "wasi:io" => "io",
+        "wasi:cli" => match interface {
+            "environment" | "exit" => "env",
+            _ => "stdio",
+        },
+        "wasi:keyvalue" => "keyvalue",
+        "wasi:config" => "config",
+        "wasi:logging" => "logging",
+        _ => "custom",
+    }
+}
+
+impl Policy {
+    fn load(path: &str) -> Result<Self> {
+        let table: toml::Table = std::fs::read_to_string(path)?.parse()?;
+        let mut policy = Policy::default();
+        for (key, value) in table {
+            let list = value
+                .as_array()
+                .and_then(|items| {
+                    items
+                        .iter()
+                        .map(|v| v.as_str().map(str::to_string))
+                        .collect()
+                })
+                .with_context(|| format!("{} must be a list of strings", key))?;
+            match key.as_str() {
+                "allow" => policy.allow = list,
+                "imports" => policy.imports = list,
+                _ => bail!("unknown policy key: {}", key),
+            }
+        }
+        if let Some(unknown) = policy
+            .allow
+            .iter()
+            .find(|c| !CAPABILITIES.contains(&c.as_str()))
+        {
+            bail!(
+                "unknown capability {:?} (expected one of {:?})",
+                unknown,
+                CAPABILITIES
+            );
+        }
+        Ok(policy)
+    }
+
+    pub fn allows(&self, name: &str) -> bool {
+        self.allow.iter().any(|c| c == capability(name))
+            || self
+                .imports
+                .iter()
+                .any(|allowed| allowed == name || allowed == unversioned(name))
+    }
+}
+
+/// Load `--policy`, if given.
+pub fn enable() -> Result<()> {
+    let Some(path) = &host::options().policy else {
+        return Ok(());
+    };
+    let policy = Policy::load(path).with_context(|| format!("invalid import policy {}", path))?;
+    let _ = POLICY.set(policy);
+    Ok(())
+}
+
+pub fn policy() -> Option<&'static Policy> {
+    POLICY.get()
+}
+
+/// Every import of `component`, in declaration order.
+pub fn imports(engine: &Engine, component: &Component) -> Vec<Import> {
+    component
+        .component_type()
+        .imports(engine)
+        .map(|(name, item)| {
+            let kind = match &item {
+                ComponentItem::ComponentInstance(instance) => {
+                    let mut funcs = Vec::new();
+                    let mut resources = Vec::new();
+                    for (name, item) in instance.exports(engine) {
+                        match item {
+                            ComponentItem::ComponentFunc(_) => funcs.push(name.to_string()),
+                            ComponentItem::Resource(_) => resources.push(name.to_string()),
+                            _ => {}
+                        }
+                    }
+                    ImportKind::Instance { funcs, resources }
+                }
+                ComponentItem::ComponentFunc(_) => ImportKind::Func,
+                _ => ImportKind::Type,
+            };
+            Import {
+                name: name.to_string(),
+                capability: capability(name),
+                kind,
+            }
+        })
+        .collect()
+}
+
+/// Stand-in for the resources of a trapping interface.
+struct Denied;
+
+/// Host type wasmtime-wasi links a WASI resource with. Interfaces re-export
+/// the resources they `use`, and those must keep their real type in a denied
+/// interface for the allowed ones to still type-check.
+fn wasi_resource(import: &str, resource: &str) -> Option<ResourceType> {
+    use wasmtime_wasi::bindings::{cli, filesystem, io, sockets};
+    if !import.starts_with("wasi:") {
+        return None;
+    }
+    Some(match resource {
+        "error" => ResourceType::host::<io::error::Error>(),
+        "pollable" => ResourceType::host::<io::poll::Pollable>(),
+        "input-stream" => ResourceType::host::<io::streams::InputStream>(),
+        "output-stream" => ResourceType::host::<io::streams::OutputStream>(),
+        "descriptor" => ResourceType::host::<filesystem::types::Descriptor>(),
+        "directory-entry-stream" => ResourceType::host::<filesystem::types::DirectoryEntryStream>(),
+        "network" => ResourceType::host::<sockets::network::Network>(),
+        "tcp-socket" => ResourceType::host::<sockets::tcp::TcpSocket>(),
+        "udp-socket" => ResourceType::host::<sockets::udp::UdpSocket>(),
+        "incoming-datagram-stream" => ResourceType::host::<sockets::udp::IncomingDatagramStream>(),
+        "outgoing-datagram-stream" => ResourceType::host::<sockets::udp::OutgoingDatagramStream>(),
+        "resolve-address-stream" => {
+            ResourceType::host::<sockets::ip_name_lookup::ResolveAddressStream>()
+        }
+        "terminal-input" => ResourceType::host::<cli::terminal_input::TerminalInput>(),
+        "terminal-output" => ResourceType::host::<cli::terminal_output::TerminalOutput>(),
+        _ => return None,
+    })
+}
+
+/// Define `import` as functions that fail with `NAME#FUNC WHY` when called.
+/// Redefining an instance drops the real implementation, so the linker must
+/// allow shadowing.
+pub fn define_trapping(linker: &mut Linker<HostState>, import: &Import, why: &str) -> Result<()> {
+    match &import.kind {
+        ImportKind::Instance { funcs, resources } => {
+            let mut instance = linker.instance(&import.name)?;
+            for resource in resources {
+                let ty = wasi_resource(&import.name, resource)
+                    .unwrap_or_else(ResourceType::host::<Denied>);
+                instance.resource(resource, ty, |_, _| Ok(()))?;
+            }
+            for func in funcs {
+                let message = format!("{}#{} {}", import.name, func, why);
+                instance.func_new(func, move |_, _, _| Err(anyhow!("{}", message)))?;
+            }
+        }
+        ImportKind::Func => {
+            let message = format!("{} {}", import.name, why);
+            linker
+                .root()
+                .func_new(&import.name, move |_, _, _| Err(anyhow!("{}", message)))?;
+        }
+        ImportKind::Type => {}
+    }
+    Ok(())
+}
+
+/// Replace the imports the policy denies with trapping functions.
+pub fn apply(linker: &mut Linker<HostState>, engine: &Engine, component: &Component) -> Result<()> {
+    let Some(policy) = policy() else {
+        return Ok(());
+    };
+    let denied: Vec<Import> = imports(engine, component)
+        .into_iter()
+        .filter(|import| !policy.allows(&import.name))
+        .collect();
+    if denied.is_empty() {
+        return Ok(());
+    }
+    linker.allow_shadowing(true);
+    for import in &denied {
+        eprintln!("[policy] denied {} ({})", import.name, import.capability);
+        let why = format!("is denied by the import policy ({})", import.capability);
+        define_trapping(linker, import, &why)?;
+    }
+    linker.allow_shadowing(false);
+    Ok(())
+}
@@ -0,0 +1,292 @@
+// Sampling profiler for a single export
+//
+// The export runs in a loop while a ticker thread bumps the engine epoch. Each
+// epoch deadline captures a wasm backtrace and records it as one sample, so
+// samples land on function entries and loop headers. The output is the Firefox
+// profiler's processed format (open it at https://profiler.firefox.com). Frame
+// names come from the name section of the component's core modules, and
+// MoonBit `gen/cabi` helpers are put in their own category.
+//
+// This is not wasmtime's `GuestProfiler`: in wasmtime 29 it only symbolizes
+// frames of the core `Module`s passed to `GuestProfiler::new`, and a
+// component's inner core modules are not reachable through the public API,
+// so every guest frame of a component would be dropped from its samples. The
+// sampler here names frames from `WasmBacktrace` instead, which does see them.
+
+use anyhow::{bail, Context, Result};
+use fxprof_processed_profile::{
+    CategoryColor, CategoryPairHandle, CpuDelta, Frame, FrameFlags, FrameInfo, Profile,
+    ReferenceTimestamp, SamplingInterval, ThreadHandle, Timestamp,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wasmtime::component::Component;
+use wasmtime::{Engine, UpdateDeadline, WasmBacktrace};
+
+use crate::host;
+use crate::bridge;
+use crate::coverage;
+use crate::policy;
+use crate::vals;
+
+const DEFAULT_ITERATIONS: u32 = 10000;
+const DEFAULT_INTERVAL_US: u64 = 100;
+const TOP_FUNCTIONS: usize = 15;
+
+struct ProfileOptions {
+    component_path: String,
+    export: String,
+    args: Vec<String>,
+    iterations: u32,
+    interval: Duration,
+    output: String,
+}
+
+fn parse_options(args: &[String]) -> Result<ProfileOptions> {
+    let mut positional = Vec::new();
+    let mut iterations = DEFAULT_ITERATIONS;
+    let mut interval = Duration::from_micros(DEFAULT_INTERVAL_US);
+    let mut output = "profile.json".to_string();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--iterations" => {
+                iterations = iter.next().context("--iterations needs a value")?.parse()?
+            }
+            "--interval-us" => {
+                let us: u64 = iter.next().context("--interval-us needs a value")?.parse()?;
+                interval = Duration::from_micros(us.max(1));
+            }
+            "--output" | "-o" => output = iter.next().context("--output needs a value")?.clone(),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if positional.len() < 2 {
+        bail!("usage: rust-host profile <component> <interface#func> [args...] [--iterations N] [--interval-us N] [--output FILE]");
+    }
+    let component_path = positional.remove(0);
+    let export = positional.remove(0);
+    Ok(ProfileOptions {
+        component_path,
+        export,
+        args: positional,
+        iterations,
+        interval,
+        output,
+    })
+}
+
+struct Sampler {
+    profile: Profile,
+    thread: ThreadHandle,
+    start: Instant,
+    last: Instant,
+    user: CategoryPairHandle,
+    cabi: CategoryPairHandle,
+    self_samples: HashMap<String, u64>,
+    samples: u64,
+}
+
+impl Sampler {
+    fn new(product: &str, interval: Duration) -> Self {
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0);
+        let mut profile = Profile::new(
+            product,
+            ReferenceTimestamp::from_millis_since_unix_epoch(unix_ms),
+            SamplingInterval::from_nanos(interval.as_nanos() as u64),
+        );
+        let user = profile.add_category("MoonBit", CategoryColor::Blue).into();
+        let cabi = profile.add_category("cabi", CategoryColor::Orange).into();
+        let zero = Timestamp::from_nanos_since_reference(0);
+        let process = profile.add_process(product, std::process::id(), zero);
+        let thread = profile.add_thread(process, 0, zero, true);
+        let now = Instant::now();
+        Sampler {
+            profile,
+            thread,
+            start: now,
+            last: now,
+            user,
+            cabi,
+            self_samples: HashMap::new(),
+            samples: 0,
+        }
+    }
+
+    fn sample(&mut self, backtrace: &WasmBacktrace) {
+        let now = Instant::now();
+        let names: Vec<String> = backtrace.frames().iter().map(frame_name).collect();
+        if let Some(leaf) = names.first() {
+            *self.self_samples.entry(leaf.clone()).or_default() += 1;
+        }
+        // Backtraces list the newest frame first; the profile wants the root first.
+        let frames: Vec<FrameInfo> = names
+            .iter()
+            .rev()
+            .map(|name| FrameInfo {
+                frame: Frame::Label(self.profile.intern_string(name)),
+                category_pair: if is_cabi(name) { self.cabi } else { self.user },
+                flags: FrameFlags::empty(),
+            })
+            .collect();
+        self.profile.add_sample(
+            self.thread,
+            self.timestamp(now),
+            frames.into_iter(),
+            CpuDelta::from(now - self.last),
+            1,
+        );
+        self.last = now;
+        self.samples += 1;
+    }
+
+    fn timestamp(&self, at: Instant) -> Timestamp {
+        Timestamp::from_nanos_since_reference((at - self.start).as_nanos() as u64)
+    }
+
+    fn finish(mut self, output: &str) -> Result<HashMap<String, u64>> {
+        let end = self.timestamp(Instant::now());
+        self.profile.set_thread_end_time(self.thread, end);
+        let file = std::fs::File::create(output)
+            .with_context(|| format!("failed to create {}", output))?;
+        serde_json::to_writer(std::io::BufWriter::new(file), &self.profile)?;
+        Ok(self.self_samples)
+    }
+}
+
+fn frame_name(frame: &wasmtime::FrameInfo) -> String {
+    match frame.func_name() {
+        Some(name) => name.to_string(),
+        None => format!(
+            "{}!wasm-function[{}]",
+            frame.module().name().unwrap_or("<module>"),
+            frame.func_index()
+        ),
+    }
+}
+
+/// Canonical ABI helpers generated into `gen/cabi`, plus the `cabi_*` exports.
+fn is_cabi(name: &str) -> bool {
+    name.contains("gen/cabi.") || name.starts_with("cabi_")
+}
+
+pub fn run_profile(args: &[String]) -> Result<()> {
+    let opts = parse_options(args)?;
+
+    let mut config = host::component_config();
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config)?;
+
+    println!("Loading component: {}", opts.component_path);
+    let component = Component::from_file(&engine, &opts.component_path)?;
+    coverage::register(&engine, &opts.component_path, &component);
+    let mut linker = host::wasi_linker(&engine)?;
+    policy::apply(&mut linker, &engine, &component)?;
+    bridge::check(&linker, &engine, &component)?;
+    let mut store = host::wasi_store(&engine, host::wasi_builder().build());
+    // No deadline while instantiating; sampling starts with the loop below.
+    store.set_epoch_deadline(u64::MAX);
+
+    let instance = linker.instantiate(&mut store, &component)?;
+    let func = host::find_func(&instance, &mut store, &opts.export)?;
+    let params: Vec<_> = func.params(&store).iter().map(|(_, ty)| ty.clone()).collect();
+    let args = vals::parse_args(&params, &opts.args)?;
+    let mut results = func
+        .results(&store)
+        .iter()
+        .map(vals::default_val)
+        .collect::<Result<Vec<_>>>()?;
+
+    let sampler = Arc::new(Mutex::new(Sampler::new(&opts.component_path, opts.interval)));
+    {
+        let sampler = sampler.clone();
+        store.epoch_deadline_callback(move |ctx| {
+            let backtrace = WasmBacktrace::capture(&ctx);
+            sampler.lock().unwrap().sample(&backtrace);
+            Ok(UpdateDeadline::Continue(1))
+        });
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let ticker = {
+        let engine = engine.clone();
+        let stop = stop.clone();
+        let interval = opts.interval;
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                std::thread::sleep(interval);
+                engine.increment_epoch();
+            }
+        })
+    };
+
+    println!(
+        "Profiling {} x {} (interval: {}µs)",
+        opts.export,
+        opts.iterations,
+        opts.interval.as_micros()
+    );
+    let start = Instant::now();
+    store.set_epoch_deadline(1);
+    let run = (|| -> Result<()> {
+        for _ in 0..opts.iterations {
+            host::call(&func, &mut store, &opts.export, &args, &mut results)?;
+        }
+        Ok(())
+    })();
+    let elapsed = start.elapsed();
+    stop.store(true, Ordering::Relaxed);
+    ticker.join().unwrap();
+    run?;
+
+    // The store's callback still holds a reference to the sampler.
+    drop(store);
+    let sampler = Arc::try_unwrap(sampler)
+        .map_err(|_| anyhow::anyhow!("sampler still in use"))?
+        .into_inner()
+        .unwrap();
+    let total = sampler.samples;
+    let self_samples = sampler.finish(&opts.output)?;
+
+    println!("Last result: {}", results.iter().map(vals::format_val).collect::<Vec<_>>().join(", "));
+    println!(
+        "Elapsed: {:.2} ms, {} samples -> {}",
+        elapsed.as_secs_f64() * 1000.0,
+        total,
+        opts.output
+    );
+    print_summary(&self_samples, total);
+    Ok(())
+}
+
+fn print_summary(self_samples: &HashMap<String, u64>, total: u64) {
+    if total == 0 {
+        println!("No samples collected; try more --iterations or a smaller --interval-us");
+        return;
+    }
+    let pct = |n: u64| n as f64 * 100.0 / total as f64;
+    let cabi: u64 = self_samples
+        .iter()
+        .filter(|(name, _)| is_cabi(name))
+        .map(|(_, n)| n)
+        .sum();
+    println!("Self time: cabi {:.1}%, other {:.1}%", pct(cabi), pct(total - cabi));
+    if self_samples.keys().all(|name| name.contains("!wasm-function[")) {
+        println!("Note: no function names found; the core module was built without a name section (try a debug build)");
+    }
+
+    let mut top: Vec<_> = self_samples.iter().collect();
+    top.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    println!("\n{:>8} {:>7}  Function", "Samples", "Self%");
+    println!("{}", "-".repeat(80));
+    for (name, n) in top.into_iter().take(TOP_FUNCTIONS) {
+        println!("{:>8} {:>6.1}%  {}", n, pct(*n), name);
+    }
+}
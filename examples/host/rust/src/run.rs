@@ -0,0 +1,178 @@
+// Runner for wasi:cli/command components
+//
+// Instantiates the component with the given argv, environment, preopened
+// directories and stdin, calls `wasi:cli/run#run`, and reports the captured
+// stdout/stderr and the exit code. `--expect-stdout` / `--expect-exit` turn a
+// run into a golden-output test.
+
+use anyhow::{bail, Context, Result};
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, Func, Val};
+use wasmtime::{Engine, Store};
+use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::{DirPerms, FilePerms, I32Exit};
+
+use crate::bridge;
+use crate::coverage;
+use crate::host::{self, HostState};
+use crate::memfs;
+use crate::policy;
+
+/// Upper bound on captured output per stream.
+const OUTPUT_CAPACITY: usize = 16 * 1024 * 1024;
+
+struct RunOptions {
+    component_path: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    dirs: Vec<(String, String)>,
+    stdin: Option<String>,
+    expect_stdout: Option<String>,
+    expect_exit: Option<i32>,
+}
+
+fn parse_options(args: &[String]) -> Result<RunOptions> {
+    let mut component_path = None;
+    let mut opts = RunOptions {
+        component_path: String::new(),
+        args: Vec::new(),
+        env: Vec::new(),
+        dirs: Vec::new(),
+        stdin: None,
+        expect_stdout: None,
+        expect_exit: None,
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--arg" => opts.args.push(iter.next().context("--arg needs a value")?.clone()),
+            "--env" => {
+                let kv = iter.next().context("--env needs KEY=VALUE")?;
+                let (k, v) = kv.split_once('=').unwrap_or((kv, ""));
+                opts.env.push((k.to_string(), v.to_string()));
+            }
+            "--dir" => {
+                // HOST[::GUEST], as in `wasmtime run --dir`
+                let spec = iter.next().context("--dir needs a path")?;
+                let (host, guest) = spec.split_once("::").unwrap_or((spec, spec));
+                opts.dirs.push((host.to_string(), guest.to_string()));
+            }
+            "--stdin" => opts.stdin = Some(iter.next().context("--stdin needs a file")?.clone()),
+            "--expect-stdout" => {
+                opts.expect_stdout = Some(iter.next().context("--expect-stdout needs a file")?.clone())
+            }
+            "--expect-exit" => {
+                opts.expect_exit = Some(iter.next().context("--expect-exit needs a code")?.parse()?)
+            }
+            "--" => opts.args.extend(iter.by_ref().cloned()),
+            _ if component_path.is_none() => component_path = Some(arg.clone()),
+            _ => opts.args.push(arg.clone()),
+        }
+    }
+
+    opts.component_path = component_path.context(
+        "usage: rust-host run <component> [--arg A]... [--env K=V]... [--dir HOST[::GUEST]]... [--stdin FILE] [--expect-stdout FILE] [--expect-exit N] [-- args...]",
+    )?;
+    Ok(opts)
+}
+
+/// Name of the `wasi:cli/run` export, whatever 0.2.x version it carries.
+pub fn run_export(engine: &Engine, component: &Component) -> Result<String> {
+    component
+        .component_type()
+        .exports(engine)
+        .find_map(|(name, item)| match item {
+            ComponentItem::ComponentInstance(_) if name.starts_with("wasi:cli/run@") => {
+                Some(name.to_string())
+            }
+            _ => None,
+        })
+        .context("component does not export wasi:cli/run")
+}
+
+/// Call `wasi:cli/run#run` and turn its outcome into a process exit code:
+/// `ok` is 0, `err` is 1, and `exit(N)` is N.
+pub fn call_run(func: &Func, store: &mut Store<HostState>, name: &str) -> Result<i32> {
+    let mut results = vec![Val::Result(Ok(None))];
+    match host::call(func, &mut *store, name, &[], &mut results) {
+        Ok(()) => Ok(match &results[0] {
+            Val::Result(Ok(_)) => 0,
+            _ => 1,
+        }),
+        Err(e) => match e.downcast_ref::<I32Exit>() {
+            Some(exit) => Ok(exit.0),
+            None => Err(e),
+        },
+    }
+}
+
+pub fn run_command(args: &[String]) -> Result<()> {
+    let opts = parse_options(args)?;
+
+    let engine = Engine::new(&host::component_config())?;
+    println!("Loading component: {}", opts.component_path);
+    let component = Component::from_file(&engine, &opts.component_path)?;
+    coverage::register(&engine, &opts.component_path, &component);
+    let run_name = format!("{}#run", run_export(&engine, &component)?);
+
+    let stdout = MemoryOutputPipe::new(OUTPUT_CAPACITY);
+    let stderr = MemoryOutputPipe::new(OUTPUT_CAPACITY);
+    let mut builder = host::wasi_builder();
+    // argv[0] is the program name, as `wasmtime run` passes it.
+    let program = std::path::Path::new(&opts.component_path)
+        .file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    builder
+        .arg(&program)
+        .args(&opts.args)
+        .envs(&opts.env)
+        .stdout(stdout.clone())
+        .stderr(stderr.clone());
+    if memfs::enabled() && !opts.dirs.is_empty() {
+        bail!("--dir cannot be combined with --memfs; use --memfs GUEST=HOST_DIR instead");
+    }
+    for (host_path, guest_path) in &opts.dirs {
+        builder
+            .preopened_dir(host_path, guest_path, DirPerms::all(), FilePerms::all())
+            .with_context(|| format!("failed to open {}", host_path))?;
+    }
+    if let Some(path) = &opts.stdin {
+        let bytes = std::fs::read(path).with_context(|| format!("failed to read {}", path))?;
+        builder.stdin(MemoryInputPipe::new(bytes));
+    }
+
+    let mut linker = host::wasi_linker(&engine)?;
+    policy::apply(&mut linker, &engine, &component)?;
+    bridge::check(&linker, &engine, &component)?;
+    let mut store = host::wasi_store(&engine, builder.build());
+    let instance = linker.instantiate(&mut store, &component)?;
+    let func = host::find_func(&instance, &mut store, &run_name)?;
+
+    let exit_code = call_run(&func, &mut store, &run_name)?;
+    drop(store);
+
+    let stdout = stdout.contents();
+    let stderr = stderr.contents();
+    println!("--- stdout ({} bytes) ---", stdout.len());
+    print!("{}", String::from_utf8_lossy(&stdout));
+    println!("--- stderr ({} bytes) ---", stderr.len());
+    print!("{}", String::from_utf8_lossy(&stderr));
+    println!("--- exit code: {} ---", exit_code);
+
+    if let Some(path) = &opts.expect_stdout {
+        let expected = std::fs::read(path).with_context(|| format!("failed to read {}", path))?;
+        if expected != stdout.as_ref() {
+            bail!("stdout does not match {}", path);
+        }
+        println!("stdout matches {} ✓", path);
+    }
+    if let Some(code) = opts.expect_exit {
+        if code != exit_code {
+            bail!("expected exit code {}, got {}", code, exit_code);
+        }
+        println!("exit code {} ✓", code);
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+// Local server for wasi:http/proxy components
+//
+// Binds a localhost port and hands every request to a fresh instance of the
+// component's `wasi:http/incoming-handler`. The component is pre-instantiated
+// once (`ProxyPre`), so a request only pays for instantiation. Request and
+// response bodies are streamed through wasmtime-wasi-http rather than
+// buffered, and every request is logged with its status and latency.
+//
+// The linker and stores come from the shared host helpers, so the global
+// flags that shape the guest's environment (memfs, deterministic, keyvalue,
+// config, logging, http fixtures, sockets, the WASI version bridge) apply to
+// every request. The server only stops on Ctrl-C, which skips the exit-time
+// reports, so the flags that only take effect there are rejected up front.
+
+use anyhow::{anyhow, Context, Result};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use wasmtime::component::Component;
+use wasmtime::Engine;
+use wasmtime_wasi_http::bindings::http::types::Scheme;
+use wasmtime_wasi_http::bindings::ProxyPre;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::io::TokioIo;
+use wasmtime_wasi_http::WasiHttpView;
+
+use crate::bridge;
+use crate::host::{self, HostState};
+use crate::policy;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+struct ServeOptions {
+    component_path: String,
+    addr: SocketAddr,
+}
+
+fn parse_options(args: &[String]) -> Result<ServeOptions> {
+    let mut component_path = None;
+    let mut addr = DEFAULT_ADDR.to_string();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--addr" => addr = iter.next().context("--addr needs HOST:PORT")?.clone(),
+            _ if component_path.is_none() => component_path = Some(arg.clone()),
+            _ => return Err(anyhow!("unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(ServeOptions {
+        component_path: component_path
+            .context("usage: rust-host serve <component> [--addr HOST:PORT]")?,
+        addr: addr
+            .parse()
+            .with_context(|| format!("invalid --addr: {}", addr))?,
+    })
+}
+
+struct Server {
+    engine: Engine,
+    pre: ProxyPre<HostState>,
+    next_id: AtomicU64,
+}
+
+impl Server {
+    async fn handle(
+        &self,
+        req: hyper::Request<hyper::body::Incoming>,
+    ) -> Result<hyper::Response<HyperOutgoingBody>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        let start = Instant::now();
+
+        let mut store = host::wasi_store(&self.engine, host::wasi_builder().build());
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let req = store.data_mut().new_incoming_request(Scheme::Http, req)?;
+        let out = store.data_mut().new_response_outparam(sender)?;
+        let pre = self.pre.clone();
+
+        // The guest keeps running after it sets the response, writing the
+        // body, so it gets its own task instead of being awaited here.
+        let task = tokio::task::spawn(async move {
+            let proxy = pre.instantiate_async(&mut store).await?;
+            proxy
+                .wasi_http_incoming_handler()
+                .call_handle(&mut store, req, out)
+                .await
+        });
+
+        let result = match receiver.await {
+            Ok(Ok(resp)) => Ok(resp),
+            Ok(Err(code)) => Err(anyhow!("component returned error: {:?}", code)),
+            // The outparam was dropped without a response; the task says why.
+            Err(_) => Err(match task.await {
+                Ok(Ok(())) => anyhow!("component did not set a response"),
+                Ok(Err(e)) => e,
+                Err(e) => anyhow!(e),
+            }),
+        };
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        match &result {
+            Ok(resp) => println!(
+                "[{}] {} {} -> {} ({:.2} ms to headers)",
+                id,
+                method,
+                uri,
+                resp.status().as_u16(),
+                elapsed
+            ),
+            Err(e) => println!("[{}] {} {} -> error ({:.2} ms): {:?}", id, method, uri, elapsed, e),
+        }
+        result
+    }
+}
+
+pub fn run_serve(args: &[String]) -> Result<()> {
+    let opts = parse_options(args)?;
+    host::reject_exit_time_flags("serve")?;
+
+    let mut config = host::component_config();
+    config.async_support(true);
+    let engine = Engine::new(&config)?;
+
+    println!("Loading component: {}", opts.component_path);
+    let component = Component::from_file(&engine, &opts.component_path)?;
+    let mut linker = host::wasi_linker_async(&engine)?;
+    policy::apply(&mut linker, &engine, &component)?;
+    bridge::check(&linker, &engine, &component)?;
+    let pre = ProxyPre::new(linker.instantiate_pre(&component)?)
+        .context("component is not a wasi:http/proxy")?;
+
+    let server = Arc::new(Server {
+        engine,
+        pre,
+        next_id: AtomicU64::new(0),
+    });
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async move {
+            let listener = TcpListener::bind(opts.addr).await?;
+            println!("Serving HTTP on http://{}/", listener.local_addr()?);
+            loop {
+                let (stream, _) = listener.accept().await?;
+                let server = server.clone();
+                tokio::task::spawn(async move {
+                    let service = service_fn(move |req| {
+                        let server = server.clone();
+                        async move { server.handle(req).await }
+                    });
+                    if let Err(e) = http1::Builder::new()
+                        .keep_alive(true)
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        eprintln!("connection error: {:?}", e);
+                    }
+                });
+            }
+        })
+}
+
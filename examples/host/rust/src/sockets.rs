@@ -0,0 +1,186 @@
+// Loopback-only wasi:sockets (`--sockets POLICY`)
+//
+// WASI contexts deny every socket address by default. A policy file opens
+// chosen loopback addresses and ports, and nothing else:
+//
+//   allow = ["127.0.0.1:7000", "127.0.0.1:8000-8010", "[::1]:*"]
+//   udp = false             # default: TCP only
+//   ip_name_lookup = false  # default: no wasi:sockets/ip-name-lookup
+//
+// Every bind/connect a guest attempts is printed as it happens and listed
+// again at exit, allowed or not.
+
+use anyhow::{bail, Context, Result};
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+use wasmtime_wasi::{SocketAddrUse, WasiCtxBuilder};
+
+use crate::host;
+
+static POLICY: Mutex<Option<SocketPolicy>> = Mutex::new(None);
+static ATTEMPTS: Mutex<Vec<Attempt>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Default)]
+struct SocketPolicy {
+    allow: Vec<Rule>,
+    udp: bool,
+    ip_name_lookup: bool,
+}
+
+/// One `allow` entry: a loopback address and a port range.
+#[derive(Clone)]
+struct Rule {
+    ip: IpAddr,
+    ports: RangeInclusive<u16>,
+}
+
+struct Attempt {
+    addr: SocketAddr,
+    usage: SocketAddrUse,
+    allowed: bool,
+}
+
+impl Rule {
+    /// `IP:PORT`, `IP:LOW-HIGH` or `IP:*`; IPv6 addresses are bracketed.
+    fn parse(spec: &str) -> Result<Self> {
+        let (ip, ports) = spec
+            .rsplit_once(':')
+            .with_context(|| format!("expected IP:PORT, got {:?}", spec))?;
+        let ip: IpAddr = ip
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .with_context(|| format!("invalid address in {:?}", spec))?;
+        if !ip.is_loopback() {
+            bail!("only loopback addresses can be allowed: {}", spec);
+        }
+        let ports = match ports {
+            "*" => 0..=u16::MAX,
+            _ => match ports.split_once('-') {
+                Some((low, high)) => low.parse()?..=high.parse()?,
+                None => {
+                    let port = ports.parse()?;
+                    port..=port
+                }
+            },
+        };
+        Ok(Rule { ip, ports })
+    }
+
+    fn matches(&self, addr: &SocketAddr) -> bool {
+        self.ip == addr.ip() && self.ports.contains(&addr.port())
+    }
+}
+
+fn load(path: &str) -> Result<SocketPolicy> {
+    let text = std::fs::read_to_string(path)?;
+    let table: toml::Table = text.parse()?;
+    let mut policy = SocketPolicy::default();
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("allow", toml::Value::Array(entries)) => {
+                for entry in entries {
+                    let spec = entry.as_str().context("allow entries must be strings")?;
+                    policy.allow.push(Rule::parse(spec)?);
+                }
+            }
+            ("udp", toml::Value::Boolean(b)) => policy.udp = b,
+            ("ip_name_lookup", toml::Value::Boolean(b)) => policy.ip_name_lookup = b,
+            (key, _) => bail!("unknown or mistyped policy key: {}", key),
+        }
+    }
+    Ok(policy)
+}
+
+/// Load the `--sockets` policy, if given.
+pub fn enable() -> Result<()> {
+    let Some(path) = &host::options().sockets else {
+        return Ok(());
+    };
+    let policy = load(path).with_context(|| format!("invalid socket policy {}", path))?;
+    *POLICY.lock().unwrap() = Some(policy);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    POLICY.lock().unwrap().is_some()
+}
+
+/// Open one more address, enabling sockets if no policy was given; used by
+/// modes that start their own loopback servers.
+pub fn allow(addr: SocketAddr) {
+    POLICY
+        .lock()
+        .unwrap()
+        .get_or_insert_with(Default::default)
+        .allow
+        .push(Rule {
+            ip: addr.ip(),
+            ports: addr.port()..=addr.port(),
+        });
+}
+
+fn usage_name(usage: SocketAddrUse) -> &'static str {
+    match usage {
+        SocketAddrUse::TcpBind => "tcp-bind",
+        SocketAddrUse::TcpConnect => "tcp-connect",
+        SocketAddrUse::UdpBind => "udp-bind",
+        SocketAddrUse::UdpConnect => "udp-connect",
+        SocketAddrUse::UdpOutgoingDatagram => "udp-send",
+    }
+}
+
+fn check(addr: SocketAddr, usage: SocketAddrUse) -> bool {
+    let allowed = POLICY
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|policy| policy.allow.iter().any(|rule| rule.matches(&addr)));
+    eprintln!(
+        "[sockets] {} {} -> {}",
+        usage_name(usage),
+        addr,
+        if allowed { "allowed" } else { "denied" }
+    );
+    ATTEMPTS.lock().unwrap().push(Attempt {
+        addr,
+        usage,
+        allowed,
+    });
+    allowed
+}
+
+/// Let the builder's context use the sockets the policy allows.
+pub fn apply(builder: &mut WasiCtxBuilder) {
+    let Some(policy) = POLICY.lock().unwrap().clone() else {
+        return;
+    };
+    builder
+        .socket_addr_check(|addr, usage| {
+            let allowed = check(addr, usage);
+            Box::pin(async move { allowed })
+        })
+        .allow_tcp(true)
+        .allow_udp(policy.udp)
+        .allow_ip_name_lookup(policy.ip_name_lookup);
+}
+
+/// List every attempt made during the run.
+pub fn finish() -> Result<()> {
+    let attempts = std::mem::take(&mut *ATTEMPTS.lock().unwrap());
+    if !enabled() {
+        return Ok(());
+    }
+    let denied = attempts.iter().filter(|a| !a.allowed).count();
+    println!("\nSocket attempts: {} ({} denied)", attempts.len(), denied);
+    for attempt in &attempts {
+        println!(
+            "  {:<12} {:<24} {}",
+            usage_name(attempt.usage),
+            attempt.addr,
+            if attempt.allowed { "allowed" } else { "denied" }
+        );
+    }
+    Ok(())
+}
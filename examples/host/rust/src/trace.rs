@@ -0,0 +1,193 @@
+// Call-boundary tracing in Chrome trace_event format
+//
+// With `--trace FILE`, every store the host creates gets a call hook. Each
+// host->guest and guest->host transition becomes a complete ("X") event, so
+// nesting shows up by time when the file is opened in chrome://tracing or
+// Perfetto. Host code names its export calls (`trace::call`) and import
+// implementations (`trace::host_call`); other imports, such as WASI, are
+// named after the calling guest function.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use wasmtime::component::{Func, Val};
+use wasmtime::{AsContextMut, CallHook, Store, WasmBacktrace};
+
+use crate::host;
+use crate::vals;
+
+static TRACER: Mutex<Option<Tracer>> = Mutex::new(None);
+static NEXT_TID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static TID: u64 = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+}
+
+struct Span {
+    cat: &'static str,
+    name: Option<String>,
+    args: Map<String, Value>,
+    start: Instant,
+}
+
+struct Tracer {
+    start: Instant,
+    stacks: HashMap<u64, Vec<Span>>,
+    events: Vec<Value>,
+}
+
+impl Tracer {
+    fn enter(&mut self, tid: u64, cat: &'static str, name: Option<String>) {
+        self.stacks.entry(tid).or_default().push(Span {
+            cat,
+            name,
+            args: Map::new(),
+            start: Instant::now(),
+        });
+    }
+
+    fn exit(&mut self, tid: u64) {
+        let Some(span) = self.stacks.get_mut(&tid).and_then(|s| s.pop()) else {
+            return;
+        };
+        let ts = (span.start - self.start).as_secs_f64() * 1e6;
+        let dur = span.start.elapsed().as_secs_f64() * 1e6;
+        let name = span.name.unwrap_or_else(|| span.cat.to_string());
+        self.events.push(json!({
+            "name": name,
+            "cat": span.cat,
+            "ph": "X",
+            "ts": ts,
+            "dur": dur,
+            "pid": std::process::id(),
+            "tid": tid,
+            "args": span.args,
+        }));
+    }
+
+    fn current(&mut self, tid: u64) -> Option<&mut Span> {
+        self.stacks.get_mut(&tid).and_then(|s| s.last_mut())
+    }
+}
+
+/// Start collecting events if `--trace` was given.
+pub fn enable() {
+    if host::options().trace.is_some() {
+        *TRACER.lock().unwrap() = Some(Tracer {
+            start: Instant::now(),
+            stacks: HashMap::new(),
+            events: Vec::new(),
+        });
+    }
+}
+
+fn enabled() -> bool {
+    TRACER.lock().unwrap().is_some()
+}
+
+fn with_tracer(f: impl FnOnce(&mut Tracer, u64)) {
+    if let Some(tracer) = TRACER.lock().unwrap().as_mut() {
+        f(tracer, TID.with(|t| *t));
+    }
+}
+
+/// Install the call hook on a store; a no-op unless tracing is enabled.
+pub fn attach<T>(store: &mut Store<T>) {
+    if !enabled() {
+        return;
+    }
+    store.call_hook(|ctx, kind| {
+        // Name host spans after the guest function making the call.
+        let caller = match kind {
+            CallHook::CallingHost => {
+                let backtrace = WasmBacktrace::capture(&ctx);
+                backtrace.frames().first().map(|f| match f.func_name() {
+                    Some(name) => format!("host <- {}", name),
+                    None => format!("host <- wasm-function[{}]", f.func_index()),
+                })
+            }
+            _ => None,
+        };
+        with_tracer(|tracer, tid| match kind {
+            CallHook::CallingWasm => tracer.enter(tid, "wasm", None),
+            CallHook::CallingHost => tracer.enter(tid, "host", caller),
+            CallHook::ReturningFromWasm | CallHook::ReturningFromHost => tracer.exit(tid),
+        });
+        Ok(())
+    });
+}
+
+fn size_args(key: &str, vals: &[Val]) -> (String, Value) {
+    (key.to_string(), json!(vals.iter().map(vals::byte_size).sum::<usize>()))
+}
+
+/// Call an export and its post-return under named spans. The export span
+/// covers lowering and lifting, so `cabi_realloc` calls show up as children.
+pub fn call(
+    func: &Func,
+    mut store: impl AsContextMut,
+    name: &str,
+    params: &[Val],
+    results: &mut [Val],
+) -> Result<()> {
+    with_tracer(|tracer, tid| {
+        tracer.enter(tid, "export", Some(name.to_string()));
+        let (key, size) = size_args("arg_bytes", params);
+        tracer.current(tid).unwrap().args.insert(key, size);
+    });
+    func.call(&mut store, params, results).inspect_err(|_| {
+        with_tracer(|tracer, tid| tracer.exit(tid));
+    })?;
+    with_tracer(|tracer, tid| {
+        let (key, size) = size_args("result_bytes", results);
+        tracer.current(tid).unwrap().args.insert(key, size);
+        tracer.exit(tid);
+        tracer.enter(tid, "export", Some(format!("{} (post-return)", name)));
+    });
+    let result = func.post_return(&mut store);
+    with_tracer(|tracer, tid| tracer.exit(tid));
+    result
+}
+
+/// Name the current import span; call at the start of a host function.
+pub fn host_call(name: &str, params: &[Val]) {
+    with_tracer(|tracer, tid| {
+        if let Some(span) = tracer.current(tid) {
+            span.cat = "import";
+            span.name = Some(name.to_string());
+            let (key, size) = size_args("arg_bytes", params);
+            span.args.insert(key, size);
+        }
+    });
+}
+
+/// Record the result size of the current import span.
+pub fn host_results(results: &[Val]) {
+    with_tracer(|tracer, tid| {
+        if let Some(span) = tracer.current(tid) {
+            let (key, size) = size_args("result_bytes", results);
+            span.args.insert(key, size);
+        }
+    });
+}
+
+/// Write the collected events to the `--trace` file.
+pub fn finish() -> Result<()> {
+    let (Some(path), Some(tracer)) = (&host::options().trace, TRACER.lock().unwrap().take())
+    else {
+        return Ok(());
+    };
+    let count = tracer.events.len();
+    let out = json!({
+        "traceEvents": tracer.events,
+        "displayTimeUnit": "ns",
+    });
+    let file =
+        std::fs::File::create(path).with_context(|| format!("failed to create {}", path))?;
+    serde_json::to_writer(std::io::BufWriter::new(file), &out)?;
+    eprintln!("Trace: {} events -> {}", count, path);
+    Ok(())
+}
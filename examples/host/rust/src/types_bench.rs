@@ -5,6 +5,10 @@ use std::time::{Duration, Instant};
 use wasmtime::component::{Component, Linker, Val};
 use wasmtime::{Config, Engine, Store};
 
+use crate::coverage;
+use crate::host;
+use crate::trace;
+
 const WARMUP_ITERATIONS: u32 = 100;
 const BENCH_ITERATIONS: u32 = 10000;
 
@@ -17,9 +21,11 @@ pub fn run_types_bench(component_path: &str) -> Result<()> {
 
     println!("Loading component: {}", component_path);
     let component = Component::from_file(&engine, component_path)?;
+    coverage::register(&engine, component_path, &component);
 
     let linker = Linker::<()>::new(&engine);
     let mut store = Store::new(&engine, ());
+    trace::attach(&mut store);
 
     let instance = linker.instantiate(&mut store, &component)?;
 
@@ -80,24 +86,21 @@ fn print_result(name: &str, duration: Duration) {
 }
 
 fn bench_echo_s32(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/primitives").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "echo-s32").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/primitives#echo-s32";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::S32(0)];
     let args = [Val::S32(42)];
 
     // Warmup
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     // Bench
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -106,22 +109,19 @@ fn bench_echo_s32(instance: &wasmtime::component::Instance, store: &mut Store<()
 }
 
 fn bench_echo_s64(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/primitives").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "echo-s64").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/primitives#echo-s64";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::S64(0)];
     let args = [Val::S64(9999999999i64)];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -130,22 +130,19 @@ fn bench_echo_s64(instance: &wasmtime::component::Instance, store: &mut Store<()
 }
 
 fn bench_echo_f32(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/primitives").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "echo-f32").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/primitives#echo-f32";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::Float32(0.0)];
     let args = [Val::Float32(3.14)];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -154,22 +151,19 @@ fn bench_echo_f32(instance: &wasmtime::component::Instance, store: &mut Store<()
 }
 
 fn bench_echo_bool(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/primitives").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "echo-bool").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/primitives#echo-bool";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::Bool(false)];
     let args = [Val::Bool(true)];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -178,23 +172,20 @@ fn bench_echo_bool(instance: &wasmtime::component::Instance, store: &mut Store<(
 }
 
 fn bench_echo_string(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/primitives").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "echo-string").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/primitives#echo-string";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::String("".into())];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::String("hello".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::String("hello".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -203,23 +194,20 @@ fn bench_echo_string(instance: &wasmtime::component::Instance, store: &mut Store
 }
 
 fn bench_echo_color(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/enums").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "echo-color").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/enums#echo-color";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::Enum("".into())];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::Enum("red".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::Enum("red".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -228,23 +216,20 @@ fn bench_echo_color(instance: &wasmtime::component::Instance, store: &mut Store<
 }
 
 fn bench_color_name(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/enums").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "color-name").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/enums#color-name";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::String("".into())];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::Enum("blue".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::Enum("blue".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -253,23 +238,20 @@ fn bench_color_name(instance: &wasmtime::component::Instance, store: &mut Store<
 }
 
 fn bench_has_read(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/flags-test").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "has-read").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/flags-test#has-read";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::Bool(false)];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::Flags(vec!["read".into(), "write".into()])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::Flags(vec!["read".into(), "write".into()])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -278,23 +260,20 @@ fn bench_has_read(instance: &wasmtime::component::Instance, store: &mut Store<()
 }
 
 fn bench_echo_permissions(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/flags-test").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "echo-permissions").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/flags-test#echo-permissions";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::Flags(vec![])];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::Flags(vec!["read".into(), "execute".into()])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::Flags(vec!["read".into(), "execute".into()])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -303,23 +282,20 @@ fn bench_echo_permissions(instance: &wasmtime::component::Instance, store: &mut
 }
 
 fn bench_sum_list(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/containers").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "sum-list").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/containers#sum-list";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::S32(0)];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::List(vec![Val::S32(1), Val::S32(2), Val::S32(3), Val::S32(4)])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::List(vec![Val::S32(1), Val::S32(2), Val::S32(3), Val::S32(4)])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -328,9 +304,8 @@ fn bench_sum_list(instance: &wasmtime::component::Instance, store: &mut Store<()
 }
 
 fn bench_count_list(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/containers").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "count-list").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/containers#count-list";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::S32(0)];
 
@@ -340,8 +315,7 @@ fn bench_count_list(instance: &wasmtime::component::Instance, store: &mut Store<
             Val::String("b".into()),
             Val::String("c".into()),
         ])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
@@ -351,8 +325,7 @@ fn bench_count_list(instance: &wasmtime::component::Instance, store: &mut Store<
             Val::String("b".into()),
             Val::String("c".into()),
         ])];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -361,22 +334,19 @@ fn bench_count_list(instance: &wasmtime::component::Instance, store: &mut Store<
 }
 
 fn bench_divide_ok(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/containers").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "divide").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/containers#divide";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::Result(Ok(None))];
     let args = [Val::S32(10), Val::S32(2)];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -385,22 +355,19 @@ fn bench_divide_ok(instance: &wasmtime::component::Instance, store: &mut Store<(
 }
 
 fn bench_divide_err(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/containers").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "divide").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/containers#divide";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::Result(Ok(None))];
     let args = [Val::S32(10), Val::S32(0)];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -409,22 +376,19 @@ fn bench_divide_err(instance: &wasmtime::component::Instance, store: &mut Store<
 }
 
 fn bench_add2(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/multi-params").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "add2").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/multi-params#add2";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::S32(0)];
     let args = [Val::S32(3), Val::S32(4)];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -433,22 +397,19 @@ fn bench_add2(instance: &wasmtime::component::Instance, store: &mut Store<()>) -
 }
 
 fn bench_add4(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/multi-params").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "add4").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/multi-params#add4";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::S32(0)];
     let args = [Val::S32(1), Val::S32(2), Val::S32(3), Val::S32(4)];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -457,9 +418,8 @@ fn bench_add4(instance: &wasmtime::component::Instance, store: &mut Store<()>) -
 }
 
 fn bench_concat3(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/multi-params").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "concat3").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/multi-params#concat3";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::String("".into())];
 
@@ -469,8 +429,7 @@ fn bench_concat3(instance: &wasmtime::component::Instance, store: &mut Store<()>
             Val::String(" ".into()),
             Val::String("World".into()),
         ];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
@@ -480,8 +439,7 @@ fn bench_concat3(instance: &wasmtime::component::Instance, store: &mut Store<()>
             Val::String(" ".into()),
             Val::String("World".into()),
         ];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -490,23 +448,20 @@ fn bench_concat3(instance: &wasmtime::component::Instance, store: &mut Store<()>
 }
 
 fn bench_mixed_params(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/multi-params").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "mixed-params").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/multi-params#mixed-params";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![Val::String("".into())];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::S32(42), Val::String("test".into()), Val::Bool(true)];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::S32(42), Val::String("test".into()), Val::Bool(true)];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -515,23 +470,20 @@ fn bench_mixed_params(instance: &wasmtime::component::Instance, store: &mut Stor
 }
 
 fn bench_no_return(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/side-effects").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "no-return").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/side-effects#no-return";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![];
 
     for _ in 0..WARMUP_ITERATIONS {
         let args = [Val::String("msg".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
         let args = [Val::String("msg".into())];
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
@@ -540,22 +492,19 @@ fn bench_no_return(instance: &wasmtime::component::Instance, store: &mut Store<(
 }
 
 fn bench_no_params_no_return(instance: &wasmtime::component::Instance, store: &mut Store<()>) -> Result<()> {
-    let iface = instance.get_export(&mut *store, None, "local:types-test/side-effects").unwrap();
-    let func_export = instance.get_export(&mut *store, Some(&iface), "no-params-no-return").unwrap();
-    let func = instance.get_func(&mut *store, &func_export).unwrap();
+    let name = "local:types-test/side-effects#no-params-no-return";
+    let func = host::find_func(instance, &mut *store, name)?;
 
     let mut results = vec![];
     let args: [Val; 0] = [];
 
     for _ in 0..WARMUP_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
 
     let start = Instant::now();
     for _ in 0..BENCH_ITERATIONS {
-        func.call(&mut *store, &args, &mut results)?;
-        func.post_return(&mut *store)?;
+        host::call(&func, &mut *store, name, &args, &mut results)?;
     }
     let duration = start.elapsed();
 
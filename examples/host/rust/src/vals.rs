@@ -0,0 +1,534 @@
+// Text form of component values (a WAVE-like subset) for CLI arguments and output
+//
+//   42  -1.5  true  'c'  "str"  [1, 2]  (1, "a")  {x: 1, y: 2}
+//   red  {read, write}  none  some(1)  ok  ok(1)  err("e")  case(1)
+
+use anyhow::{anyhow, bail, Result};
+use wasmtime::component::{Type, Val};
+
+pub fn parse_val(ty: &Type, src: &str) -> Result<Val> {
+    let mut parser = Parser { src, pos: 0 };
+    let val = parser.value(ty)?;
+    parser.skip_ws();
+    if parser.pos != src.len() {
+        bail!("trailing input at {}: {:?}", parser.pos, &src[parser.pos..]);
+    }
+    Ok(val)
+}
+
+/// Parse one value per parameter type.
+pub fn parse_args(params: &[Type], args: &[String]) -> Result<Vec<Val>> {
+    if params.len() != args.len() {
+        bail!("expected {} argument(s), got {}", params.len(), args.len());
+    }
+    params
+        .iter()
+        .zip(args)
+        .map(|(ty, arg)| parse_val(ty, arg))
+        .collect()
+}
+
+pub fn format_val(val: &Val) -> String {
+    let mut out = String::new();
+    write_val(&mut out, val);
+    out
+}
+
+/// Zero value of a type, used for result slots and auto-filled arguments.
+pub fn default_val(ty: &Type) -> Result<Val> {
+    Ok(match ty {
+        Type::Bool => Val::Bool(false),
+        Type::S8 => Val::S8(0),
+        Type::U8 => Val::U8(0),
+        Type::S16 => Val::S16(0),
+        Type::U16 => Val::U16(0),
+        Type::S32 => Val::S32(0),
+        Type::U32 => Val::U32(0),
+        Type::S64 => Val::S64(0),
+        Type::U64 => Val::U64(0),
+        Type::Float32 => Val::Float32(0.0),
+        Type::Float64 => Val::Float64(0.0),
+        Type::Char => Val::Char('\0'),
+        Type::String => Val::String(String::new()),
+        Type::List(_) => Val::List(vec![]),
+        Type::Record(r) => Val::Record(
+            r.fields()
+                .map(|f| Ok((f.name.to_string(), default_val(&f.ty)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Type::Tuple(t) => Val::Tuple(t.types().map(|t| default_val(&t)).collect::<Result<_>>()?),
+        Type::Variant(v) => {
+            let case = v.cases().next().ok_or_else(|| anyhow!("empty variant"))?;
+            let payload = match &case.ty {
+                Some(ty) => Some(Box::new(default_val(ty)?)),
+                None => None,
+            };
+            Val::Variant(case.name.to_string(), payload)
+        }
+        Type::Enum(e) => Val::Enum(
+            e.names()
+                .next()
+                .ok_or_else(|| anyhow!("empty enum"))?
+                .to_string(),
+        ),
+        Type::Option(_) => Val::Option(None),
+        Type::Result(r) => Val::Result(Ok(match r.ok() {
+            Some(ty) => Some(Box::new(default_val(&ty)?)),
+            None => None,
+        })),
+        Type::Flags(_) => Val::Flags(vec![]),
+        Type::Own(_) | Type::Borrow(_) => bail!("resources have no default value"),
+    })
+}
+
+/// Payload size in bytes: primitives at their natural width, strings as UTF-8,
+/// containers as the sum of their elements (discriminants not counted).
+pub fn byte_size(val: &Val) -> usize {
+    match val {
+        Val::Bool(_) | Val::S8(_) | Val::U8(_) => 1,
+        Val::S16(_) | Val::U16(_) => 2,
+        Val::S32(_) | Val::U32(_) | Val::Float32(_) | Val::Char(_) => 4,
+        Val::S64(_) | Val::U64(_) | Val::Float64(_) => 8,
+        Val::String(s) => s.len(),
+        Val::List(items) | Val::Tuple(items) => items.iter().map(byte_size).sum(),
+        Val::Record(fields) => fields.iter().map(|(_, v)| byte_size(v)).sum(),
+        Val::Variant(_, payload) | Val::Option(payload) => payload.as_deref().map_or(0, byte_size),
+        Val::Result(Ok(payload)) | Val::Result(Err(payload)) => {
+            payload.as_deref().map_or(0, byte_size)
+        }
+        Val::Enum(_) | Val::Flags(_) | Val::Resource(_) => 4,
+    }
+}
+
+fn write_val(out: &mut String, val: &Val) {
+    match val {
+        Val::Bool(v) => out.push_str(&v.to_string()),
+        Val::S8(v) => out.push_str(&v.to_string()),
+        Val::U8(v) => out.push_str(&v.to_string()),
+        Val::S16(v) => out.push_str(&v.to_string()),
+        Val::U16(v) => out.push_str(&v.to_string()),
+        Val::S32(v) => out.push_str(&v.to_string()),
+        Val::U32(v) => out.push_str(&v.to_string()),
+        Val::S64(v) => out.push_str(&v.to_string()),
+        Val::U64(v) => out.push_str(&v.to_string()),
+        Val::Float32(v) => out.push_str(&v.to_string()),
+        Val::Float64(v) => out.push_str(&v.to_string()),
+        Val::Char(c) => out.push_str(&format!("{:?}", c)),
+        Val::String(s) => out.push_str(&format!("{:?}", s)),
+        Val::List(items) => write_seq(out, '[', ']', items),
+        Val::Tuple(items) => write_seq(out, '(', ')', items),
+        Val::Record(fields) => {
+            out.push('{');
+            for (i, (name, v)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(name);
+                out.push_str(": ");
+                write_val(out, v);
+            }
+            out.push('}');
+        }
+        Val::Variant(name, payload) => write_case(out, name, payload.as_deref()),
+        Val::Enum(name) => out.push_str(name),
+        Val::Option(None) => out.push_str("none"),
+        Val::Option(Some(v)) => write_case(out, "some", Some(v)),
+        Val::Result(Ok(v)) => write_case(out, "ok", v.as_deref()),
+        Val::Result(Err(v)) => write_case(out, "err", v.as_deref()),
+        Val::Flags(names) => {
+            out.push('{');
+            out.push_str(&names.join(", "));
+            out.push('}');
+        }
+        Val::Resource(_) => out.push_str("<resource>"),
+    }
+}
+
+fn write_seq(out: &mut String, open: char, close: char, items: &[Val]) {
+    out.push(open);
+    for (i, v) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_val(out, v);
+    }
+    out.push(close);
+}
+
+fn write_case(out: &mut String, name: &str, payload: Option<&Val>) {
+    out.push_str(name);
+    if let Some(v) = payload {
+        out.push('(');
+        write_val(out, v);
+        out.push(')');
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self, ty: &Type) -> Result<Val> {
+        self.skip_ws();
+        Ok(match ty {
+            Type::Bool => match self.ident()? {
+                "true" => Val::Bool(true),
+                "false" => Val::Bool(false),
+                other => bail!("expected bool, got {:?}", other),
+            },
+            Type::S8 => Val::S8(self.number()?),
+            Type::U8 => Val::U8(self.number()?),
+            Type::S16 => Val::S16(self.number()?),
+            Type::U16 => Val::U16(self.number()?),
+            Type::S32 => Val::S32(self.number()?),
+            Type::U32 => Val::U32(self.number()?),
+            Type::S64 => Val::S64(self.number()?),
+            Type::U64 => Val::U64(self.number()?),
+            Type::Float32 => Val::Float32(self.number()?),
+            Type::Float64 => Val::Float64(self.number()?),
+            Type::Char => {
+                let s = self.quoted('\'')?;
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Val::Char(c),
+                    _ => bail!("expected a single char, got {:?}", s),
+                }
+            }
+            Type::String => Val::String(self.quoted('"')?),
+            Type::List(list) => {
+                let elem = list.ty();
+                Val::List(self.seq('[', ']', |p| p.value(&elem))?)
+            }
+            Type::Tuple(tuple) => {
+                let types: Vec<Type> = tuple.types().collect();
+                let mut i = 0;
+                let items = self.seq('(', ')', |p| {
+                    let ty = types.get(i).ok_or_else(|| anyhow!("too many tuple elements"))?;
+                    i += 1;
+                    p.value(ty)
+                })?;
+                if items.len() != types.len() {
+                    bail!("expected {} tuple elements, got {}", types.len(), items.len());
+                }
+                Val::Tuple(items)
+            }
+            Type::Record(record) => {
+                let fields: Vec<(String, Type)> = record
+                    .fields()
+                    .map(|f| (f.name.to_string(), f.ty))
+                    .collect();
+                let mut given = self.seq('{', '}', |p| {
+                    let name = p.ident()?.to_string();
+                    p.expect(':')?;
+                    let ty = fields
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, ty)| ty)
+                        .ok_or_else(|| anyhow!("unknown field: {}", name))?;
+                    Ok((name, p.value(ty)?))
+                })?;
+                let mut out = Vec::with_capacity(fields.len());
+                for (name, ty) in &fields {
+                    match given.iter().position(|(n, _)| n == name) {
+                        Some(i) => out.push(given.swap_remove(i)),
+                        None if matches!(ty, Type::Option(_)) => {
+                            out.push((name.clone(), Val::Option(None)))
+                        }
+                        None => bail!("missing field: {}", name),
+                    }
+                }
+                Val::Record(out)
+            }
+            Type::Variant(variant) => {
+                let name = self.ident()?.to_string();
+                let case = variant
+                    .cases()
+                    .find(|c| c.name == name)
+                    .ok_or_else(|| anyhow!("unknown case: {}", name))?;
+                let payload = self.payload(case.ty.as_ref())?;
+                Val::Variant(name, payload)
+            }
+            Type::Enum(e) => {
+                let name = self.ident()?;
+                if !e.names().any(|n| n == name) {
+                    bail!("unknown enum case: {}", name);
+                }
+                Val::Enum(name.to_string())
+            }
+            Type::Option(option) => match self.ident()? {
+                "none" => Val::Option(None),
+                "some" => Val::Option(self.payload(Some(&option.ty()))?),
+                other => bail!("expected none or some(..), got {:?}", other),
+            },
+            Type::Result(result) => match self.ident()? {
+                "ok" => Val::Result(Ok(self.payload(result.ok().as_ref())?)),
+                "err" => Val::Result(Err(self.payload(result.err().as_ref())?)),
+                other => bail!("expected ok or err, got {:?}", other),
+            },
+            Type::Flags(flags) => {
+                let names = self.seq('{', '}', |p| {
+                    let name = p.ident()?;
+                    if !flags.names().any(|n| n == name) {
+                        bail!("unknown flag: {}", name);
+                    }
+                    Ok(name.to_string())
+                })?;
+                Val::Flags(names)
+            }
+            Type::Own(_) | Type::Borrow(_) => bail!("resources cannot be written as text"),
+        })
+    }
+
+    fn payload(&mut self, ty: Option<&Type>) -> Result<Option<Box<Val>>> {
+        match ty {
+            Some(ty) => {
+                self.expect('(')?;
+                let v = self.value(ty)?;
+                self.expect(')')?;
+                Ok(Some(Box::new(v)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn seq<T>(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        self.expect(open)?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.eat(close) {
+                return Ok(items);
+            }
+            items.push(item(self)?);
+            self.skip_ws();
+            if !self.eat(',') {
+                self.expect(close)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T> {
+        let token = self.take_while(|c| c.is_ascii_alphanumeric() || "+-._".contains(c));
+        token
+            .replace('_', "")
+            .parse()
+            .map_err(|_| anyhow!("invalid number: {:?}", token))
+    }
+
+    fn ident(&mut self) -> Result<&'a str> {
+        self.skip_ws();
+        let token = self.take_while(|c| c.is_ascii_alphanumeric() || c == '-' || c == '%');
+        if token.is_empty() {
+            bail!("expected identifier at {}", self.pos);
+        }
+        Ok(token.strip_prefix('%').unwrap_or(token))
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String> {
+        self.expect(quote)?;
+        let mut out = String::new();
+        let mut chars = self.src[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.pos += i + c.len_utf8();
+                    return Ok(out);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('0') => out.push('\0'),
+                    Some('u') => {
+                        let mut code = String::new();
+                        if chars.next().map(|(_, c)| c) == Some('{') {
+                            for (_, c) in chars.by_ref() {
+                                if c == '}' {
+                                    break;
+                                }
+                                code.push(c);
+                            }
+                        }
+                        let c = u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| anyhow!("invalid escape \\u{{{}}}", code))?;
+                        out.push(c);
+                    }
+                    Some(c) => out.push(c),
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        bail!("unterminated {} literal", quote)
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        let len = self.src[start..]
+            .find(|c: char| !f(c))
+            .unwrap_or(self.src.len() - start);
+        self.pos += len;
+        &self.src[start..start + len]
+    }
+
+    fn skip_ws(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.src[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        self.skip_ws();
+        if !self.eat(c) {
+            bail!("expected {:?} at {}", c, self.pos);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Config, Engine};
+
+    const WIT: &str = "package test:vals;
+        interface types {
+            record point { x: s32, y: f64, label: option<string> }
+            variant shape { none, circle(f32), named(string) }
+            flags perms { read, write, exec }
+            enum color { red, green }
+            f: func(
+                p: point, s: shape, fl: perms, o: option<option<u8>>,
+                r: result<s64, string>, bare: result, text: string, c: char,
+                i8: s8, i64: s64, n: u64, x: f32, y: f64, l: list<tuple<u16, color>>,
+            );
+        }
+        world vals { export types; }";
+
+    /// Parameter types of `f` above, by parameter name.
+    fn types() -> Vec<(&'static str, Type)> {
+        let path = std::env::temp_dir().join(format!(
+            "vals-{}-{:?}.wit",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::write(&path, WIT).unwrap();
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let sigs = crate::cabi::signatures(&engine, path.to_str().unwrap(), None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let names = [
+            "p", "s", "fl", "o", "r", "bare", "text", "c", "i8", "i64", "n", "x", "y", "l",
+        ];
+        names.into_iter().zip(sigs[0].params.clone()).collect()
+    }
+
+    /// Parse `src` as the type of parameter `param`, format the value and
+    /// check the text parses back to the same value.
+    fn round_trip(types: &[(&str, Type)], param: &str, src: &str) -> String {
+        let ty = &types.iter().find(|(name, _)| *name == param).unwrap().1;
+        let val = parse_val(ty, src).unwrap_or_else(|e| panic!("{}: {}", src, e));
+        let text = format_val(&val);
+        assert_eq!(parse_val(ty, &text).unwrap(), val, "{} -> {}", src, text);
+        text
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let types = types();
+        assert_eq!(
+            round_trip(&types, "p", "{y: 1.5, x: -3}"),
+            "{x: -3, y: 1.5, label: none}"
+        );
+        assert_eq!(
+            round_trip(&types, "p", "{x: 0, y: -0.25, label: some(\"a, b\")}"),
+            "{x: 0, y: -0.25, label: some(\"a, b\")}"
+        );
+    }
+
+    #[test]
+    fn variants_round_trip() {
+        let types = types();
+        assert_eq!(round_trip(&types, "s", "none"), "none");
+        assert_eq!(round_trip(&types, "s", "circle( 2.5 )"), "circle(2.5)");
+        assert_eq!(round_trip(&types, "s", "named(\"x\")"), "named(\"x\")");
+        assert_eq!(
+            round_trip(&types, "l", "[(1, red), (65535, %green)]"),
+            "[(1, red), (65535, green)]"
+        );
+    }
+
+    #[test]
+    fn flags_round_trip() {
+        let types = types();
+        assert_eq!(round_trip(&types, "fl", "{read, exec}"), "{read, exec}");
+        assert_eq!(round_trip(&types, "fl", "{ }"), "{}");
+    }
+
+    #[test]
+    fn options_and_results_round_trip() {
+        let types = types();
+        assert_eq!(round_trip(&types, "o", "none"), "none");
+        assert_eq!(round_trip(&types, "o", "some(none)"), "some(none)");
+        assert_eq!(round_trip(&types, "o", "some(some(7))"), "some(some(7))");
+        assert_eq!(
+            round_trip(&types, "r", "ok(-9000000000)"),
+            "ok(-9000000000)"
+        );
+        assert_eq!(round_trip(&types, "r", "err(\"bad\")"), "err(\"bad\")");
+        assert_eq!(round_trip(&types, "bare", "ok"), "ok");
+        assert_eq!(round_trip(&types, "bare", "err"), "err");
+    }
+
+    #[test]
+    fn escaped_strings_and_chars_round_trip() {
+        let types = types();
+        assert_eq!(
+            round_trip(&types, "text", r#""q\"b\\s\n\t\r\0 é\u{1}""#),
+            r#""q\"b\\s\n\t\r\0 é\u{1}""#
+        );
+        assert_eq!(round_trip(&types, "text", "\"it's\""), "\"it's\"");
+        for c in [r"'\''", r"'\\'", r"'\n'", "'é'", "'\"'", r"'\u{301}'"] {
+            assert_eq!(round_trip(&types, "c", c), c);
+        }
+        assert_eq!(round_trip(&types, "c", r"'\u{1F600}'"), "'😀'");
+    }
+
+    #[test]
+    fn numbers_round_trip() {
+        let types = types();
+        assert_eq!(round_trip(&types, "i8", "-128"), "-128");
+        assert_eq!(
+            round_trip(&types, "i64", "-9223372036854775808"),
+            "-9223372036854775808"
+        );
+        assert_eq!(
+            round_trip(&types, "n", "18_446_744_073_709_551_615"),
+            "18446744073709551615"
+        );
+        assert_eq!(round_trip(&types, "x", "-1.5"), "-1.5");
+        assert_eq!(round_trip(&types, "y", "-0.000123"), "-0.000123");
+        assert_eq!(round_trip(&types, "y", "-0"), "-0");
+        assert_eq!(round_trip(&types, "y", "-inf"), "-inf");
+        round_trip(&types, "y", "1e300");
+        assert!(parse_val(&types[8].1, "128").is_err());
+    }
+}
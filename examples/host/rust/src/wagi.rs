@@ -0,0 +1,363 @@
+// WAGI runner for wasi:cli/command web handlers
+//
+// Implements the contract Spin's `wagi` executor gives a command component:
+// request metadata becomes CGI environment variables, the query string becomes
+// argv, the request body is stdin, and stdout is parsed as a CGI response
+// (headers, a blank line, then the body). Each request runs in a fresh
+// instance, like `rust-host run`. Like `serve`, it only stops on Ctrl-C, so
+// flags whose output is written at exit are rejected up front.
+
+use anyhow::{anyhow, bail, Context, Result};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use wasmtime::component::{Component, InstancePre};
+use wasmtime::Engine;
+use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi_http::io::TokioIo;
+
+use crate::bridge;
+use crate::host::{self, HostState};
+use crate::policy;
+use crate::run;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:3000";
+/// Upper bound on a handler's stdout, which holds the whole response.
+const OUTPUT_CAPACITY: usize = 16 * 1024 * 1024;
+
+struct WagiOptions {
+    component_path: String,
+    addr: SocketAddr,
+    route: String,
+    env: Vec<(String, String)>,
+}
+
+fn parse_options(args: &[String]) -> Result<WagiOptions> {
+    let mut component_path = None;
+    let mut addr = DEFAULT_ADDR.to_string();
+    let mut route = "/...".to_string();
+    let mut env = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--addr" => addr = iter.next().context("--addr needs HOST:PORT")?.clone(),
+            "--route" => route = iter.next().context("--route needs a path")?.clone(),
+            "--env" => {
+                let kv = iter.next().context("--env needs KEY=VALUE")?;
+                let (k, v) = kv.split_once('=').unwrap_or((kv, ""));
+                env.push((k.to_string(), v.to_string()));
+            }
+            _ if component_path.is_none() => component_path = Some(arg.clone()),
+            _ => bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    Ok(WagiOptions {
+        component_path: component_path.context(
+            "usage: rust-host wagi <component> [--addr HOST:PORT] [--route /path/...] [--env K=V]...",
+        )?,
+        addr: addr
+            .parse()
+            .with_context(|| format!("invalid --addr: {}", addr))?,
+        route,
+        env,
+    })
+}
+
+/// The route prefix, with Spin's `/...` wildcard suffix removed.
+fn route_base(route: &str) -> &str {
+    route.strip_suffix("/...").unwrap_or(route)
+}
+
+/// Whether `path` is handled by `route` (`/api/...` matches `/api` and below).
+fn route_matches(route: &str, path: &str) -> bool {
+    match route.strip_suffix("/...") {
+        Some(base) => base.is_empty() || path == base || path.starts_with(&format!("{}/", base)),
+        None => path == route,
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+struct Request {
+    method: String,
+    uri: hyper::Uri,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+    remote: SocketAddr,
+}
+
+/// CGI variables for one request, as Spin's WAGI executor sets them.
+fn cgi_env(opts: &WagiOptions, local: SocketAddr, req: &Request) -> Vec<(String, String)> {
+    let base = route_base(&opts.route);
+    let path = req.uri.path();
+    let path_info = path.strip_prefix(base).unwrap_or(path);
+    let header = |name: &str| {
+        req.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    };
+    let host = match header("host") {
+        h if h.is_empty() => local.to_string(),
+        h => h,
+    };
+
+    let mut env = vec![
+        ("GATEWAY_INTERFACE".into(), "CGI/1.1".into()),
+        ("SERVER_SOFTWARE".into(), "rust-host-wagi".into()),
+        ("SERVER_PROTOCOL".into(), "HTTP/1.1".into()),
+        ("SERVER_NAME".into(), local.ip().to_string()),
+        ("SERVER_PORT".into(), local.port().to_string()),
+        ("REQUEST_METHOD".into(), req.method.clone()),
+        ("SCRIPT_NAME".into(), base.to_string()),
+        ("PATH_INFO".into(), path_info.to_string()),
+        ("PATH_TRANSLATED".into(), path_info.to_string()),
+        ("QUERY_STRING".into(), req.uri.query().unwrap_or("").to_string()),
+        ("REMOTE_ADDR".into(), req.remote.ip().to_string()),
+        ("REMOTE_HOST".into(), req.remote.ip().to_string()),
+        ("REMOTE_USER".into(), String::new()),
+        ("AUTH_TYPE".into(), String::new()),
+        ("CONTENT_LENGTH".into(), req.body.len().to_string()),
+        ("CONTENT_TYPE".into(), header("content-type")),
+        ("X_MATCHED_ROUTE".into(), opts.route.clone()),
+        ("X_RAW_PATH_INFO".into(), path_info.to_string()),
+        ("X_FULL_URL".into(), format!("http://{}{}", host, req.uri)),
+    ];
+    for (name, value) in &req.headers {
+        let key = format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_"));
+        // Repeated headers are joined, as CGI allows one value per variable.
+        match env.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => {
+                v.push_str(", ");
+                v.push_str(value);
+            }
+            None => env.push((key, value.clone())),
+        }
+    }
+    env.extend(opts.env.iter().cloned());
+    env
+}
+
+/// argv for one request: the path, then each `&`-separated query parameter.
+fn cgi_args(req: &Request) -> Vec<String> {
+    let mut args = vec![req.uri.path().to_string()];
+    if let Some(query) = req.uri.query() {
+        args.extend(query.split('&').filter(|p| !p.is_empty()).map(percent_decode));
+    }
+    args
+}
+
+/// Parse handler stdout into a response. Without a `Status` header the status
+/// is 302 when `Location` is set and 200 otherwise.
+fn parse_cgi_response(stdout: &[u8]) -> Result<hyper::Response<Full<Bytes>>> {
+    let (head, body) = ["\r\n\r\n", "\n\n"]
+        .iter()
+        .filter_map(|sep| {
+            stdout
+                .windows(sep.len())
+                .position(|w| w == sep.as_bytes())
+                .map(|i| (i, sep.len()))
+        })
+        .min()
+        .map(|(i, n)| (&stdout[..i], &stdout[i + n..]))
+        .ok_or_else(|| anyhow!("handler output has no blank line after the headers"))?;
+
+    let mut status = None;
+    let mut builder = hyper::Response::builder();
+    let mut has_location = false;
+    for line in String::from_utf8_lossy(head).lines() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid header line: {:?}", line))?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("status") {
+            // `Status: 404 Not Found`; only the code matters.
+            let code = value.split_whitespace().next().unwrap_or("");
+            status = Some(code.parse::<u16>().with_context(|| format!("invalid status: {}", value))?);
+        } else {
+            has_location |= name.eq_ignore_ascii_case("location");
+            builder = builder.header(name, value);
+        }
+    }
+    let status = status.unwrap_or(if has_location { 302 } else { 200 });
+    Ok(builder
+        .status(status)
+        .body(Full::new(Bytes::copy_from_slice(body)))?)
+}
+
+struct Server {
+    opts: WagiOptions,
+    engine: Engine,
+    pre: InstancePre<HostState>,
+    run_name: String,
+    local: SocketAddr,
+    next_id: AtomicU64,
+}
+
+impl Server {
+    /// Run the handler for one request; returns its exit code, stdout and stderr.
+    fn invoke(&self, req: &Request) -> Result<(i32, Bytes, Bytes)> {
+        let stdout = MemoryOutputPipe::new(OUTPUT_CAPACITY);
+        let stderr = MemoryOutputPipe::new(OUTPUT_CAPACITY);
+        let mut builder = host::wasi_builder();
+        builder
+            .args(&cgi_args(req))
+            .envs(&cgi_env(&self.opts, self.local, req))
+            .stdin(MemoryInputPipe::new(req.body.clone()))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone());
+
+        let mut store = host::wasi_store(&self.engine, builder.build());
+        let instance = self.pre.instantiate(&mut store)?;
+        let func = host::find_func(&instance, &mut store, &self.run_name)?;
+        let exit_code = run::call_run(&func, &mut store, &self.run_name)?;
+        drop(store);
+        Ok((exit_code, stdout.contents(), stderr.contents()))
+    }
+
+    fn respond(&self, req: &Request) -> Result<hyper::Response<Full<Bytes>>> {
+        let (exit_code, stdout, stderr) = self.invoke(req)?;
+        if !stderr.is_empty() {
+            eprint!("{}", String::from_utf8_lossy(&stderr));
+        }
+        if exit_code != 0 {
+            bail!("handler exited with code {}", exit_code);
+        }
+        parse_cgi_response(&stdout)
+    }
+
+    async fn handle(
+        self: Arc<Self>,
+        req: hyper::Request<hyper::body::Incoming>,
+        remote: SocketAddr,
+    ) -> Result<hyper::Response<Full<Bytes>>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let start = Instant::now();
+        let (parts, body) = req.into_parts();
+        let req = Request {
+            method: parts.method.to_string(),
+            uri: parts.uri,
+            headers: parts
+                .headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), String::from_utf8_lossy(v.as_bytes()).into_owned()))
+                .collect(),
+            body: body.collect().await?.to_bytes(),
+            remote,
+        };
+
+        // Handlers run synchronously; keep them off the accept loop.
+        let server = self.clone();
+        let (req, result) = tokio::task::spawn_blocking(move || {
+            let result = if route_matches(&server.opts.route, req.uri.path()) {
+                server.respond(&req)
+            } else {
+                hyper::Response::builder()
+                    .status(404)
+                    .body(Full::new(Bytes::from_static(b"Not Found\n")))
+                    .map_err(Into::into)
+            };
+            (req, result)
+        })
+        .await?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                println!("[{}] {} {} -> error: {:?}", id, req.method, req.uri, e);
+                hyper::Response::builder()
+                    .status(500)
+                    .body(Full::new(Bytes::from(format!("{:#}\n", e))))?
+            }
+        };
+        println!(
+            "[{}] {} {} -> {} ({:.2} ms)",
+            id,
+            req.method,
+            req.uri,
+            resp.status().as_u16(),
+            elapsed
+        );
+        Ok(resp)
+    }
+}
+
+pub fn run_wagi(args: &[String]) -> Result<()> {
+    let opts = parse_options(args)?;
+    host::reject_exit_time_flags("wagi")?;
+
+    let engine = Engine::new(&host::component_config())?;
+    println!("Loading component: {}", opts.component_path);
+    let component = Component::from_file(&engine, &opts.component_path)?;
+    let run_name = format!("{}#run", run::run_export(&engine, &component)?);
+    let mut linker = host::wasi_linker(&engine)?;
+    policy::apply(&mut linker, &engine, &component)?;
+    bridge::check(&linker, &engine, &component)?;
+    let pre = linker.instantiate_pre(&component)?;
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async move {
+            let listener = TcpListener::bind(opts.addr).await?;
+            let local = listener.local_addr()?;
+            println!("Serving WAGI route {} on http://{}/", opts.route, local);
+            let server = Arc::new(Server {
+                opts,
+                engine,
+                pre,
+                run_name,
+                local,
+                next_id: AtomicU64::new(0),
+            });
+            loop {
+                let (stream, remote) = listener.accept().await?;
+                let server = server.clone();
+                tokio::task::spawn(async move {
+                    let service = service_fn(move |req| server.clone().handle(req, remote));
+                    if let Err(e) = http1::Builder::new()
+                        .keep_alive(true)
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        eprintln!("connection error: {:?}", e);
+                    }
+                });
+            }
+        })
+}
@@ -0,0 +1,260 @@
+// Decoded WIT surface of a component
+//
+// The world a component decodes to, as nested maps that can be compared
+// item by item: import or export name, then its kind (interface, func or
+// type), then each item of it by name, then that item's type printed as WIT.
+// Named types print as their qualified name, so two components agree when
+// they declare the same items with the same types, whatever order the types
+// were defined in.
+//
+// componentize-diff compares components with it, and the differential test
+// harnesses include this file through tests/common.
+
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use wit_component::DecodedWasm;
+use wit_parser::{
+    Function, Handle, Resolve, Results, Type, TypeDefKind, TypeId, TypeOwner, WorldId, WorldItem,
+    WorldKey,
+};
+
+/// One import or export of a world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// `interface`, `func` or `type`.
+    pub kind: &'static str,
+    /// WIT type of every item, by name. A func or type entry has a single
+    /// item under its own name.
+    pub items: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Surface {
+    pub imports: BTreeMap<String, Entry>,
+    pub exports: BTreeMap<String, Entry>,
+}
+
+impl Surface {
+    /// The surface of a component binary's world.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        match wit_component::decode(bytes)? {
+            DecodedWasm::Component(resolve, world) => Ok(Self::of_world(&resolve, world)),
+            DecodedWasm::WitPackage(..) => bail!("not a component"),
+        }
+    }
+
+    pub fn of_world(resolve: &Resolve, world: WorldId) -> Self {
+        let world = &resolve.worlds[world];
+        let entries = |items: Vec<(&WorldKey, &WorldItem)>| {
+            items
+                .into_iter()
+                .map(|(key, item)| (resolve.name_world_key(key), entry(resolve, item)))
+                .collect()
+        };
+        Surface {
+            imports: entries(world.imports.iter().collect()),
+            exports: entries(world.exports.iter().collect()),
+        }
+    }
+
+    /// How `actual` differs from `self`, one line per difference: `-` for
+    /// what `actual` lacks, `+` for what only `actual` has, and `~` for an
+    /// entry or item both have with a different kind or type.
+    pub fn diff(&self, actual: &Surface) -> Vec<String> {
+        let mut diff = Vec::new();
+        for (direction, expected, actual) in [
+            ("import", &self.imports, &actual.imports),
+            ("export", &self.exports, &actual.exports),
+        ] {
+            for (name, want) in expected {
+                let Some(got) = actual.get(name) else {
+                    diff.push(format!("- {} {} ({})", direction, name, want.kind));
+                    continue;
+                };
+                if want.kind != got.kind {
+                    diff.push(format!(
+                        "~ {} {}: {}, found {}",
+                        direction, name, want.kind, got.kind
+                    ));
+                    continue;
+                }
+                for (item, ty) in &want.items {
+                    match got.items.get(item) {
+                        None => diff.push(format!("- {} {}: {}: {}", direction, name, item, ty)),
+                        Some(found) if found != ty => diff.push(format!(
+                            "~ {} {}: {}: {}, found {}",
+                            direction, name, item, ty, found
+                        )),
+                        Some(_) => {}
+                    }
+                }
+                for (item, ty) in &got.items {
+                    if !want.items.contains_key(item) {
+                        diff.push(format!("+ {} {}: {}: {}", direction, name, item, ty));
+                    }
+                }
+            }
+            for (name, got) in actual {
+                if !expected.contains_key(name) {
+                    diff.push(format!("+ {} {} ({})", direction, name, got.kind));
+                }
+            }
+        }
+        diff
+    }
+}
+
+fn entry(resolve: &Resolve, item: &WorldItem) -> Entry {
+    match item {
+        WorldItem::Interface { id, .. } => {
+            let interface = &resolve.interfaces[*id];
+            let types = interface
+                .types
+                .iter()
+                .map(|(name, ty)| (name.clone(), define(resolve, *ty)));
+            let funcs = interface
+                .functions
+                .iter()
+                .map(|(name, func)| (name.clone(), func_type(resolve, func)));
+            Entry {
+                kind: "interface",
+                items: types.chain(funcs).collect(),
+            }
+        }
+        WorldItem::Function(func) => Entry {
+            kind: "func",
+            items: BTreeMap::from([(func.name.clone(), func_type(resolve, func))]),
+        },
+        WorldItem::Type(ty) => Entry {
+            kind: "type",
+            items: BTreeMap::from([(
+                resolve.types[*ty].name.clone().unwrap_or_default(),
+                define(resolve, *ty),
+            )]),
+        },
+    }
+}
+
+fn func_type(resolve: &Resolve, func: &Function) -> String {
+    let params: Vec<String> = func
+        .params
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, type_name(resolve, ty)))
+        .collect();
+    let results = match &func.results {
+        Results::Anon(ty) => format!(" -> {}", type_name(resolve, ty)),
+        Results::Named(named) if named.is_empty() => String::new(),
+        Results::Named(named) => {
+            let named: Vec<String> = named
+                .iter()
+                .map(|(name, ty)| format!("{}: {}", name, type_name(resolve, ty)))
+                .collect();
+            format!(" -> ({})", named.join(", "))
+        }
+    };
+    format!("func({}){}", params.join(", "), results)
+}
+
+/// The definition of a type: its structure, not its name.
+fn define(resolve: &Resolve, id: TypeId) -> String {
+    let ty = |ty: &Type| type_name(resolve, ty);
+    match &resolve.types[id].kind {
+        TypeDefKind::Record(record) => {
+            let fields: Vec<String> = record
+                .fields
+                .iter()
+                .map(|field| format!("{}: {}", field.name, ty(&field.ty)))
+                .collect();
+            format!("record {{ {} }}", fields.join(", "))
+        }
+        TypeDefKind::Variant(variant) => {
+            let cases: Vec<String> = variant
+                .cases
+                .iter()
+                .map(|case| match &case.ty {
+                    Some(t) => format!("{}({})", case.name, ty(t)),
+                    None => case.name.clone(),
+                })
+                .collect();
+            format!("variant {{ {} }}", cases.join(", "))
+        }
+        TypeDefKind::Enum(enum_) => {
+            let cases: Vec<&str> = enum_.cases.iter().map(|case| case.name.as_str()).collect();
+            format!("enum {{ {} }}", cases.join(", "))
+        }
+        TypeDefKind::Flags(flags) => {
+            let flags: Vec<&str> = flags.flags.iter().map(|flag| flag.name.as_str()).collect();
+            format!("flags {{ {} }}", flags.join(", "))
+        }
+        TypeDefKind::Resource => "resource".to_string(),
+        kind => format!("type {}", anonymous(resolve, kind)),
+    }
+}
+
+/// How a type is written where it is used: named types by their qualified
+/// name, anonymous ones by their structure.
+fn type_name(resolve: &Resolve, ty: &Type) -> String {
+    let id = match ty {
+        Type::Bool => return "bool".to_string(),
+        Type::U8 => return "u8".to_string(),
+        Type::U16 => return "u16".to_string(),
+        Type::U32 => return "u32".to_string(),
+        Type::U64 => return "u64".to_string(),
+        Type::S8 => return "s8".to_string(),
+        Type::S16 => return "s16".to_string(),
+        Type::S32 => return "s32".to_string(),
+        Type::S64 => return "s64".to_string(),
+        Type::F32 => return "f32".to_string(),
+        Type::F64 => return "f64".to_string(),
+        Type::Char => return "char".to_string(),
+        Type::String => return "string".to_string(),
+        Type::Id(id) => *id,
+    };
+    let def = &resolve.types[id];
+    match &def.name {
+        Some(name) => match def.owner {
+            TypeOwner::Interface(interface) => match resolve.id_of(interface) {
+                Some(interface) => format!("{}.{}", interface, name),
+                None => name.clone(),
+            },
+            _ => name.clone(),
+        },
+        None => anonymous(resolve, &def.kind),
+    }
+}
+
+fn anonymous(resolve: &Resolve, kind: &TypeDefKind) -> String {
+    let ty = |ty: &Type| type_name(resolve, ty);
+    let or_blank = |t: &Option<Type>| t.as_ref().map_or("_".to_string(), ty);
+    match kind {
+        TypeDefKind::Type(t) => ty(t),
+        TypeDefKind::List(t) => format!("list<{}>", ty(t)),
+        TypeDefKind::Option(t) => format!("option<{}>", ty(t)),
+        TypeDefKind::Result(result) => match (&result.ok, &result.err) {
+            (None, None) => "result".to_string(),
+            (Some(ok), None) => format!("result<{}>", ty(ok)),
+            (ok, Some(err)) => format!("result<{}, {}>", or_blank(ok), ty(err)),
+        },
+        TypeDefKind::Tuple(tuple) => {
+            let types: Vec<String> = tuple.types.iter().map(ty).collect();
+            format!("tuple<{}>", types.join(", "))
+        }
+        TypeDefKind::Handle(Handle::Own(id)) => ty(&Type::Id(*id)),
+        TypeDefKind::Handle(Handle::Borrow(id)) => format!("borrow<{}>", ty(&Type::Id(*id))),
+        TypeDefKind::Future(None) => "future".to_string(),
+        TypeDefKind::Future(Some(t)) => format!("future<{}>", ty(t)),
+        TypeDefKind::Stream(stream) => {
+            format!(
+                "stream<{}, {}>",
+                or_blank(&stream.element),
+                or_blank(&stream.end)
+            )
+        }
+        TypeDefKind::Record(_)
+        | TypeDefKind::Variant(_)
+        | TypeDefKind::Enum(_)
+        | TypeDefKind::Flags(_)
+        | TypeDefKind::Resource => format!("<anonymous {}>", kind.as_str()),
+        TypeDefKind::Unknown => "<unknown>".to_string(),
+    }
+}
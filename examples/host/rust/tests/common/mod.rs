@@ -0,0 +1,272 @@
+// Helpers shared by the differential harnesses under tests/
+//
+// Each harness includes this file with `#[path = "../common/mod.rs"] mod common;`
+// and uses only part of it.
+//
+// moon-component is found through MOON_COMPONENT_BIN, then PATH, then the
+// repository's native release build.
+
+#![allow(dead_code)]
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+// The structured surface componentize-diff compares components with.
+#[path = "../../src/wit_surface.rs"]
+mod wit_surface;
+#[allow(unused_imports)]
+pub use wit_surface::Surface;
+
+pub fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../..")
+}
+
+pub fn on_path(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|bin| bin.is_file())
+}
+
+pub fn moon_component_bin() -> Option<PathBuf> {
+    if let Some(bin) = std::env::var_os("MOON_COMPONENT_BIN") {
+        return Some(bin.into());
+    }
+    if let Some(bin) = on_path("moon-component") {
+        return Some(bin);
+    }
+    let bin = repo_root().join("_build/native/release/build/src/cmd/moon-component/moon-component.exe");
+    bin.is_file().then_some(bin)
+}
+
+/// moon-component, or `None` after printing that the run is skipped.
+pub fn moon_component_or_skip() -> Option<PathBuf> {
+    let bin = moon_component_bin();
+    if bin.is_none() {
+        eprintln!(
+            "moon-component not found; skipping. Build it with `moon build --target native --release` or set MOON_COMPONENT_BIN."
+        );
+    }
+    bin
+}
+
+/// The harness's arguments, after the binary name.
+pub struct Args(std::iter::Skip<std::env::Args>);
+
+impl Args {
+    pub fn new() -> Self {
+        Args(std::env::args().skip(1))
+    }
+
+    /// The argument after `flag`.
+    pub fn value(&mut self, flag: &str) -> Result<String> {
+        self.0.next().with_context(|| format!("{} needs a value", flag))
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.0.next()
+    }
+}
+
+/// Flags cargo's test runner may forward (e.g. --nocapture); harnesses
+/// ignore them.
+pub fn is_runner_flag(arg: &str) -> bool {
+    arg.starts_with('-')
+}
+
+/// Component binaries start with the layer-1 component preamble.
+pub fn is_component(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && bytes[..4] == *b"\0asm" && bytes[4..8] == [0x0d, 0x00, 0x01, 0x00]
+}
+
+pub fn collect_files(dir: &Path, ext: &str, out: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if matches!(&*name, ".zig-cache" | "node_modules" | "target" | "_build") {
+                continue;
+            }
+            collect_files(&path, ext, out)?;
+        } else if path.extension().is_some_and(|e| e == ext) {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Top-level `(component ...)` forms of a .wast script. Components nested in
+/// assertions are skipped: most of them are invalid on purpose.
+pub fn wast_components(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut forms = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b';' if bytes.get(i + 1) == Some(&b';') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'(' if bytes.get(i + 1) == Some(&b';') => {
+                let mut nesting = 0;
+                while i + 1 < bytes.len() {
+                    if bytes[i] == b'(' && bytes[i + 1] == b';' {
+                        nesting += 1;
+                        i += 2;
+                    } else if bytes[i] == b';' && bytes[i + 1] == b')' {
+                        nesting -= 1;
+                        i += 2;
+                        if nesting == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                continue;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'(' => {
+                if depth == 0 {
+                    start = i;
+                }
+                depth += 1;
+            }
+            b')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let form = &text[start..=i];
+                    let head = form[1..].trim_start();
+                    let directive = ["component definition", "component instance"]
+                        .iter()
+                        .any(|d| head.starts_with(d));
+                    if head.starts_with("component") && !directive {
+                        forms.push(form);
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    forms
+}
+
+/// Every component binary under examples/, then every top-level component of
+/// the component-model suite's .wast scripts, written to `work/wast`: `(name,
+/// path, bytes)`, named by repository path (`script.wast#N` for a script's
+/// Nth component). Script components that do not parse go to `skipped`.
+pub fn collect_components(
+    work: &Path,
+    skipped: &mut Vec<(String, String)>,
+) -> Result<Vec<(String, PathBuf, Vec<u8>)>> {
+    let root = repo_root();
+    let mut components = Vec::new();
+
+    let mut files = Vec::new();
+    collect_files(&root.join("examples"), "wasm", &mut files)?;
+    files.sort();
+    for path in files {
+        let bytes = std::fs::read(&path)?;
+        if !is_component(&bytes) {
+            continue;
+        }
+        let name = path
+            .strip_prefix(&root)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        components.push((name, path, bytes));
+    }
+
+    let suite = root.join("tests/component-model");
+    let mut scripts = Vec::new();
+    if suite.exists() {
+        collect_files(&suite, "wast", &mut scripts)?;
+    }
+    scripts.sort();
+    let wast_dir = work.join("wast");
+    std::fs::create_dir_all(&wast_dir)?;
+    for script in scripts {
+        let text = std::fs::read_to_string(&script)?;
+        let rel = script
+            .strip_prefix(&root)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        for (index, form) in wast_components(&text).into_iter().enumerate() {
+            let name = format!("{}#{}", rel, index);
+            let bytes = match wat::parse_str(form) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    skipped.push((name, format!("wat: {}", err)));
+                    continue;
+                }
+            };
+            let path = wast_dir.join(format!("{}.wasm", name.replace(['/', '#'], "_")));
+            std::fs::write(&path, &bytes)?;
+            components.push((name, path, bytes));
+        }
+    }
+    Ok(components)
+}
+
+fn show(v: &Value) -> String {
+    let text = v.to_string();
+    if text.chars().count() > 120 {
+        format!("{}...", text.chars().take(117).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// Structural differences between two normalized JSON documents, `expected`
+/// from the reference implementation and `actual` from moon-component: nodes
+/// only `expected` has are `missing`, nodes only `actual` has `unexpected`.
+pub fn json_diff(path: &str, expected: &Value, actual: &Value, out: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(e), Value::Object(a)) => {
+            for (k, ev) in e {
+                let at = format!("{}.{}", path, k);
+                match a.get(k) {
+                    Some(av) => json_diff(&at, ev, av, out),
+                    None => out.push(format!("{}: missing (expected {})", at, show(ev))),
+                }
+            }
+            for (k, av) in a {
+                if !e.contains_key(k) {
+                    out.push(format!("{}.{}: unexpected {}", path, k, show(av)));
+                }
+            }
+        }
+        (Value::Array(e), Value::Array(a)) => {
+            for (i, (ev, av)) in e.iter().zip(a).enumerate() {
+                json_diff(&format!("{}[{}]", path, i), ev, av, out);
+            }
+            for (i, ev) in e.iter().enumerate().skip(a.len()) {
+                out.push(format!("{}[{}]: missing (expected {})", path, i, show(ev)));
+            }
+            for (i, av) in a.iter().enumerate().skip(e.len()) {
+                out.push(format!("{}[{}]: unexpected {}", path, i, show(av)));
+            }
+        }
+        _ if expected != actual => {
+            out.push(format!("{}: {}, expected {}", path, show(actual), show(expected)))
+        }
+        _ => {}
+    }
+}
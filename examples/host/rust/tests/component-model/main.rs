@@ -0,0 +1,327 @@
+// Component-model reference suite (tests/component-model/**/*.wast) run in-process
+//
+//   cargo test --test component-model
+//   cargo test --test component-model -- --filter async --json results.json
+//   cargo test --test component-model -- --bless
+//
+// Each top-level directory of the suite has an expected-failure list in
+// `expected-failures/<dir>.txt` (one file name per line, `#` comments). The run
+// fails on any unexpected failure, and on any unexpected pass so the lists stay
+// current; `--bless` rewrites the lists from the actual results instead.
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use wasmtime::{Config, Engine, Store};
+use wasmtime_wast::{SpectestConfig, WastContext};
+
+/// Same toggles as `tools/component-model-tests/run.py`.
+const DEFAULT_FLAGS: &[&str] = &[
+    "component-model-async=y",
+    "component-model-async-builtins=y",
+    "component-model-threading=y",
+    "component-model-async-stackful=y",
+    "exceptions=y",
+];
+
+struct Options {
+    filters: Vec<String>,
+    limit: usize,
+    flags: Vec<String>,
+    json: Option<PathBuf>,
+    bless: bool,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        filters: Vec::new(),
+        limit: 0,
+        flags: Vec::new(),
+        json: None,
+        bless: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => opts.filters.push(args.next().context("--filter needs a value")?),
+            "--limit" => opts.limit = args.next().context("--limit needs a value")?.parse()?,
+            "-W" | "--flag" => opts.flags.push(args.next().context("-W needs a value")?),
+            "--json" => opts.json = Some(args.next().context("--json needs a file")?.into()),
+            "--bless" => opts.bless = true,
+            // Flags cargo's test runner may forward (e.g. --nocapture).
+            _ if arg.starts_with('-') => {}
+            _ => opts.filters.push(arg),
+        }
+    }
+    if opts.flags.is_empty() {
+        opts.flags = DEFAULT_FLAGS.iter().map(|s| s.to_string()).collect();
+    }
+    Ok(opts)
+}
+
+/// Apply a `name=y|n` toggle in the `wasmtime -W` spelling. Returns false for
+/// toggles this wasmtime version does not have.
+fn apply_flag(config: &mut Config, flag: &str) -> Result<bool> {
+    let (name, value) = flag.split_once('=').unwrap_or((flag, "y"));
+    let on = match value {
+        "y" | "yes" | "true" => true,
+        "n" | "no" | "false" => false,
+        _ => bail!("invalid value in -W {}", flag),
+    };
+    match name {
+        "component-model" => config.wasm_component_model(on),
+        "component-model-more-flags" => config.wasm_component_model_more_flags(on),
+        "component-model-multiple-returns" => config.wasm_component_model_multiple_returns(on),
+        "gc" => config.wasm_gc(on),
+        "function-references" => config.wasm_function_references(on),
+        "memory64" => config.wasm_memory64(on),
+        "multi-memory" => config.wasm_multi_memory(on),
+        "tail-call" => config.wasm_tail_call(on),
+        "threads" => config.wasm_threads(on),
+        "custom-page-sizes" => config.wasm_custom_page_sizes(on),
+        "wide-arithmetic" => config.wasm_wide_arithmetic(on),
+        _ => return Ok(false),
+    };
+    Ok(true)
+}
+
+fn suite_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../../tests/component-model")
+}
+
+fn expected_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/component-model/expected-failures")
+}
+
+fn collect_tests(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_tests(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "wast") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn load_expected(group: &str) -> Result<BTreeSet<String>> {
+    let path = expected_dir().join(format!("{}.txt", group));
+    if !path.exists() {
+        return Ok(BTreeSet::new());
+    }
+    Ok(std::fs::read_to_string(&path)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+fn write_expected(group: &str, failures: &BTreeSet<String>) -> Result<()> {
+    let path = expected_dir().join(format!("{}.txt", group));
+    if failures.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        return Ok(());
+    }
+    // Keep the leading comment that explains the list.
+    let header: String = std::fs::read_to_string(&path)
+        .unwrap_or_default()
+        .lines()
+        .take_while(|l| l.starts_with('#'))
+        .map(|l| format!("{}\n", l))
+        .collect();
+    let body: String = failures.iter().map(|f| format!("{}\n", f)).collect();
+    std::fs::write(&path, header + &body)?;
+    Ok(())
+}
+
+fn run_wast(config: &Config, path: &Path) -> Result<()> {
+    let engine = Engine::new(config)?;
+    let mut wast = WastContext::new(Store::new(&engine, ()));
+    wast.register_spectest(&SpectestConfig {
+        use_shared_memory: true,
+        suppress_prints: true,
+    })?;
+    wast.run_file(path)
+}
+
+/// `run_wast`, with a panic in the parser or runtime reported as this file's
+/// failure instead of ending the run.
+fn run_isolated(config: &Config, path: &Path) -> std::result::Result<(), String> {
+    match std::panic::catch_unwind(AssertUnwindSafe(|| run_wast(config, path))) {
+        Ok(result) => result.map_err(|e| format!("{:?}", e)),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "non-string panic payload".to_string());
+            Err(format!("panicked: {}", message))
+        }
+    }
+}
+
+struct Outcome {
+    group: String,
+    name: String,
+    error: Option<String>,
+    expected_fail: bool,
+}
+
+impl Outcome {
+    fn status(&self) -> &'static str {
+        match (self.error.is_some(), self.expected_fail) {
+            (false, false) => "pass",
+            (true, true) => "xfail",
+            (true, false) => "fail",
+            (false, true) => "xpass",
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let root = suite_root();
+    if !root.exists() {
+        eprintln!("component-model tests not found. Run: tools/component-model-tests/update.sh");
+        std::process::exit(2);
+    }
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    for flag in &opts.flags {
+        if !apply_flag(&mut config, flag)? {
+            eprintln!("warning: -W {} is not supported by this wasmtime; ignored", flag);
+        }
+    }
+
+    let mut tests = Vec::new();
+    collect_tests(&root, &mut tests)?;
+    tests.sort();
+    if !opts.filters.is_empty() {
+        tests.retain(|t| {
+            let s = t.to_string_lossy();
+            opts.filters.iter().any(|f| s.contains(f.as_str()))
+        });
+    }
+    if opts.limit > 0 {
+        tests.truncate(opts.limit);
+    }
+    if tests.is_empty() {
+        println!("No tests matched.");
+        std::process::exit(1);
+    }
+
+    let mut expected: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut outcomes = Vec::new();
+    for test in &tests {
+        let rel = test.strip_prefix(&root)?;
+        let group = rel
+            .components()
+            .next()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = rel
+            .strip_prefix(&group)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if !expected.contains_key(&group) {
+            expected.insert(group.clone(), load_expected(&group)?);
+        }
+        let expected_fail = expected[&group].contains(&name);
+        let error = run_isolated(&config, test).err();
+        let outcome = Outcome {
+            group,
+            name,
+            error,
+            expected_fail,
+        };
+        match outcome.status() {
+            "fail" => println!(
+                "FAIL: {}/{}\n{}",
+                outcome.group,
+                outcome.name,
+                outcome.error.as_deref().unwrap_or("")
+            ),
+            "xpass" => println!(
+                "XPASS: {}/{} (listed in expected-failures/{}.txt)",
+                outcome.group, outcome.name, outcome.group
+            ),
+            _ => {}
+        }
+        outcomes.push(outcome);
+    }
+
+    println!("\n{:<16} {:>6} {:>6} {:>6} {:>6}", "Directory", "pass", "xfail", "fail", "xpass");
+    println!("{}", "-".repeat(44));
+    for group in expected.keys() {
+        let count = |status: &str| {
+            outcomes
+                .iter()
+                .filter(|o| &o.group == group && o.status() == status)
+                .count()
+        };
+        println!(
+            "{:<16} {:>6} {:>6} {:>6} {:>6}",
+            group,
+            count("pass"),
+            count("xfail"),
+            count("fail"),
+            count("xpass")
+        );
+    }
+
+    if let Some(path) = &opts.json {
+        let results: Vec<_> = outcomes
+            .iter()
+            .map(|o| {
+                json!({
+                    "directory": o.group,
+                    "test": o.name,
+                    "status": o.status(),
+                    "error": o.error,
+                })
+            })
+            .collect();
+        std::fs::write(path, serde_json::to_string_pretty(&json!({ "results": results }))?)?;
+        println!("\nResults written to {}", path.display());
+    }
+
+    if opts.bless {
+        for (group, listed) in &expected {
+            // Only the tests that ran are re-evaluated; filtered-out entries stay.
+            let ran: BTreeSet<&String> = outcomes
+                .iter()
+                .filter(|o| &o.group == group)
+                .map(|o| &o.name)
+                .collect();
+            let mut failures: BTreeSet<String> =
+                listed.iter().filter(|n| !ran.contains(n)).cloned().collect();
+            failures.extend(
+                outcomes
+                    .iter()
+                    .filter(|o| &o.group == group && o.error.is_some())
+                    .map(|o| o.name.clone()),
+            );
+            write_expected(group, &failures)?;
+        }
+        println!("\nExpected-failure lists updated");
+        return Ok(());
+    }
+
+    let unexpected = outcomes
+        .iter()
+        .filter(|o| matches!(o.status(), "fail" | "xpass"))
+        .count();
+    if unexpected > 0 {
+        println!("\n{} unexpected result(s) out of {} test(s)", unexpected, outcomes.len());
+        std::process::exit(1);
+    }
+    println!("\nAll {} test(s) matched expectations", outcomes.len());
+    Ok(())
+}
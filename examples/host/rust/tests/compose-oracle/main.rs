@@ -0,0 +1,361 @@
+// Differential composition: `moon-component plug`/`compose` against wac-graph
+//
+//   cargo test --test compose-oracle
+//   cargo test --test compose-oracle -- --filter wac --keep /tmp/compose-oracle
+//
+// Each example composition is performed twice: by moon-component and by the
+// `wac-graph` crate (`wac_graph::plug` for sockets and plugs, a wac-parser
+// resolution for .wac scripts). The run fails when moon-component rejects a
+// composition wac-graph accepts, when the two outputs differ in their import
+// or export surface (each import and export compared item by item as decoded
+// WIT, so type signatures count), or when a smoke call through `rust-host
+// profile` fails or returns different results on the two outputs.
+// Compositions without an exported function are only instantiated.
+//
+//   - core-module: examples/core-module/socket.wasm plugged with plug.wasm
+//   - compose:     the bundle of examples/compose/moon-component.toml; its
+//                  components come from `_build/bundle/`, and the case is
+//                  skipped until they are built
+//   - wac:         examples/wac/script.wac with the packages in its deps/
+//
+// moon-component is found as described in tests/common/mod.rs; without it the
+// run is skipped.
+
+use anyhow::{anyhow, bail, Context, Result};
+use indexmap::IndexMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wac_graph::types::{BorrowedPackageKey, Package};
+use wac_graph::{CompositionGraph, EncodeOptions};
+
+#[path = "../common/mod.rs"]
+mod common;
+
+use common::{is_runner_flag, moon_component_or_skip, repo_root, Args, Surface};
+
+struct Options {
+    filters: Vec<String>,
+    keep: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        filters: Vec::new(),
+        keep: None,
+    };
+    let mut args = Args::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => opts.filters.push(args.value("--filter")?),
+            "--keep" => opts.keep = Some(args.value("--keep")?.into()),
+            _ if is_runner_flag(&arg) => {}
+            _ => opts.filters.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+enum Inputs {
+    /// A socket and the plugs for its imports, each with a package name.
+    Plug {
+        socket: (String, PathBuf),
+        plugs: Vec<(String, PathBuf)>,
+    },
+    /// A .wac script; its packages live in `deps/<namespace>/<name>.wasm`.
+    Wac { script: PathBuf },
+}
+
+struct Case {
+    name: &'static str,
+    inputs: Inputs,
+    /// Export to smoke-call as `interface#func`, with its arguments.
+    call: Option<(&'static str, &'static [&'static str])>,
+}
+
+/// The bundle described by a moon-component.toml: the entry component is the
+/// socket and every dependency a plug, at the paths `bundle` builds them to.
+fn bundle_inputs(dir: &Path) -> Result<Inputs> {
+    let text = std::fs::read_to_string(dir.join("moon-component.toml"))?;
+    let config: toml::Table = toml::from_str(&text)?;
+    let bundle = dir.join("_build/bundle");
+    let entry = bundle.join("entry.wasm");
+    let mut plugs = Vec::new();
+    let deps = config
+        .get("dependencies")
+        .and_then(|d| d.as_table())
+        .ok_or_else(|| anyhow!("moon-component.toml has no [dependencies]"))?;
+    for name in deps.keys() {
+        let (namespace, package) = name
+            .split_once(':')
+            .ok_or_else(|| anyhow!("dependency `{}` is not namespace:name", name))?;
+        plugs.push((
+            name.clone(),
+            bundle.join("deps").join(namespace).join(format!("{}.wasm", package)),
+        ));
+    }
+    let socket_name = config
+        .get("bundle")
+        .and_then(|b| b.get("name"))
+        .and_then(|n| n.as_str())
+        .unwrap_or("bundle:entry")
+        .replace('/', ":");
+    Ok(Inputs::Plug {
+        socket: (socket_name, entry),
+        plugs,
+    })
+}
+
+fn cases() -> Result<Vec<Case>> {
+    let examples = repo_root().join("examples");
+    let core = examples.join("core-module");
+    Ok(vec![
+        Case {
+            name: "core-module",
+            inputs: Inputs::Plug {
+                socket: ("example:socket".to_string(), core.join("socket.wasm")),
+                plugs: vec![("example:plug".to_string(), core.join("plug.wasm"))],
+            },
+            call: None,
+        },
+        Case {
+            name: "compose",
+            inputs: bundle_inputs(&examples.join("compose"))?,
+            call: Some(("example:app/api#calc-add", &["2", "3"])),
+        },
+        Case {
+            name: "wac",
+            inputs: Inputs::Wac {
+                script: examples.join("wac/script.wac"),
+            },
+            call: Some(("greet", &[])),
+        },
+    ])
+}
+
+fn missing_inputs(inputs: &Inputs) -> Vec<PathBuf> {
+    let paths: Vec<&PathBuf> = match inputs {
+        Inputs::Plug { socket, plugs } => std::iter::once(&socket.1)
+            .chain(plugs.iter().map(|(_, path)| path))
+            .collect(),
+        Inputs::Wac { script } => vec![script],
+    };
+    paths.into_iter().filter(|p| !p.is_file()).cloned().collect()
+}
+
+fn wac_graph_plug(socket: &(String, PathBuf), plugs: &[(String, PathBuf)]) -> Result<Vec<u8>> {
+    let mut graph = CompositionGraph::new();
+    let package = Package::from_file(&socket.0, None, &socket.1, graph.types_mut())?;
+    let socket = graph.register_package(package)?;
+    let mut ids = Vec::new();
+    for (name, path) in plugs {
+        let package = Package::from_file(name, None, path, graph.types_mut())?;
+        ids.push(graph.register_package(package)?);
+    }
+    wac_graph::plug(&mut graph, ids, socket)?;
+    Ok(graph.encode(EncodeOptions::default())?)
+}
+
+/// Every component under `deps/`, keyed by the package name its path spells.
+fn wac_packages(deps: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut packages = Vec::new();
+    if !deps.is_dir() {
+        return Ok(packages);
+    }
+    for namespace in std::fs::read_dir(deps)? {
+        let namespace = namespace?.path();
+        if !namespace.is_dir() {
+            continue;
+        }
+        for file in std::fs::read_dir(&namespace)? {
+            let file = file?.path();
+            if file.extension().is_some_and(|e| e == "wasm") {
+                let name = format!(
+                    "{}:{}",
+                    namespace.file_name().unwrap().to_string_lossy(),
+                    file.file_stem().unwrap().to_string_lossy()
+                );
+                packages.push((name, std::fs::read(&file)?));
+            }
+        }
+    }
+    packages.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(packages)
+}
+
+fn wac_parser_compose(script: &Path) -> Result<Vec<u8>> {
+    let source = std::fs::read_to_string(script)?;
+    let document = wac_parser::Document::parse(&source).map_err(|e| anyhow!("{}", e))?;
+    let deps = wac_packages(&script.with_file_name("deps"))?;
+    let packages: IndexMap<_, _> = deps
+        .iter()
+        .map(|(name, bytes)| (BorrowedPackageKey { name, version: None }, bytes.clone()))
+        .collect();
+    let resolution = document.resolve(packages).map_err(|e| anyhow!("{}", e))?;
+    resolution
+        .encode(EncodeOptions::default())
+        .map_err(|e| anyhow!("{}", e))
+}
+
+fn oracle(case: &Case) -> Result<Vec<u8>> {
+    match &case.inputs {
+        Inputs::Plug { socket, plugs } => wac_graph_plug(socket, plugs),
+        Inputs::Wac { script } => wac_parser_compose(script),
+    }
+}
+
+fn moon_component(bin: &Path, case: &Case, out: &Path) -> Result<Vec<u8>> {
+    let mut cmd = Command::new(bin);
+    match &case.inputs {
+        Inputs::Plug { socket, plugs } => {
+            cmd.arg("plug").arg(&socket.1);
+            for (_, path) in plugs {
+                cmd.arg("--plug").arg(path);
+            }
+        }
+        Inputs::Wac { script } => {
+            cmd.arg("compose").arg(script);
+        }
+    }
+    let output = cmd
+        .arg("-o")
+        .arg(out)
+        .current_dir(repo_root())
+        .output()
+        .with_context(|| format!("failed to run {}", bin.display()))?;
+    if !output.status.success() {
+        bail!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    std::fs::read(out).with_context(|| format!("no output at {}", out.display()))
+}
+
+/// Call `export` through `rust-host profile` once and return what it printed
+/// as the result; without an export, instantiate the component instead.
+fn smoke(component: &Path, call: Option<(&str, &[&str])>, work: &Path) -> Result<String> {
+    let Some((export, args)) = call else {
+        let engine = wasmtime::Engine::default();
+        let component = wasmtime::component::Component::from_file(&engine, component)?;
+        let linker = wasmtime::component::Linker::<()>::new(&engine);
+        linker.instantiate(&mut wasmtime::Store::new(&engine, ()), &component)?;
+        return Ok("instantiated".to_string());
+    };
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-host"))
+        .arg("profile")
+        .arg(component)
+        .arg(export)
+        .args(args)
+        .arg("--iterations")
+        .arg("1")
+        .arg("--output")
+        .arg(work.join("profile.json"))
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim_end());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Last result: "))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("no result in rust-host output"))
+}
+
+/// Problems with one case; empty when moon-component matches wac-graph. Only
+/// a failure of wac-graph itself is an error.
+fn check(bin: &Path, case: &Case, work: &Path) -> Result<Vec<String>> {
+    let expected = oracle(case)?;
+    Ok(compare(bin, case, &expected, work).unwrap_or_else(|err| vec![format!("{:#}", err)]))
+}
+
+fn compare(bin: &Path, case: &Case, expected: &[u8], work: &Path) -> Result<Vec<String>> {
+    std::fs::create_dir_all(work)?;
+    let oracle_path = work.join("wac-graph.wasm");
+    std::fs::write(&oracle_path, expected)?;
+    let moon_path = work.join("moon-component.wasm");
+    let actual = match moon_component(bin, case, &moon_path) {
+        Ok(bytes) => bytes,
+        Err(err) => return Ok(vec![format!("moon-component failed: {:#}", err)]),
+    };
+
+    let mut problems = Vec::new();
+    let features = wasmparser::WasmFeatures::all();
+    if let Err(err) = wasmparser::Validator::new_with_features(features).validate_all(&actual) {
+        problems.push(format!("moon-component output is invalid: {}", err));
+        return Ok(problems);
+    }
+    let expected = Surface::decode(expected).context("decoding wac-graph output")?;
+    let actual = Surface::decode(&actual).context("decoding moon-component output")?;
+    for line in expected.diff(&actual) {
+        problems.push(format!("surface {}", line));
+    }
+    let want = smoke(&oracle_path, case.call, work).context("smoke call on wac-graph output")?;
+    match smoke(&moon_path, case.call, work) {
+        Ok(got) if got == want => {}
+        Ok(got) => problems.push(format!("smoke call: wac-graph {} / moon-component {}", want, got)),
+        Err(err) => problems.push(format!("smoke call failed: {:#}", err)),
+    }
+    Ok(problems)
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let Some(bin) = moon_component_or_skip() else {
+        return Ok(());
+    };
+
+    let work = match &opts.keep {
+        Some(dir) => dir.clone(),
+        None => std::env::temp_dir().join(format!("compose-oracle-{}", std::process::id())),
+    };
+    if work.exists() {
+        std::fs::remove_dir_all(&work)?;
+    }
+    let mut cases = cases()?;
+    if !opts.filters.is_empty() {
+        cases.retain(|c| opts.filters.iter().any(|f| c.name.contains(f.as_str())));
+    }
+    if cases.is_empty() {
+        println!("No cases matched.");
+        std::process::exit(1);
+    }
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for case in &cases {
+        let missing = missing_inputs(&case.inputs);
+        if !missing.is_empty() {
+            println!("SKIP: {} (missing {})", case.name, missing[0].display());
+            skipped += 1;
+            continue;
+        }
+        match check(&bin, case, &work.join(case.name)) {
+            Ok(problems) if problems.is_empty() => {
+                println!("PASS: {}", case.name);
+                passed += 1;
+            }
+            Ok(problems) => {
+                println!("FAIL: {}", case.name);
+                for line in &problems {
+                    println!("  {}", line);
+                }
+                failed += 1;
+            }
+            // The oracle itself could not compose: nothing to compare against.
+            Err(err) => {
+                println!("SKIP: {} (wac-graph: {:#})", case.name, err);
+                skipped += 1;
+            }
+        }
+    }
+    println!("\n{} passed, {} failed, {} skipped", passed, failed, skipped);
+
+    if opts.keep.is_none() {
+        let _ = std::fs::remove_dir_all(&work);
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
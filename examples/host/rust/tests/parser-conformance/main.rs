@@ -0,0 +1,351 @@
+// Component binary parser conformance: `moon-component inspect` against wasmparser
+//
+//   cargo test --test parser-conformance
+//   cargo test --test parser-conformance -- --filter resources --json results.json
+//   cargo test --test parser-conformance -- --keep /tmp/parser-conformance
+//
+// Every component under examples/ and every top-level component of
+// tests/component-model/**/*.wast is read by both parsers. The run fails
+// where they disagree on:
+//
+//   - the top-level sections (id and payload size, in order)
+//   - the number of core modules, core types, nested components, component
+//     instances and component types defined at the top level
+//   - the names and kinds of the component's imports and exports
+//
+// and where `moon-component inspect --reencode` produces a component that
+// wasmparser rejects, whose top-level shape differs from the original's, or
+// whose component type is not the original's (each a subtype of the other).
+//
+// moon-component is found as described in tests/common/mod.rs; without it the
+// run is skipped.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wasmparser::component_types::{ComponentTypeId, SubtypeCx};
+use wasmparser::types::Types;
+use wasmparser::{
+    ComponentExternalKind, ComponentTypeRef, Parser, Payload, Validator, WasmFeatures,
+};
+
+#[path = "../common/mod.rs"]
+mod common;
+
+use common::{collect_components, is_runner_flag, moon_component_or_skip, Args};
+
+struct Options {
+    filters: Vec<String>,
+    json: Option<PathBuf>,
+    keep: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        filters: Vec::new(),
+        json: None,
+        keep: None,
+    };
+    let mut args = Args::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => opts.filters.push(args.value("--filter")?),
+            "--json" => opts.json = Some(args.value("--json")?.into()),
+            "--keep" => opts.keep = Some(args.value("--keep")?.into()),
+            _ if is_runner_flag(&arg) => {}
+            _ => opts.filters.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+struct Input {
+    /// Where the component came from, for reports and `--filter`.
+    name: String,
+    path: PathBuf,
+    bytes: Vec<u8>,
+}
+
+fn import_kind(ty: &ComponentTypeRef) -> &'static str {
+    match ty {
+        ComponentTypeRef::Module(_) => "CoreModule",
+        ComponentTypeRef::Func(_) => "Func",
+        ComponentTypeRef::Value(_) => "Value",
+        ComponentTypeRef::Type(_) => "Type",
+        ComponentTypeRef::Instance(_) => "Instance",
+        ComponentTypeRef::Component(_) => "Component",
+    }
+}
+
+fn export_kind(kind: ComponentExternalKind) -> &'static str {
+    match kind {
+        ComponentExternalKind::Module => "CoreModule",
+        ComponentExternalKind::Func => "Func",
+        ComponentExternalKind::Value => "Value",
+        ComponentExternalKind::Type => "Type",
+        ComponentExternalKind::Instance => "Instance",
+        ComponentExternalKind::Component => "Component",
+    }
+}
+
+/// wasmparser's view of the top level of a component, in the shape
+/// `moon-component inspect` prints.
+fn summarize(bytes: &[u8]) -> Result<Value> {
+    let mut sections = Vec::new();
+    let (mut core_modules, mut core_types, mut components) = (0, 0, 0);
+    let (mut instances, mut types) = (0, 0);
+    let mut imports = Vec::new();
+    let mut exports = Vec::new();
+    // Payloads of nested modules and components come between their section
+    // and the matching `End`; only depth 0 is the component itself.
+    let mut depth = 0usize;
+    for payload in Parser::new(0).parse_all(bytes) {
+        let payload = payload?;
+        if depth == 0 {
+            if let Some((id, range)) = payload.as_section() {
+                sections.push(json!({ "id": id, "size": range.len() }));
+            }
+            match &payload {
+                Payload::ModuleSection { .. } => core_modules += 1,
+                Payload::CoreTypeSection(reader) => core_types += reader.count(),
+                Payload::ComponentSection { .. } => components += 1,
+                Payload::ComponentInstanceSection(reader) => instances += reader.count(),
+                Payload::ComponentTypeSection(reader) => types += reader.count(),
+                Payload::ComponentImportSection(reader) => {
+                    for import in reader.clone() {
+                        let import = import?;
+                        imports.push(json!({ "name": import.name.0, "kind": import_kind(&import.ty) }));
+                    }
+                }
+                Payload::ComponentExportSection(reader) => {
+                    for export in reader.clone() {
+                        let export = export?;
+                        exports.push(json!({ "name": export.name.0, "kind": export_kind(export.kind) }));
+                    }
+                }
+                _ => {}
+            }
+        }
+        match payload {
+            Payload::ModuleSection { .. } | Payload::ComponentSection { .. } => depth += 1,
+            Payload::End(_) if depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+    Ok(json!({
+        "sections": sections,
+        "core_modules": core_modules,
+        "core_types": core_types,
+        "components": components,
+        "instances": instances,
+        "types": types,
+        "imports": imports,
+        "exports": exports,
+    }))
+}
+
+/// One line per top-level field where the two summaries differ.
+fn diff_summaries(expected: &Value, actual: &Value, skip: &[&str]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let Some(fields) = expected.as_object() else {
+        return lines;
+    };
+    for (key, want) in fields {
+        if skip.contains(&key.as_str()) {
+            continue;
+        }
+        let got = actual.get(key).unwrap_or(&Value::Null);
+        if want != got {
+            lines.push(format!("{}: wasmparser {} / moon-component {}", key, want, got));
+        }
+    }
+    lines
+}
+
+fn validate(bytes: &[u8]) -> Result<Types> {
+    Ok(Validator::new_with_features(WasmFeatures::all()).validate_all(bytes)?)
+}
+
+/// `bytes` as the only nested component of an otherwise empty component, so
+/// that its type, imported resources included, can be compared as a whole.
+fn nested_component_type(bytes: &[u8]) -> Result<(Types, ComponentTypeId)> {
+    let mut wrapper = b"\0asm\x0d\x00\x01\x00".to_vec();
+    wrapper.push(4);
+    let mut len = bytes.len();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            wrapper.push(byte);
+            break;
+        }
+        wrapper.push(byte | 0x80);
+    }
+    wrapper.extend_from_slice(bytes);
+    let types = validate(&wrapper)?;
+    let id = types.as_ref().component_at(0);
+    Ok((types, id))
+}
+
+/// The re-encoded component must have the same type as the original: each
+/// a subtype of the other.
+fn compare_types(original: &[u8], reencoded: &[u8]) -> Result<Option<String>> {
+    let (a_types, a) = nested_component_type(original)?;
+    let (b_types, b) = nested_component_type(reencoded)?;
+    let mut cx = SubtypeCx::new_with_refs(a_types.as_ref(), b_types.as_ref());
+    if let Err(err) = cx.component_type(a, b, 0) {
+        return Ok(Some(format!("type changed: {}", err.message())));
+    }
+    cx.swap();
+    if let Err(err) = cx.component_type(b, a, 0) {
+        return Ok(Some(format!("type changed: {}", err.message())));
+    }
+    Ok(None)
+}
+
+fn inspect(bin: &Path, input: &Input, reencoded: &Path) -> Result<Value> {
+    let output = Command::new(bin)
+        .arg("inspect")
+        .arg(&input.path)
+        .arg("--reencode")
+        .arg(reencoded)
+        .output()
+        .with_context(|| format!("failed to run {}", bin.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}{}", stdout.trim(), stderr.trim_end());
+    }
+    serde_json::from_str(&stdout).with_context(|| format!("bad inspect output: {}", stdout.trim()))
+}
+
+/// Problems found with one input; empty when both parsers agree.
+fn check(bin: &Path, input: &Input, work: &Path, index: usize) -> Result<Vec<String>> {
+    let expected = summarize(&input.bytes)?;
+    let reencoded_path = work.join("reencoded").join(format!("{}.wasm", index));
+    let actual = match inspect(bin, input, &reencoded_path) {
+        Ok(summary) => summary,
+        Err(err) => return Ok(vec![format!("inspect failed: {:#}", err)]),
+    };
+    let mut problems = diff_summaries(&expected, &actual, &[]);
+
+    let reencoded = std::fs::read(&reencoded_path)
+        .with_context(|| format!("no re-encoded output at {}", reencoded_path.display()))?;
+    if let Err(err) = validate(&reencoded) {
+        problems.push(format!("re-encoded component is invalid: {:#}", err));
+        return Ok(problems);
+    }
+    let shape = summarize(&reencoded)?;
+    // Names may be re-encoded with a different discriminator, which can
+    // change section sizes without changing their meaning.
+    for line in diff_summaries(&expected, &shape, &["sections"]) {
+        problems.push(format!("re-encoded {}", line));
+    }
+    let ids = |summary: &Value| -> Vec<Value> {
+        summary["sections"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|s| s["id"].clone())
+            .collect()
+    };
+    if ids(&expected) != ids(&shape) {
+        problems.push(format!(
+            "re-encoded section ids: original {:?} / re-encoded {:?}",
+            ids(&expected),
+            ids(&shape)
+        ));
+    }
+    // wasmparser cannot equate abstract resources declared inside exported
+    // types across two validations, not even of the same bytes; such
+    // components are only checked for shape.
+    if compare_types(&input.bytes, &input.bytes)?.is_none() {
+        problems.extend(compare_types(&input.bytes, &reencoded)?);
+    }
+    Ok(problems)
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let Some(bin) = moon_component_or_skip() else {
+        return Ok(());
+    };
+
+    let work = match &opts.keep {
+        Some(dir) => dir.clone(),
+        None => std::env::temp_dir().join(format!("parser-conformance-{}", std::process::id())),
+    };
+    if work.exists() {
+        std::fs::remove_dir_all(&work)?;
+    }
+    let mut skipped = Vec::new();
+    let mut inputs: Vec<Input> = collect_components(&work, &mut skipped)?
+        .into_iter()
+        .map(|(name, path, bytes)| Input { name, path, bytes })
+        .collect();
+    if !opts.filters.is_empty() {
+        inputs.retain(|i| opts.filters.iter().any(|f| i.name.contains(f.as_str())));
+        skipped.retain(|(name, _)| opts.filters.iter().any(|f| name.contains(f.as_str())));
+    }
+    if inputs.is_empty() {
+        println!("No inputs matched.");
+        std::process::exit(1);
+    }
+    std::fs::create_dir_all(work.join("reencoded"))?;
+
+    let mut results = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        // Inputs wasmparser itself rejects say nothing about moon-component.
+        if let Err(err) = validate(&input.bytes) {
+            skipped.push((input.name.clone(), format!("wasmparser: {:#}", err)));
+            continue;
+        }
+        let problems = match check(&bin, input, &work, index) {
+            Ok(problems) => problems,
+            Err(err) => vec![format!("{:#}", err)],
+        };
+        if !problems.is_empty() {
+            println!("FAIL: {}", input.name);
+            for line in &problems {
+                println!("  {}", line);
+            }
+        }
+        results.push((input.name.clone(), problems));
+    }
+
+    let failures = results.iter().filter(|(_, p)| !p.is_empty()).count();
+    println!("\n{} input(s) checked, {} failed", results.len(), failures);
+    println!("{} input(s) skipped", skipped.len());
+
+    if let Some(path) = &opts.json {
+        let results: Vec<_> = results
+            .iter()
+            .map(|(name, problems)| {
+                json!({
+                    "input": name,
+                    "status": if problems.is_empty() { "pass" } else { "fail" },
+                    "problems": problems,
+                })
+            })
+            .collect();
+        let skipped: Vec<_> = skipped
+            .iter()
+            .map(|(name, reason)| json!({ "input": name, "reason": reason }))
+            .collect();
+        std::fs::write(
+            path,
+            serde_json::to_string_pretty(&json!({ "results": results, "skipped": skipped }))?,
+        )?;
+        println!("\nResults written to {}", path.display());
+    }
+
+    if opts.keep.is_none() {
+        let _ = std::fs::remove_dir_all(&work);
+    }
+    if failures > 0 {
+        std::process::exit(1);
+    }
+    println!("\nAll {} input(s) conform to wasmparser", results.len());
+    Ok(())
+}
@@ -0,0 +1,477 @@
+// Differential WIT resolution: `moon-component resolve-json` against wit-parser
+//
+//   cargo test --test resolve-oracle
+//   cargo test --test resolve-oracle -- --filter resources --features active,unstable
+//   cargo test --test resolve-oracle -- --emit /tmp/resolve-json --json results.json
+//
+// Every test of the wit-parser suite (tests/wit-parser/ui, fetched with
+// tools/wit-tests/update.sh) is resolved twice: by the `wit-parser` crate,
+// serialized to the same resolve.json shape as examples/calc.json (`--emit`
+// writes those files), and by `moon-component resolve-json`. Both are
+// normalized and diffed structurally, and the run fails on every test where
+// they differ or where only one side accepts the input.
+//
+// Normalization drops what is not significant: arena indices. Packages,
+// worlds, interfaces and named types are keyed by their qualified names
+// (`pkg/world`, `pkg/iface`, `pkg/iface.type`), references to them become
+// those names, and anonymous types (`list<u8>`, `option<T>`, ...) are inlined
+// where they are used. Field, case and parameter order stay significant.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wit_parser::Resolve;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+use common::{is_runner_flag, json_diff, moon_component_or_skip, repo_root, Args};
+
+/// Keys whose values are type references (`"u32"` or a type index), directly
+/// or as the elements of a list or the values of a map.
+const TYPE_KEYS: &[&str] = &[
+    "type",
+    "types",
+    "list",
+    "option",
+    "ok",
+    "err",
+    "own",
+    "borrow",
+    "future",
+    "stream",
+    "element",
+    "end",
+    "result",
+    "method",
+    "static",
+    "constructor",
+    "async-method",
+    "async-static",
+];
+
+/// At most this many differences are printed per test.
+const MAX_DIFFS: usize = 20;
+
+struct Options {
+    filters: Vec<String>,
+    features: String,
+    json: Option<PathBuf>,
+    emit: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        filters: Vec::new(),
+        features: "active".to_string(),
+        json: None,
+        emit: None,
+    };
+    let mut args = Args::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => opts.filters.push(args.value("--filter")?),
+            "--features" => opts.features = args.value("--features")?,
+            "--json" => opts.json = Some(args.value("--json")?.into()),
+            "--emit" => opts.emit = Some(args.value("--emit")?.into()),
+            _ if is_runner_flag(&arg) => {}
+            _ => opts.filters.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+fn suite_root() -> PathBuf {
+    repo_root().join("tests/wit-parser/ui")
+}
+
+/// Test inputs of a suite directory, as `tools/wit-tests/run.py` collects them.
+fn collect_tests(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|n| n == "parse-fail") {
+            continue;
+        }
+        if path.is_dir()
+            || path
+                .extension()
+                .is_some_and(|e| matches!(e.to_str(), Some("md" | "wit" | "wat" | "wasm")))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// wit-parser's resolution of `path`, in resolve.json form.
+fn oracle(path: &Path, features: &[&str]) -> Result<Value> {
+    let mut resolve = Resolve::default();
+    resolve.features.extend(features.iter().map(|f| f.to_string()));
+    let (pkg, _) = resolve.push_path(path)?;
+    let world = resolve.select_world(pkg, None).ok().map(|id| id.index());
+    let mut resolve = serde_json::to_value(&resolve)?;
+    single_results(&mut resolve);
+    Ok(json!({ "resolve": resolve, "world_id": world }))
+}
+
+/// wit-parser 0.221 serializes a function's result as `"results": [{"type": T}]`
+/// (and no result as `"results": []`); resolve.json has `"result": T`.
+fn single_results(v: &mut Value) {
+    match v {
+        Value::Object(map) => {
+            if map.contains_key("params") {
+                if let Some(Value::Array(results)) = map.get("results") {
+                    match results.as_slice() {
+                        [] => {
+                            map.remove("results");
+                        }
+                        [result] if result.get("name").is_none() => {
+                            let ty = result["type"].clone();
+                            map.remove("results");
+                            map.insert("result".to_string(), ty);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            map.values_mut().for_each(single_results);
+        }
+        Value::Array(items) => items.iter_mut().for_each(single_results),
+        _ => {}
+    }
+}
+
+fn moon_component(bin: &Path, path: &Path, features: &str) -> Result<Value> {
+    let output = Command::new(bin)
+        .arg("resolve-json")
+        .arg(path)
+        .env("MOON_COMPONENT_WIT_FEATURES", features)
+        .current_dir(repo_root())
+        .output()
+        .with_context(|| format!("failed to run {}", bin.display()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        anyhow::bail!("{}", if stderr.trim().is_empty() { stdout } else { stderr }.trim());
+    }
+    serde_json::from_slice(&output.stdout).context("invalid JSON output")
+}
+
+/// Qualified names for the arena entries of one resolve.json.
+struct Names<'a> {
+    resolve: &'a Value,
+    packages: Vec<String>,
+    worlds: Vec<String>,
+    interfaces: Vec<String>,
+    /// `None` for anonymous types, which are inlined.
+    types: Vec<Option<String>>,
+}
+
+fn arena<'a>(resolve: &'a Value, key: &str) -> &'a [Value] {
+    resolve[key].as_array().map_or(&[], Vec::as_slice)
+}
+
+impl<'a> Names<'a> {
+    fn new(resolve: &'a Value) -> Self {
+        let packages: Vec<String> = arena(resolve, "packages")
+            .iter()
+            .enumerate()
+            .map(|(i, p)| p["name"].as_str().map_or(format!("package#{}", i), str::to_string))
+            .collect();
+        let package = |v: &Value| {
+            v.as_u64()
+                .and_then(|i| packages.get(i as usize))
+                .cloned()
+        };
+        let worlds: Vec<String> = arena(resolve, "worlds")
+            .iter()
+            .enumerate()
+            .map(|(i, w)| match (package(&w["package"]), w["name"].as_str()) {
+                (Some(pkg), Some(name)) => format!("{}/{}", pkg, name),
+                _ => format!("world#{}", i),
+            })
+            .collect();
+        let mut interfaces: Vec<Option<String>> = arena(resolve, "interfaces")
+            .iter()
+            .map(|iface| match (package(&iface["package"]), iface["name"].as_str()) {
+                (Some(pkg), Some(name)) => Some(format!("{}/{}", pkg, name)),
+                _ => None,
+            })
+            .collect();
+        // Anonymous interfaces are named after the world item that holds them.
+        for (world, w) in arena(resolve, "worlds").iter().enumerate() {
+            for direction in ["imports", "exports"] {
+                let Some(items) = w[direction].as_object() else {
+                    continue;
+                };
+                for (key, item) in items {
+                    let Some(id) = item["interface"]["id"].as_u64() else {
+                        continue;
+                    };
+                    if let Some(slot @ None) = interfaces.get_mut(id as usize) {
+                        *slot = Some(format!("{}#{}:{}", worlds[world], direction, key));
+                    }
+                }
+            }
+        }
+        let interfaces: Vec<String> = interfaces
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| name.unwrap_or_else(|| format!("interface#{}", i)))
+            .collect();
+        let types = arena(resolve, "types")
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                let name = ty["name"].as_str()?;
+                let owner = &ty["owner"];
+                let owner = if let Some(id) = owner["interface"].as_u64() {
+                    interfaces.get(id as usize).cloned()
+                } else if let Some(id) = owner["world"].as_u64() {
+                    worlds.get(id as usize).cloned()
+                } else {
+                    None
+                };
+                Some(match owner {
+                    Some(owner) => format!("{}.{}", owner, name),
+                    None => format!("type#{}.{}", i, name),
+                })
+            })
+            .collect();
+        Names {
+            resolve,
+            packages,
+            worlds,
+            interfaces,
+            types,
+        }
+    }
+
+    fn name(list: &[String], v: &Value) -> Value {
+        v.as_u64()
+            .and_then(|i| list.get(i as usize))
+            .map_or_else(|| v.clone(), |name| Value::String(name.clone()))
+    }
+
+    /// A type reference: primitives as-is, named types as `{"ref": NAME}`,
+    /// anonymous types as their normalized definition.
+    fn ty(&self, v: &Value) -> Value {
+        let Some(id) = v.as_u64() else {
+            return self.walk(v);
+        };
+        match self.types.get(id as usize) {
+            Some(Some(name)) => json!({ "ref": name }),
+            Some(None) => self.walk(&arena(self.resolve, "types")[id as usize]["kind"]),
+            None => v.clone(),
+        }
+    }
+
+    fn types_in(&self, v: &Value) -> Value {
+        match v {
+            Value::Array(items) => Value::Array(items.iter().map(|i| self.types_in(i)).collect()),
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (self.key(k), self.types_in(v)))
+                    .collect(),
+            ),
+            _ => self.ty(v),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        if let Some(id) = key.strip_prefix("interface-").and_then(|i| i.parse::<usize>().ok()) {
+            if let Some(name) = self.interfaces.get(id) {
+                return format!("interface:{}", name);
+            }
+        }
+        match key {
+            "fixed-size-list" => "fixed-length-list".to_string(),
+            _ => key.to_string(),
+        }
+    }
+
+    fn walk(&self, v: &Value) -> Value {
+        match v {
+            Value::Array(items) => Value::Array(items.iter().map(|i| self.walk(i)).collect()),
+            Value::Object(map) => {
+                let mut out = Map::new();
+                for (k, v) in map {
+                    let v = match k.as_str() {
+                        "package" => Self::name(&self.packages, v),
+                        "world" => Self::name(&self.worlds, v),
+                        "interface" if v.is_object() => {
+                            let mut item = self.walk(v);
+                            item["id"] = Self::name(&self.interfaces, &v["id"]);
+                            item
+                        }
+                        "interface" => Self::name(&self.interfaces, v),
+                        // `[element, length]`
+                        "fixed-size-list" | "fixed-length-list" => match v.as_array() {
+                            Some(pair) if !pair.is_empty() => {
+                                let mut pair = pair.clone();
+                                pair[0] = self.ty(&pair[0]);
+                                Value::Array(pair)
+                            }
+                            _ => v.clone(),
+                        },
+                        k if TYPE_KEYS.contains(&k) => self.types_in(v),
+                        _ => self.walk(v),
+                    };
+                    out.insert(self.key(k), v);
+                }
+                Value::Object(out)
+            }
+            _ => v.clone(),
+        }
+    }
+
+    /// Arena `key` as a map from qualified name to normalized entry.
+    fn keyed(&self, key: &str, names: &[String]) -> Value {
+        let mut out = Map::new();
+        for (i, item) in arena(self.resolve, key).iter().enumerate() {
+            out.insert(names[i].clone(), self.walk(item));
+        }
+        Value::Object(out)
+    }
+
+    fn normalize(&self) -> Value {
+        let mut packages = Map::new();
+        for (i, pkg) in arena(self.resolve, "packages").iter().enumerate() {
+            let mut pkg = pkg.clone();
+            for (key, names) in [("interfaces", &self.interfaces), ("worlds", &self.worlds)] {
+                if let Some(items) = pkg[key].as_object_mut() {
+                    for v in items.values_mut() {
+                        *v = Self::name(names, v);
+                    }
+                }
+            }
+            packages.insert(self.packages[i].clone(), pkg);
+        }
+        let mut types = Map::new();
+        for (i, ty) in arena(self.resolve, "types").iter().enumerate() {
+            if let Some(name) = &self.types[i] {
+                types.insert(name.clone(), self.walk(ty));
+            }
+        }
+        json!({
+            "packages": packages,
+            "worlds": self.keyed("worlds", &self.worlds),
+            "interfaces": self.keyed("interfaces", &self.interfaces),
+            "types": types,
+        })
+    }
+}
+
+/// Normalized form of a `{"resolve": ..., "world_id": ...}` document (or of a
+/// bare resolve), with the selected world by name.
+fn normalize(doc: &Value) -> Value {
+    let resolve = doc.get("resolve").unwrap_or(doc);
+    let names = Names::new(resolve);
+    let mut normalized = names.normalize();
+    if let Some(world) = doc.get("world_id").filter(|w| !w.is_null()) {
+        normalized["world"] = Names::name(&names.worlds, world);
+    }
+    normalized
+}
+
+struct Outcome {
+    name: String,
+    status: &'static str,
+    details: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let root = suite_root();
+    if !root.exists() {
+        eprintln!("wit-parser tests not found; skipping. Run: tools/wit-tests/update.sh");
+        return Ok(());
+    }
+    let Some(bin) = moon_component_or_skip() else {
+        return Ok(());
+    };
+    let features: Vec<&str> = opts.features.split(',').filter(|f| !f.is_empty()).collect();
+
+    let mut tests = Vec::new();
+    collect_tests(&root, &mut tests)?;
+    collect_tests(&root.join("parse-fail"), &mut tests)?;
+    tests.sort();
+    if !opts.filters.is_empty() {
+        tests.retain(|t| {
+            let s = t.to_string_lossy();
+            opts.filters.iter().any(|f| s.contains(f.as_str()))
+        });
+    }
+    if tests.is_empty() {
+        println!("No tests matched.");
+        std::process::exit(1);
+    }
+
+    let mut outcomes = Vec::new();
+    for test in &tests {
+        let name = test
+            .strip_prefix(&root)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let expected = oracle(test, &features);
+        if let (Some(dir), Ok(doc)) = (&opts.emit, &expected) {
+            let path = dir.join(format!("{}.json", name));
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(&path, serde_json::to_string_pretty(doc)?)?;
+        }
+        let actual = moon_component(&bin, test, &opts.features);
+        let (status, details) = match (&expected, &actual) {
+            (Err(_), Err(_)) => ("rejected", Vec::new()),
+            (Ok(_), Err(err)) => ("fail", vec![format!("moon-component rejected it: {:#}", err)]),
+            (Err(err), Ok(_)) => ("fail", vec![format!("wit-parser rejected it: {:#}", err)]),
+            (Ok(expected), Ok(actual)) => {
+                let mut diffs = Vec::new();
+                json_diff("", &normalize(expected), &normalize(actual), &mut diffs);
+                (if diffs.is_empty() { "pass" } else { "fail" }, diffs)
+            }
+        };
+        if status == "fail" {
+            println!("FAIL: {}", name);
+            for line in details.iter().take(MAX_DIFFS) {
+                println!("  {}", line);
+            }
+            if details.len() > MAX_DIFFS {
+                println!("  ... {} more", details.len() - MAX_DIFFS);
+            }
+        }
+        outcomes.push(Outcome {
+            name,
+            status,
+            details,
+        });
+    }
+
+    let count = |status: &str| outcomes.iter().filter(|o| o.status == status).count();
+    println!(
+        "\n{} identical, {} rejected by both, {} different",
+        count("pass"),
+        count("rejected"),
+        count("fail")
+    );
+
+    if let Some(path) = &opts.json {
+        let results: Vec<_> = outcomes
+            .iter()
+            .map(|o| json!({ "test": o.name, "status": o.status, "diff": o.details }))
+            .collect();
+        std::fs::write(path, serde_json::to_string_pretty(&json!({ "results": results }))?)?;
+        println!("\nResults written to {}", path.display());
+    }
+
+    let failures = count("fail");
+    if failures > 0 {
+        println!("\n{} of {} test(s) resolve differently", failures, outcomes.len());
+        std::process::exit(1);
+    }
+    println!("\nAll {} test(s) resolve the same as wit-parser", outcomes.len());
+    Ok(())
+}
@@ -0,0 +1,378 @@
+// Differential test for `moon-component targets` against wit-component
+//
+//   cargo test --test targets-oracle
+//   cargo test --test targets-oracle -- --filter wasm-tools --json results.json
+//   cargo test --test targets-oracle -- --keep /tmp/targets-cases
+//
+// Every case is a (component, WIT directory, world) triple. The verdict of
+// `wit_component::targets` on the same files is the oracle; the run fails on
+// any case where `moon-component targets` disagrees with it. Cases come from:
+//
+//   - the components under examples/, against each world of their `wit/`
+//   - the top-level components of tests/component-model/**/*.wast
+//   - every component against its own decoded world, printed back to WIT,
+//     and against three mutations of it (an export the component lacks, a
+//     dropped import, an extra parameter on an export) as negative cases
+//
+// moon-component is found as described in tests/common/mod.rs; without it the
+// run is skipped.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wit_component::{DecodedWasm, WitPrinter};
+use wit_parser::{
+    Docs, Function, FunctionKind, PackageId, Resolve, Results, Stability, Type, WorldItem,
+    WorldKey,
+};
+
+#[path = "../common/mod.rs"]
+mod common;
+
+use common::{collect_components, is_runner_flag, moon_component_or_skip, repo_root, Args};
+
+struct Options {
+    filters: Vec<String>,
+    json: Option<PathBuf>,
+    keep: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        filters: Vec::new(),
+        json: None,
+        keep: None,
+    };
+    let mut args = Args::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => opts.filters.push(args.value("--filter")?),
+            "--json" => opts.json = Some(args.value("--json")?.into()),
+            "--keep" => opts.keep = Some(args.value("--keep")?.into()),
+            _ if is_runner_flag(&arg) => {}
+            _ => opts.filters.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+/// Nearest `wit/` directory at or above `path`, stopping at `examples/`.
+fn example_wit_dir(path: &Path, examples: &Path) -> Option<PathBuf> {
+    let mut dir = path.parent()?;
+    while dir.starts_with(examples) && dir != examples {
+        let wit = dir.join("wit");
+        if wit.is_dir() {
+            return Some(wit);
+        }
+        dir = dir.parent()?;
+    }
+    None
+}
+
+/// Write `pkg` to `dir/main.wit` and every other package of `resolve` under
+/// `dir/deps/`, the layout both resolvers read.
+fn write_wit(resolve: &Resolve, pkg: PackageId, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join("main.wit"), WitPrinter::default().print(resolve, pkg, &[])?)?;
+    for (id, package) in resolve.packages.iter() {
+        if id == pkg {
+            continue;
+        }
+        let name = &package.name;
+        let mut dep = format!("{}-{}", name.namespace, name.name);
+        if let Some(version) = &name.version {
+            dep.push_str(&format!("-{}", version));
+        }
+        let dep_dir = dir.join("deps").join(dep);
+        std::fs::create_dir_all(&dep_dir)?;
+        std::fs::write(dep_dir.join("package.wit"), WitPrinter::default().print(resolve, id, &[])?)?;
+    }
+    Ok(())
+}
+
+/// The decoded world, then each mutation that applies to it.
+fn world_variants(resolve: &Resolve, world: wit_parser::WorldId) -> Vec<(&'static str, Resolve)> {
+    let mut variants = vec![("decoded", resolve.clone())];
+
+    let mut extra = resolve.clone();
+    extra.worlds[world].exports.insert(
+        WorldKey::Name("oracle-missing".to_string()),
+        WorldItem::Function(Function {
+            name: "oracle-missing".to_string(),
+            kind: FunctionKind::Freestanding,
+            params: Vec::new(),
+            results: Results::Named(Vec::new()),
+            docs: Docs::default(),
+            stability: Stability::Unknown,
+        }),
+    );
+    variants.push(("missing-export", extra));
+
+    let mut dropped = resolve.clone();
+    let imports = &mut dropped.worlds[world].imports;
+    if let Some(index) = imports
+        .values()
+        .position(|item| !matches!(item, WorldItem::Type(_)))
+    {
+        imports.shift_remove_index(index);
+        variants.push(("dropped-import", dropped));
+    }
+
+    let mut param = resolve.clone();
+    let extra_param = ("oracle-extra".to_string(), Type::U32);
+    let target = param.worlds[world].exports.values().find_map(|item| match item {
+        WorldItem::Function(_) => Some(None),
+        WorldItem::Interface { id, .. } if !param.interfaces[*id].functions.is_empty() => {
+            Some(Some(*id))
+        }
+        _ => None,
+    });
+    let mutated = match target {
+        Some(Some(id)) => {
+            let func = param.interfaces[id].functions.values_mut().next().unwrap();
+            func.params.push(extra_param);
+            true
+        }
+        Some(None) => {
+            let func = param.worlds[world]
+                .exports
+                .values_mut()
+                .find_map(|item| match item {
+                    WorldItem::Function(func) => Some(func),
+                    _ => None,
+                })
+                .unwrap();
+            func.params.push(extra_param);
+            true
+        }
+        None => false,
+    };
+    if mutated {
+        variants.push(("extra-param", param));
+    }
+    variants
+}
+
+struct Case {
+    /// Where the case came from, for reports and `--filter`.
+    name: String,
+    component: PathBuf,
+    wit: PathBuf,
+    world: String,
+}
+
+/// Cases for `component` against its own decoded world and its mutations.
+fn decoded_cases(name: &str, component: &Path, bytes: &[u8], dir: &Path) -> Result<Vec<Case>> {
+    let (resolve, world) = match wit_component::decode(bytes)? {
+        DecodedWasm::Component(resolve, world) => (resolve, world),
+        DecodedWasm::WitPackage(..) => bail!("not a component"),
+    };
+    let pkg = resolve.worlds[world]
+        .package
+        .ok_or_else(|| anyhow!("decoded world has no package"))?;
+    let world_name = resolve.worlds[world].name.clone();
+    let mut cases = Vec::new();
+    for (variant, resolve) in world_variants(&resolve, world) {
+        let wit = dir.join(variant);
+        write_wit(&resolve, pkg, &wit)?;
+        cases.push(Case {
+            name: format!("{} [{}]", name, variant),
+            component: component.to_path_buf(),
+            wit,
+            world: world_name.clone(),
+        });
+    }
+    Ok(cases)
+}
+
+fn collect_cases(work: &Path, skipped: &mut Vec<(String, String)>) -> Result<Vec<Case>> {
+    let examples = repo_root().join("examples");
+    let components = collect_components(work, skipped)?;
+
+    let mut cases = Vec::new();
+    for (index, (name, path, bytes)) in components.iter().enumerate() {
+        if path.starts_with(&examples) {
+            if let Some(wit) = example_wit_dir(path, &examples) {
+                let mut resolve = Resolve::default();
+                match resolve.push_path(&wit) {
+                    Ok((pkg, _)) => {
+                        for world in resolve.packages[pkg].worlds.keys() {
+                            cases.push(Case {
+                                name: format!("{} [{}]", name, world),
+                                component: path.clone(),
+                                wit: wit.clone(),
+                                world: world.clone(),
+                            });
+                        }
+                    }
+                    Err(err) => skipped.push((name.clone(), format!("{}: {:#}", wit.display(), err))),
+                }
+            }
+        }
+        let dir = work.join("decoded").join(index.to_string());
+        match decoded_cases(name, path, bytes, &dir) {
+            Ok(decoded) => cases.extend(decoded),
+            Err(err) => skipped.push((name.clone(), format!("decode: {:#}", err))),
+        }
+    }
+    Ok(cases)
+}
+
+/// `Ok(())` if the component targets the world, `Err(reason)` otherwise.
+type Verdict = std::result::Result<(), String>;
+
+fn oracle(case: &Case) -> Result<Verdict> {
+    let mut resolve = Resolve::default();
+    let (pkg, _) = resolve.push_path(&case.wit)?;
+    let world = resolve.select_world(pkg, Some(&case.world))?;
+    let bytes = std::fs::read(&case.component)?;
+    Ok(wit_component::targets(&resolve, world, &bytes).map_err(|e| format!("{:#}", e)))
+}
+
+fn moon_component(bin: &Path, case: &Case) -> Result<Verdict> {
+    let output = Command::new(bin)
+        .arg("targets")
+        .arg(&case.component)
+        .arg(&case.wit)
+        .arg("--world")
+        .arg(&case.world)
+        .output()
+        .with_context(|| format!("failed to run {}", bin.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && stdout.lines().last() == Some("OK") {
+        return Ok(Ok(()));
+    }
+    // Inputs the oracle accepted must load in moon-component too.
+    if stdout.starts_with("Error resolving WIT") || stdout.starts_with("Error reading") {
+        bail!("{}", stdout);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(Err(format!("{}{}", stdout, stderr.trim_end())))
+}
+
+fn describe(verdict: &Verdict) -> String {
+    match verdict {
+        Ok(()) => "targets".to_string(),
+        Err(reason) => format!("does not target ({})", reason),
+    }
+}
+
+struct Outcome {
+    name: String,
+    expected: Verdict,
+    actual: std::result::Result<Verdict, String>,
+}
+
+impl Outcome {
+    fn agrees(&self) -> bool {
+        matches!(&self.actual, Ok(actual) if actual.is_ok() == self.expected.is_ok())
+    }
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let Some(bin) = moon_component_or_skip() else {
+        return Ok(());
+    };
+
+    let work = match &opts.keep {
+        Some(dir) => dir.clone(),
+        None => std::env::temp_dir().join(format!("targets-oracle-{}", std::process::id())),
+    };
+    if work.exists() {
+        std::fs::remove_dir_all(&work)?;
+    }
+    let mut skipped = Vec::new();
+    let mut cases = collect_cases(&work, &mut skipped)?;
+    if !opts.filters.is_empty() {
+        cases.retain(|c| opts.filters.iter().any(|f| c.name.contains(f.as_str())));
+        skipped.retain(|(name, _)| opts.filters.iter().any(|f| name.contains(f.as_str())));
+    }
+    if cases.is_empty() {
+        println!("No cases matched.");
+        std::process::exit(1);
+    }
+
+    let mut outcomes = Vec::new();
+    for case in &cases {
+        let expected = match oracle(case) {
+            Ok(verdict) => verdict,
+            Err(err) => {
+                skipped.push((case.name.clone(), format!("wit-parser: {:#}", err)));
+                continue;
+            }
+        };
+        let actual = moon_component(&bin, case).map_err(|e| format!("{:#}", e));
+        let outcome = Outcome {
+            name: case.name.clone(),
+            expected,
+            actual,
+        };
+        if !outcome.agrees() {
+            let actual = match &outcome.actual {
+                Ok(verdict) => describe(verdict),
+                Err(err) => format!("failed: {}", err),
+            };
+            println!(
+                "FAIL: {}\n  wit:            {} (world {})\n  wit-component:  {}\n  moon-component: {}",
+                outcome.name,
+                case.wit.display(),
+                case.world,
+                describe(&outcome.expected),
+                actual
+            );
+        }
+        outcomes.push(outcome);
+    }
+
+    let count = |targets: bool, agrees: bool| {
+        outcomes
+            .iter()
+            .filter(|o| o.expected.is_ok() == targets && o.agrees() == agrees)
+            .count()
+    };
+    println!("\n{:<16} {:>6} {:>6}", "Oracle verdict", "agree", "fail");
+    println!("{}", "-".repeat(30));
+    println!("{:<16} {:>6} {:>6}", "targets", count(true, true), count(true, false));
+    println!("{:<16} {:>6} {:>6}", "does not target", count(false, true), count(false, false));
+    println!("{} input(s) skipped", skipped.len());
+
+    if let Some(path) = &opts.json {
+        let results: Vec<_> = outcomes
+            .iter()
+            .map(|o| {
+                json!({
+                    "case": o.name,
+                    "status": if o.agrees() { "agree" } else { "fail" },
+                    "wit-component": o.expected.as_ref().err(),
+                    "targets": o.expected.is_ok(),
+                    "moon-component": match &o.actual {
+                        Ok(verdict) => json!({ "targets": verdict.is_ok(), "error": verdict.as_ref().err() }),
+                        Err(err) => json!({ "failed": err }),
+                    },
+                })
+            })
+            .collect();
+        let skipped: Vec<_> = skipped
+            .iter()
+            .map(|(name, reason)| json!({ "input": name, "reason": reason }))
+            .collect();
+        std::fs::write(
+            path,
+            serde_json::to_string_pretty(&json!({ "results": results, "skipped": skipped }))?,
+        )?;
+        println!("\nResults written to {}", path.display());
+    }
+
+    if opts.keep.is_none() {
+        let _ = std::fs::remove_dir_all(&work);
+    }
+    let failures = outcomes.iter().filter(|o| !o.agrees()).count();
+    if failures > 0 {
+        println!("\n{} disagreement(s) out of {} case(s)", failures, outcomes.len());
+        std::process::exit(1);
+    }
+    println!("\nAll {} case(s) agree with wit-component", outcomes.len());
+    Ok(())
+}
@@ -0,0 +1,653 @@
+// Differential WAC parsing and resolution: `moon-component wac-parse` and
+// `wac-resolve` against wac-parser
+//
+//   cargo test --test wac-oracle
+//   cargo test --test wac-oracle -- --filter resolution/fail --json results.json
+//
+// Documents come from examples/wac/script.wac and from the wac-parser suite
+// (tests/wac-parser, fetched with tools/wac-tests/update.sh): everything
+// under parser/ and resolution/, including the fail/ directories. Every
+// document is parsed by both sides; resolution tests and the example are
+// also resolved, with the packages wac-parser's tests use (`<test>/<ns>/
+// <name>.{wat,wasm}` or a WIT directory) encoded into the `deps/<ns>/
+// <name>.wasm` layout moon-component reads.
+//
+// The run fails when only one side accepts a document, and, when both do,
+// when their outputs differ structurally. Both ASTs (wac-parser's, through
+// serde, and moon-component's `wac-parse` JSON) are normalized to one shape:
+// the package directive, then each statement by kind (import, type, let or
+// export) with the name it binds or exports and the expression tree or type
+// after it. Expressions become nested `new`, `ident` and `access` nodes,
+// without parentheses. Node and field names are wac-parser's; the
+// normalization drops spans and doc comments, reads keys and variant tags in
+// any case, and accepts serde's and MoonBit's enum encodings, so only the
+// tree itself is compared. Every node missing from moon-component's tree or
+// only in it is reported. `wac-resolve` output is compared the same way, as
+// its sorted import and export names.
+//
+// Import and export names alone do not catch an import or export of the wrong kind or type, so
+// a composition both sides resolve is also encoded by both (`moon-component
+// compose` and wac-graph), and the decoded import and export surfaces must
+// match, as in the compose oracle.
+//
+// moon-component is found as described in tests/common/mod.rs; without it the
+// run is skipped.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wac_graph::EncodeOptions;
+use wac_parser::Document;
+use wac_resolver::{packages, FileSystemPackageResolver};
+
+#[path = "../common/mod.rs"]
+mod common;
+
+use common::{is_runner_flag, json_diff, moon_component_or_skip, repo_root, Args, Surface};
+
+struct Options {
+    filters: Vec<String>,
+    json: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        filters: Vec::new(),
+        json: None,
+    };
+    let mut args = Args::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => opts.filters.push(args.value("--filter")?),
+            "--json" => opts.json = Some(args.value("--json")?.into()),
+            _ if is_runner_flag(&arg) => {}
+            _ => opts.filters.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+struct Test {
+    /// Path relative to the repository, for reports and `--filter`.
+    name: String,
+    path: PathBuf,
+    /// Where wac-parser's resolver looks for packages; `None` for tests that
+    /// are only parsed.
+    packages: Option<PathBuf>,
+}
+
+fn wac_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if dir.is_dir() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "wac") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn collect_tests() -> Result<Vec<Test>> {
+    let root = repo_root();
+    let relative = |path: &Path| -> Result<String> {
+        Ok(path.strip_prefix(&root)?.to_string_lossy().replace('\\', "/"))
+    };
+    let example = root.join("examples/wac/script.wac");
+    let mut tests = vec![Test {
+        name: relative(&example)?,
+        packages: Some(root.join("examples/wac/deps")),
+        path: example,
+    }];
+    let suite = root.join("tests/wac-parser");
+    for dir in ["parser", "parser/fail", "resolution", "resolution/fail"] {
+        for path in wac_files(&suite.join(dir))? {
+            let packages = dir
+                .starts_with("resolution")
+                .then(|| path.with_extension(""));
+            tests.push(Test {
+                name: relative(&path)?,
+                path,
+                packages,
+            });
+        }
+    }
+    Ok(tests)
+}
+
+/// `namedAccess`, `named_access` and `NamedAccess` all become `named-access`,
+/// so keys and variant tags compare across naming conventions.
+fn kebab(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.char_indices() {
+        if c == '_' {
+            out.push('-');
+        } else if c.is_ascii_uppercase() {
+            if i > 0 && !out.ends_with('-') {
+                out.push('-');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn field<'a>(v: &'a Value, name: &str) -> &'a Value {
+    v.as_object()
+        .and_then(|map| map.iter().find(|(k, _)| kebab(k) == name))
+        .map_or(&Value::Null, |(_, v)| v)
+}
+
+/// A variant in MoonBit's encodings: `{"$tag": "Variant", ...}` with its
+/// fields or positional `"0"`, or `["Variant", payload]`.
+fn moonbit_variant(v: &Value) -> Option<(String, Value)> {
+    match v {
+        Value::Array(items) => match items.as_slice() {
+            [Value::String(tag), rest @ ..]
+                if rest.len() <= 1 && tag.starts_with(|c: char| c.is_ascii_uppercase()) =>
+            {
+                Some((kebab(tag), rest.first().cloned().unwrap_or(Value::Null)))
+            }
+            _ => None,
+        },
+        Value::Object(map) => {
+            let tag = map.get("$tag")?.as_str()?;
+            let payload = match map.get("0") {
+                Some(payload) => payload.clone(),
+                None if map.len() == 1 => Value::Null,
+                None => Value::Object(
+                    map.iter()
+                        .filter(|(k, _)| *k != "$tag")
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect(),
+                ),
+            };
+            Some((kebab(tag), payload))
+        }
+        _ => None,
+    }
+}
+
+/// An enum value's variant and payload, whichever way it is encoded:
+/// MoonBit's, or serde's `{"variant": payload}` and bare `"variant"` for unit
+/// variants.
+fn tagged(v: &Value) -> Option<(String, Value)> {
+    if let Some(variant) = moonbit_variant(v) {
+        return Some(variant);
+    }
+    match v {
+        Value::String(tag) => Some((kebab(tag), Value::Null)),
+        Value::Object(map) if map.len() == 1 => {
+            let (tag, payload) = map.iter().next()?;
+            Some((kebab(tag), payload.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// The text of a name: an identifier, string literal or package name, bare or
+/// wrapped in a node or a variant.
+fn text(v: &Value) -> Value {
+    match v {
+        Value::String(_) | Value::Null => v.clone(),
+        _ => match (field(v, "string"), field(v, "value")) {
+            (Value::String(s), _) | (_, Value::String(s)) => Value::String(s.clone()),
+            _ => match tagged(v) {
+                Some((_, payload)) if payload != *v => text(&payload),
+                _ => plain(v),
+            },
+        },
+    }
+}
+
+/// A node with spans and doc comments dropped, keys in kebab case, variants
+/// in serde's encoding, and names reduced to their text.
+fn plain(v: &Value) -> Value {
+    if let Some((tag, payload)) = moonbit_variant(v) {
+        return match plain(&payload) {
+            Value::Null => Value::String(tag),
+            payload => json!({ tag: payload }),
+        };
+    }
+    match v {
+        Value::Array(items) => Value::Array(items.iter().map(plain).collect()),
+        Value::Object(map) => {
+            // A span carried as a variant's payload, as in `u32` or `...`.
+            if map.len() == 2 && map.contains_key("offset") && map.contains_key("length") {
+                return Value::Null;
+            }
+            let mut out = serde_json::Map::new();
+            for (k, v) in map {
+                let k = kebab(k);
+                if k == "span" || k == "docs" {
+                    continue;
+                }
+                match plain(v) {
+                    // A unit variant: `{"u32": span}` is `"u32"`.
+                    Value::Null if map.len() == 1 => return Value::String(k),
+                    Value::Null => {}
+                    v => {
+                        out.insert(k, v);
+                    }
+                }
+            }
+            match out.iter().next() {
+                Some((k, Value::String(s))) if out.len() == 1 && (k == "string" || k == "value") => {
+                    Value::String(s.clone())
+                }
+                _ => Value::Object(out),
+            }
+        }
+        _ => v.clone(),
+    }
+}
+
+/// An expression as a tree: `new` with its package and arguments, an
+/// identifier, or an access (`.id` or `["name"]`) of an inner expression.
+/// Parentheses are not part of the tree.
+fn expr_tree(v: &Value) -> Value {
+    let primary = match tagged(field(v, "primary")) {
+        Some((tag, new)) if tag == "new" => {
+            let arguments: Vec<Value> = field(&new, "arguments")
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|argument| match tagged(argument) {
+                    Some((tag, payload)) if tag == "named" => json!({
+                        "name": text(field(&payload, "name")),
+                        "expr": expr_tree(field(&payload, "expr")),
+                    }),
+                    Some((tag, _)) if tag == "fill" => json!("..."),
+                    Some((tag, payload)) => json!({ tag: text(&payload) }),
+                    None => plain(argument),
+                })
+                .collect();
+            json!({ "new": text(field(&new, "package")), "arguments": arguments })
+        }
+        Some((tag, nested)) if tag == "nested" => expr_tree(field(&nested, "inner")),
+        Some((tag, ident)) if tag == "ident" => json!({ "ident": text(&ident) }),
+        _ => plain(field(v, "primary")),
+    };
+    let postfix = field(v, "postfix").as_array().into_iter().flatten();
+    postfix.fold(primary, |inner, access| match tagged(access) {
+        Some((tag, payload)) if tag == "access" => {
+            json!({ "access": text(field(&payload, "id")), "of": inner })
+        }
+        Some((tag, payload)) if tag == "named-access" => {
+            json!({ "named-access": text(field(&payload, "string")), "of": inner })
+        }
+        _ => json!({ "postfix": plain(access), "of": inner }),
+    })
+}
+
+/// One statement: its kind, the name it binds or exports (`target`), and
+/// the expression or type that follows.
+fn statement(v: &Value) -> Value {
+    let Some((kind, payload)) = tagged(v) else {
+        return plain(v);
+    };
+    let payload = &payload;
+    match kind.as_str() {
+        "let" => json!({
+            "kind": kind,
+            "target": text(field(payload, "id")),
+            "expr": expr_tree(field(payload, "expr")),
+        }),
+        "export" => {
+            let target = match tagged(field(payload, "options")) {
+                Some((option, _)) if option == "spread" => json!("..."),
+                Some((option, name)) if option == "rename" => text(&name),
+                _ => Value::Null,
+            };
+            json!({
+                "kind": kind,
+                "target": target,
+                "expr": expr_tree(field(payload, "expr")),
+            })
+        }
+        "import" => json!({
+            "kind": kind,
+            "target": text(field(payload, "id")),
+            "name": text(field(payload, "name")),
+            "type": plain(field(payload, "ty")),
+        }),
+        "type" => {
+            // Interface, world and type declarations all name themselves with
+            // an `id`, one variant down.
+            let target = match field(payload, "id") {
+                Value::Null => tagged(payload).map_or(Value::Null, |(_, decl)| text(field(&decl, "id"))),
+                id => text(id),
+            };
+            json!({ "kind": kind, "target": target, "type": plain(payload) })
+        }
+        _ => json!({ "kind": kind, "node": plain(payload) }),
+    }
+}
+
+/// A WAC document's AST in the shape both sides are compared in: the package
+/// directive, then every statement in order.
+fn normalize_ast(doc: &Value) -> Value {
+    let directive = match field(doc, "directive") {
+        Value::Null => doc,
+        directive => directive,
+    };
+    let statements: Vec<Value> = field(doc, "statements")
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(statement)
+        .collect();
+    json!({
+        "package": text(field(directive, "package")),
+        "targets": text(field(directive, "targets")),
+        "statements": statements,
+    })
+}
+
+/// A resolved composition as its sorted import and export names; entries of
+/// moon-component's summary may be names or nodes with a `name`.
+fn normalize_resolution(summary: &Value) -> Value {
+    let names = |key: &str| {
+        let mut names: Vec<Value> = field(summary, key)
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|entry| match field(entry, "name") {
+                Value::Null => text(entry),
+                name => text(name),
+            })
+            .collect();
+        names.sort_by_key(|name| name.to_string());
+        Value::Array(names)
+    };
+    json!({ "imports": names("imports"), "exports": names("exports") })
+}
+
+/// Run a moon-component subcommand on `path`: its JSON output, or the error
+/// it printed.
+fn moon_component(bin: &Path, command: &str, path: &Path) -> Result<std::result::Result<Value, String>> {
+    let output = Command::new(bin)
+        .arg(command)
+        .arg(path)
+        .output()
+        .with_context(|| format!("failed to run {}", bin.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = format!("{}{}", stdout.trim(), stderr.trim_end());
+        return Ok(Err(message.lines().next().unwrap_or_default().to_string()));
+    }
+    Ok(serde_json::from_str(&stdout).map_err(|e| format!("bad JSON output: {}", e)))
+}
+
+/// `moon-component compose` on `script`, writing to `out`: the component, or
+/// the error it printed.
+fn moon_component_compose(bin: &Path, script: &Path, out: &Path) -> Result<std::result::Result<Vec<u8>, String>> {
+    let output = Command::new(bin)
+        .arg("compose")
+        .arg(script)
+        .arg("-o")
+        .arg(out)
+        .output()
+        .with_context(|| format!("failed to run {}", bin.display()))?;
+    if !output.status.success() {
+        let message = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+        return Ok(Err(message.lines().next().unwrap_or_default().to_string()));
+    }
+    Ok(std::fs::read(out).map_err(|e| format!("no output at {}: {}", out.display(), e)))
+}
+
+/// Differences between the import and export surfaces of wac-graph's
+/// encoding and moon-component's, kinds and types included.
+fn surface_problems(expected: &[u8], actual: std::result::Result<Vec<u8>, String>) -> Vec<String> {
+    let actual = match actual {
+        Ok(bytes) => bytes,
+        Err(err) => return vec![format!("moon-component compose fails: {}", err)],
+    };
+    let expected = match Surface::decode(expected) {
+        Ok(surface) => surface,
+        Err(err) => return vec![format!("wac-graph output does not decode: {:#}", err)],
+    };
+    match Surface::decode(&actual) {
+        Ok(surface) => expected
+            .diff(&surface)
+            .into_iter()
+            .map(|line| format!("surface {}", line))
+            .collect(),
+        Err(err) => vec![format!("moon-component compose output does not decode: {:#}", err)],
+    }
+}
+
+/// A composition wac-parser resolved: its normalized import and export
+/// names, the package bytes it was resolved against, and wac-graph's
+/// encoding of it.
+struct Resolved {
+    names: Value,
+    packages: Vec<(String, Vec<u8>)>,
+    component: std::result::Result<Vec<u8>, String>,
+}
+
+fn wac_resolve(document: &Document, root: &Path) -> std::result::Result<Resolved, String> {
+    let keys = packages(document).map_err(|e| e.to_string())?;
+    let resolver = FileSystemPackageResolver::new(root, HashMap::new(), true);
+    let resolved = resolver.resolve(&keys).map_err(|e| format!("{:#}", anyhow!(e)))?;
+    let packages = resolved
+        .iter()
+        .map(|(key, bytes)| (key.name.to_string(), bytes.clone()))
+        .collect();
+    let resolution = document.resolve(resolved).map_err(|e| e.to_string())?;
+    let graph = resolution.graph();
+    let imports: Vec<&str> = graph.imports().map(|(name, ..)| name).collect();
+    let exports: Vec<&str> = graph.node_ids().filter_map(|id| graph[id].export_name()).collect();
+    let names = normalize_resolution(&json!({ "imports": imports, "exports": exports }));
+    let component = resolution
+        .encode(EncodeOptions::default())
+        .map_err(|e| e.to_string());
+    Ok(Resolved {
+        names,
+        packages,
+        component,
+    })
+}
+
+/// Lay `test` out for moon-component: the document next to `deps/<ns>/
+/// <name>.wasm` for every package wac-parser resolved it against.
+fn stage(test: &Test, packages: &[(String, Vec<u8>)], dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let script = dir.join(test.path.file_name().unwrap());
+    std::fs::copy(&test.path, &script)?;
+    for (name, bytes) in packages {
+        let (namespace, package) = name.split_once(':').unwrap_or(("", name));
+        let deps = dir.join("deps").join(namespace);
+        std::fs::create_dir_all(&deps)?;
+        std::fs::write(deps.join(format!("{}.wasm", package)), bytes)?;
+    }
+    Ok(script)
+}
+
+struct Outcome {
+    test: String,
+    stage: &'static str,
+    /// wac-parser's verdict: `Ok` when it accepts the document.
+    expected: std::result::Result<(), String>,
+    problems: Vec<String>,
+}
+
+fn describe(verdict: &std::result::Result<(), String>) -> String {
+    match verdict {
+        Ok(()) => "accepts".to_string(),
+        Err(err) => format!("rejects ({})", err.lines().next().unwrap_or_default()),
+    }
+}
+
+/// wac-parser's verdict, and the problems with moon-component's: a verdict
+/// that differs, or every node of the normalized output that is missing from
+/// moon-component's or only in it.
+fn compare(
+    stage: &str,
+    expected: std::result::Result<Value, String>,
+    actual: std::result::Result<Value, String>,
+) -> (std::result::Result<(), String>, Vec<String>) {
+    let mut problems = Vec::new();
+    match (&expected, &actual) {
+        (Ok(want), Ok(json)) => {
+            let got = match stage {
+                "parse" => normalize_ast(json),
+                _ => normalize_resolution(json),
+            };
+            let mut diffs = Vec::new();
+            json_diff(stage, want, &got, &mut diffs);
+            problems.extend(diffs);
+        }
+        (Ok(_), Err(err)) => problems.push(format!("moon-component rejects: {}", err)),
+        (Err(_), Ok(_)) => problems.push("moon-component accepts".to_string()),
+        (Err(_), Err(_)) => {}
+    }
+    (expected.map(|_| ()), problems)
+}
+
+fn run_test(bin: &Path, test: &Test, work: &Path, outcomes: &mut Vec<Outcome>) -> Result<()> {
+    let source = std::fs::read_to_string(&test.path)?.replace("\r\n", "\n");
+    let document = Document::parse(&source);
+
+    let expected = match &document {
+        Ok(document) => Ok(normalize_ast(&serde_json::to_value(document)?)),
+        Err(err) => Err(err.to_string()),
+    };
+    let actual = moon_component(bin, "wac-parse", &test.path)?;
+    let (expected, problems) = compare("parse", expected, actual);
+    outcomes.push(Outcome {
+        test: test.name.clone(),
+        stage: "parse",
+        expected,
+        problems,
+    });
+
+    // Resolution needs a document both sides parsed.
+    let (Some(root), Ok(document)) = (&test.packages, &document) else {
+        return Ok(());
+    };
+    let resolved = wac_resolve(document, root);
+    let packages = resolved.as_ref().map(|r| r.packages.as_slice()).unwrap_or(&[]);
+    let dir = work.join(test.name.replace('/', "_"));
+    let script = if test.path.starts_with(repo_root().join("examples")) {
+        test.path.clone()
+    } else {
+        stage(test, packages, &dir)?
+    };
+    let actual = moon_component(bin, "wac-resolve", &script)?;
+    let accepted = actual.is_ok();
+    let (component, names) = match resolved {
+        Ok(resolved) => (Some(resolved.component), Ok(resolved.names)),
+        Err(err) => (None, Err(err)),
+    };
+    let (expected, mut problems) = compare("resolve", names, actual);
+    // Encoding is wac-graph's own step; a composition it cannot encode has no
+    // surface to compare against.
+    if let (true, Some(Ok(component))) = (accepted, &component) {
+        std::fs::create_dir_all(&dir)?;
+        let composed = moon_component_compose(bin, &script, &dir.join("composed.wasm"))?;
+        problems.extend(surface_problems(component, composed));
+    }
+    outcomes.push(Outcome {
+        test: test.name.clone(),
+        stage: "resolve",
+        expected,
+        problems,
+    });
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let Some(bin) = moon_component_or_skip() else {
+        return Ok(());
+    };
+
+    let mut tests = collect_tests()?;
+    if !opts.filters.is_empty() {
+        tests.retain(|t| opts.filters.iter().any(|f| t.name.contains(f.as_str())));
+    }
+    if tests.is_empty() {
+        println!("No tests matched.");
+        std::process::exit(1);
+    }
+    if !repo_root().join("tests/wac-parser/parser").is_dir() {
+        println!("wac-parser suite not found; run tools/wac-tests/update.sh to fetch it.");
+    }
+
+    let work = std::env::temp_dir().join(format!("wac-oracle-{}", std::process::id()));
+    let mut outcomes = Vec::new();
+    for test in &tests {
+        run_test(&bin, test, &work, &mut outcomes)?;
+    }
+    let _ = std::fs::remove_dir_all(&work);
+
+    for outcome in outcomes.iter().filter(|o| !o.problems.is_empty()) {
+        println!(
+            "FAIL: {} [{}]\n  wac-parser: {}",
+            outcome.test,
+            outcome.stage,
+            describe(&outcome.expected)
+        );
+        for problem in &outcome.problems {
+            println!("  {}", problem);
+        }
+    }
+
+    let count = |stage: &str, accepts: bool, agrees: bool| {
+        outcomes
+            .iter()
+            .filter(|o| {
+                o.stage == stage && o.expected.is_ok() == accepts && o.problems.is_empty() == agrees
+            })
+            .count()
+    };
+    println!("\n{:<20} {:>6} {:>6}", "wac-parser verdict", "agree", "fail");
+    println!("{}", "-".repeat(34));
+    for stage in ["parse", "resolve"] {
+        for (accepts, label) in [(true, "accepts"), (false, "rejects")] {
+            println!(
+                "{:<20} {:>6} {:>6}",
+                format!("{} {}", stage, label),
+                count(stage, accepts, true),
+                count(stage, accepts, false)
+            );
+        }
+    }
+
+    if let Some(path) = &opts.json {
+        let results: Vec<_> = outcomes
+            .iter()
+            .map(|o| {
+                json!({
+                    "test": o.test,
+                    "stage": o.stage,
+                    "status": if o.problems.is_empty() { "agree" } else { "fail" },
+                    "wac-parser": describe(&o.expected),
+                    "problems": o.problems,
+                })
+            })
+            .collect();
+        std::fs::write(path, serde_json::to_string_pretty(&json!({ "results": results }))?)?;
+        println!("\nResults written to {}", path.display());
+    }
+
+    let failures = outcomes.iter().filter(|o| !o.problems.is_empty()).count();
+    if failures > 0 {
+        println!("\n{} disagreement(s) out of {} check(s)", failures, outcomes.len());
+        std::process::exit(1);
+    }
+    println!("\nAll {} check(s) agree with wac-parser", outcomes.len());
+    Ok(())
+}
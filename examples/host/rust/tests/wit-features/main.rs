@@ -0,0 +1,442 @@
+// WIT feature matrix: every single-feature world of tests/wit-features
+// through generate, build, componentize and a rust-host round-trip, for the
+// wasm and wasm-gc targets
+//
+//   cargo test --test wit-features
+//   cargo test --test wit-features -- --target wasm-gc --filter resource
+//   cargo test --test wit-features -- --markdown matrix.md --json matrix.json
+//
+// Each corpus file is a package with one `features` world that exercises one
+// feature. Its leading comments are the description and the calls:
+//
+//   // A record of mixed fields
+//   // roundtrip: local:record/api#roundtrip {x: 1, y: -2, label: "origin"}
+//
+// Stages, per feature and target, in a fresh project:
+//
+//   generate      moon-component generate wit -o <dir> -p features
+//   build         moon build --target <target> --release, after filling in
+//                 every `roundtrip*(x : T) -> T` stub of impl/ with `x`
+//   componentize  moon-component componentize impl.wasm --wit-dir wit
+//   roundtrip     rust-host instantiate, then for each call `rust-host
+//                 profile` with the value, which must come back unchanged
+//
+// A feature without calls (resources: the host cannot construct a guest
+// resource from the command line) is only instantiated, and its roundtrip
+// cell says so instead of passing.
+//
+// The matrix is printed as Markdown and optionally written as Markdown and
+// JSON; the run fails when any cell does.
+//
+// moon-component is found as described in tests/common/mod.rs, and moon
+// through PATH; without either the run is skipped.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+use common::{is_runner_flag, moon_component_bin, on_path, repo_root, Args};
+
+const TARGETS: &[&str] = &["wasm", "wasm-gc"];
+const STAGES: &[&str] = &["generate", "build", "componentize", "roundtrip"];
+
+struct Options {
+    targets: Vec<String>,
+    filters: Vec<String>,
+    markdown: Option<PathBuf>,
+    json: Option<PathBuf>,
+    keep: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        targets: Vec::new(),
+        filters: Vec::new(),
+        markdown: None,
+        json: None,
+        keep: None,
+    };
+    let mut args = Args::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => opts.targets.push(args.value("--target")?),
+            "--filter" => opts.filters.push(args.value("--filter")?),
+            "--markdown" => opts.markdown = Some(args.value("--markdown")?.into()),
+            "--json" => opts.json = Some(args.value("--json")?.into()),
+            "--keep" => opts.keep = Some(args.value("--keep")?.into()),
+            _ if is_runner_flag(&arg) => {}
+            _ => opts.filters.push(arg),
+        }
+    }
+    if let Some(target) = opts.targets.iter().find(|t| !TARGETS.contains(&t.as_str())) {
+        anyhow::bail!("unknown target {} (expected one of {})", target, TARGETS.join(", "));
+    }
+    if opts.targets.is_empty() {
+        opts.targets = TARGETS.iter().map(|t| t.to_string()).collect();
+    }
+    Ok(opts)
+}
+
+struct Feature {
+    name: String,
+    path: PathBuf,
+    description: String,
+    /// `(export, value)`: the value is passed in and must come back as is.
+    calls: Vec<(String, String)>,
+}
+
+fn load_feature(path: &Path) -> Result<Feature> {
+    let text = std::fs::read_to_string(path)?;
+    let mut description = Vec::new();
+    let mut calls = Vec::new();
+    for line in text.lines() {
+        let Some(comment) = line.strip_prefix("//") else {
+            break;
+        };
+        let comment = comment.trim();
+        match comment.strip_prefix("roundtrip:") {
+            Some(call) => {
+                let (export, value) = call
+                    .trim()
+                    .split_once(' ')
+                    .with_context(|| format!("{}: roundtrip needs an export and a value", path.display()))?;
+                calls.push((export.to_string(), value.trim().to_string()));
+            }
+            None => description.push(comment),
+        }
+    }
+    Ok(Feature {
+        name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+        path: path.to_path_buf(),
+        description: description.join(" "),
+        calls,
+    })
+}
+
+fn collect_features() -> Result<Vec<Feature>> {
+    let dir = repo_root().join("tests/wit-features");
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(&dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "wit") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths.iter().map(|p| load_feature(p)).collect()
+}
+
+/// The stage that failed and what it printed.
+struct Failure {
+    stage: &'static str,
+    message: String,
+}
+
+fn failed(stage: &'static str, message: impl Into<String>) -> Failure {
+    Failure {
+        stage,
+        message: message.into(),
+    }
+}
+
+fn run(stage: &'static str, command: &mut Command) -> std::result::Result<String, Failure> {
+    let output = command
+        .output()
+        .map_err(|e| failed(stage, format!("failed to run: {}", e)))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if output.status.success() {
+        return Ok(stdout);
+    }
+    let text = format!("{}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+    // The interesting part of a build log is the first error, not the tail.
+    let message = text
+        .lines()
+        .find(|line| line.contains("rror"))
+        .or_else(|| text.lines().rev().find(|line| !line.trim().is_empty()))
+        .unwrap_or("exited unsuccessfully")
+        .trim();
+    Err(failed(stage, message))
+}
+
+/// Replace the `abort` body of every `roundtrip*(x : T) -> T` stub in the
+/// generated impl package with `x`, so each one echoes its argument.
+fn fill_roundtrips(impl_dir: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(impl_dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "mbt") {
+            continue;
+        }
+        let text = std::fs::read_to_string(&path)?;
+        let mut out = Vec::new();
+        let mut echo = false;
+        for line in text.lines() {
+            if echo && line.trim() == "abort(\"not implemented\")" {
+                out.push("  x".to_string());
+                echo = false;
+                continue;
+            }
+            echo = line
+                .strip_prefix("pub fn roundtrip")
+                .and_then(|rest| rest.split_once("(x : "))
+                .and_then(|(_, sig)| sig.strip_suffix(" {"))
+                .and_then(|sig| sig.split_once(") -> "))
+                .is_some_and(|(param, result)| param == result);
+            out.push(line.to_string());
+        }
+        std::fs::write(&path, out.join("\n") + "\n")?;
+    }
+    Ok(())
+}
+
+struct Tools {
+    moon_component: PathBuf,
+    moon: PathBuf,
+}
+
+/// Run every stage of `feature` for `target` in a fresh project under `dir`;
+/// `Ok(())` when the round-trip passes.
+fn pipeline(tools: &Tools, feature: &Feature, target: &str, dir: &Path) -> Result<std::result::Result<(), Failure>> {
+    let _ = std::fs::remove_dir_all(dir);
+    let wit = dir.join("wit");
+    std::fs::create_dir_all(&wit)?;
+    std::fs::copy(&feature.path, wit.join("world.wit"))?;
+    std::fs::write(dir.join("moon.mod.json"), "{\n  \"name\": \"features\",\n  \"version\": \"0.1.0\"\n}\n")?;
+    let core = dir.join(format!("_build/{}/release/build/impl/impl.wasm", target));
+    let component = dir.join("component.wasm");
+
+    Ok((|| {
+        run(
+            "generate",
+            Command::new(&tools.moon_component)
+                .arg("generate")
+                .arg(&wit)
+                .args(["-p", "features", "-o"])
+                .arg(dir),
+        )?;
+        fill_roundtrips(&dir.join("impl")).map_err(|e| failed("build", e.to_string()))?;
+        run(
+            "build",
+            Command::new(&tools.moon)
+                .args(["build", "--target", target, "--release", "--directory"])
+                .arg(dir),
+        )?;
+        run(
+            "componentize",
+            Command::new(&tools.moon_component)
+                .arg("componentize")
+                .arg(&core)
+                .arg("--wit-dir")
+                .arg(&wit)
+                .arg("-o")
+                .arg(&component),
+        )?;
+        run(
+            "roundtrip",
+            Command::new(env!("CARGO_BIN_EXE_rust-host"))
+                .arg("instantiate")
+                .arg(&component),
+        )?;
+        for (export, value) in &feature.calls {
+            let stdout = run(
+                "roundtrip",
+                Command::new(env!("CARGO_BIN_EXE_rust-host"))
+                    .arg("profile")
+                    .arg(&component)
+                    .args([export, value, "--iterations", "1", "--output"])
+                    .arg(dir.join("profile.json")),
+            )?;
+            let result = stdout
+                .lines()
+                .find_map(|line| line.strip_prefix("Last result: "))
+                .unwrap_or_default();
+            if result != value {
+                return Err(failed(
+                    "roundtrip",
+                    format!("{} {} came back as {}", export, value, result),
+                ));
+            }
+        }
+        Ok(())
+    })())
+}
+
+/// The roundtrip cell of a feature without calls, when instantiation passed.
+const INSTANTIATED: &str = "instantiate only";
+
+/// Whether the roundtrip stage of `feature` only instantiated the component.
+fn instantiate_only(feature: &Feature, stage: &str, cell: Option<bool>) -> bool {
+    stage == "roundtrip" && cell == Some(true) && feature.calls.is_empty()
+}
+
+/// One cell per stage: `Some(true)` passed, `Some(false)` failed, `None`
+/// not reached.
+fn cells(outcome: &std::result::Result<(), Failure>) -> Vec<Option<bool>> {
+    let failed_at = outcome
+        .as_ref()
+        .err()
+        .and_then(|f| STAGES.iter().position(|s| *s == f.stage));
+    (0..STAGES.len())
+        .map(|i| match failed_at {
+            Some(at) if i > at => None,
+            Some(at) => Some(i < at),
+            None => Some(true),
+        })
+        .collect()
+}
+
+fn markdown(targets: &[String], rows: &[(&Feature, Vec<std::result::Result<(), Failure>>)]) -> String {
+    let mut out = String::from("| Feature |");
+    let mut rule = String::from("|---------|");
+    for target in targets {
+        for stage in STAGES {
+            out.push_str(&format!(" {} {} |", target, stage));
+            rule.push_str(":-:|");
+        }
+    }
+    out.push('\n');
+    out.push_str(&rule);
+    out.push('\n');
+    let mut notes = Vec::new();
+    for (feature, outcomes) in rows {
+        out.push_str(&format!("| `{}` |", feature.name));
+        for (target, outcome) in targets.iter().zip(outcomes) {
+            for (stage, cell) in STAGES.iter().zip(cells(outcome)) {
+                if instantiate_only(feature, stage, cell) {
+                    out.push_str(&format!(" {} |", INSTANTIATED));
+                    continue;
+                }
+                out.push_str(match cell {
+                    Some(true) => " ✅ |",
+                    Some(false) => " ❌ |",
+                    None => " – |",
+                });
+            }
+            if let Err(failure) = outcome {
+                notes.push(format!(
+                    "- `{}` ({}, {}): {}",
+                    feature.name, target, failure.stage, failure.message
+                ));
+            }
+        }
+        out.push('\n');
+    }
+    if !notes.is_empty() {
+        out.push_str("\nFailures:\n\n");
+        out.push_str(&notes.join("\n"));
+        out.push('\n');
+    }
+    out
+}
+
+fn matrix_json(targets: &[String], rows: &[(&Feature, Vec<std::result::Result<(), Failure>>)]) -> Value {
+    let features: Vec<Value> = rows
+        .iter()
+        .map(|(feature, outcomes)| {
+            let per_target: serde_json::Map<String, Value> = targets
+                .iter()
+                .zip(outcomes)
+                .map(|(target, outcome)| {
+                    let mut stages = serde_json::Map::new();
+                    for (stage, cell) in STAGES.iter().zip(cells(outcome)) {
+                        let status = match cell {
+                            _ if instantiate_only(feature, stage, cell) => INSTANTIATED,
+                            Some(true) => "pass",
+                            Some(false) => "fail",
+                            None => "skipped",
+                        };
+                        stages.insert(stage.to_string(), json!(status));
+                    }
+                    if let Err(failure) = outcome {
+                        stages.insert("error".to_string(), json!(failure.message));
+                    }
+                    (target.clone(), Value::Object(stages))
+                })
+                .collect();
+            json!({
+                "feature": feature.name,
+                "description": feature.description,
+                "calls": feature.calls.len(),
+                "targets": per_target,
+            })
+        })
+        .collect();
+    json!({ "targets": targets, "stages": STAGES, "features": features })
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let (Some(moon_component), Some(moon)) = (moon_component_bin(), on_path("moon")) else {
+        eprintln!(
+            "moon-component or moon not found; skipping. Build moon-component with `moon build --target native --release` or set MOON_COMPONENT_BIN."
+        );
+        return Ok(());
+    };
+    let tools = Tools { moon_component, moon };
+
+    let mut features = collect_features()?;
+    if !opts.filters.is_empty() {
+        features.retain(|f| opts.filters.iter().any(|filter| f.name.contains(filter.as_str())));
+    }
+    if features.is_empty() {
+        println!("No features matched.");
+        std::process::exit(1);
+    }
+
+    let work = match &opts.keep {
+        Some(dir) => dir.clone(),
+        None => std::env::temp_dir().join(format!("wit-features-{}", std::process::id())),
+    };
+    let mut rows = Vec::new();
+    let mut failures: BTreeMap<&str, usize> = BTreeMap::new();
+    for feature in &features {
+        let mut outcomes = Vec::new();
+        for target in &opts.targets {
+            let dir = work.join(target).join(&feature.name);
+            let outcome = pipeline(&tools, feature, target, &dir)?;
+            if let Err(failure) = &outcome {
+                println!("FAIL: {} ({}) at {}\n  {}", feature.name, target, failure.stage, failure.message);
+                *failures.entry(failure.stage).or_default() += 1;
+            }
+            outcomes.push(outcome);
+        }
+        rows.push((feature, outcomes));
+    }
+    if opts.keep.is_none() {
+        let _ = std::fs::remove_dir_all(&work);
+    }
+
+    let table = markdown(&opts.targets, &rows);
+    println!("\n{}", table);
+    if let Some(path) = &opts.markdown {
+        std::fs::write(path, &table)?;
+        println!("Matrix written to {}", path.display());
+    }
+    if let Some(path) = &opts.json {
+        let matrix = matrix_json(&opts.targets, &rows);
+        std::fs::write(path, serde_json::to_string_pretty(&matrix)?)?;
+        println!("Matrix written to {}", path.display());
+    }
+
+    let total: usize = failures.values().sum();
+    if total > 0 {
+        let by_stage: Vec<String> = failures.iter().map(|(s, n)| format!("{} {}", n, s)).collect();
+        println!(
+            "\n{} of {} feature/target pair(s) failed ({})",
+            total,
+            features.len() * opts.targets.len(),
+            by_stage.join(", ")
+        );
+        std::process::exit(1);
+    }
+    println!(
+        "\nAll {} feature(s) pass every stage on {}",
+        features.len(),
+        opts.targets.join(" and ")
+    );
+    Ok(())
+}
@@ -0,0 +1,505 @@
+// Random WIT through the whole pipeline: wit-smith packages fed to
+// `moon-component generate`, `moon build --target wasm` and `moon-component
+// componentize`, then validated and instantiated
+//
+//   cargo test --test wit-smith
+//   cargo test --test wit-smith -- --seed 100 --count 50 --keep /tmp/wit-smith
+//
+// Each seed expands into the bytes wit-smith draws a package from. The package
+// is printed as a WIT directory (`wit/world.wit`, dependencies under
+// `wit/deps/`), and the world of the main package with the most imports and
+// exports goes through the stages in order:
+//
+//   generate      moon-component generate wit --world W -o <case> -p smith
+//   build         moon build --target wasm --release
+//   componentize  moon-component componentize ... --wit-dir wit --world W
+//   validate      wasmparser, every feature enabled
+//   instantiate   rust-host instantiate (WASI linked, other imports trap)
+//
+// Only worlds that export a function are built: `generate` writes no impl
+// package for the others, so there is no core module to componentize. Seeds
+// without such a world are passed over.
+//
+// When a stage fails, the WIT is minimized by deleting items (declarations,
+// functions, fields, whole interfaces and packages) while the rest still
+// resolves with wit-parser, the world still exports a function and the same
+// stage still fails, and is saved under tests/wit-smith/<stage>-<seed>/.
+// Saved cases run before the random ones, so a fixed bug stays fixed.
+//
+// moon-component is found as described in tests/common/mod.rs, and moon
+// through PATH; without either the run is skipped.
+
+use anyhow::{Context, Result};
+use arbitrary::Unstructured;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wit_component::{DecodedWasm, WitPrinter};
+use wit_parser::{Resolve, WorldId, WorldItem};
+
+#[path = "../common/mod.rs"]
+mod common;
+
+use common::{is_runner_flag, moon_component_bin, on_path, repo_root, Args};
+
+const STAGES: &[&str] = &["generate", "build", "componentize", "validate", "instantiate"];
+
+struct Options {
+    seed: u64,
+    count: u64,
+    /// Pipeline runs allowed per minimization.
+    budget: usize,
+    json: Option<PathBuf>,
+    keep: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut opts = Options {
+        seed: 0,
+        count: 20,
+        budget: 100,
+        json: None,
+        keep: None,
+    };
+    let mut args = Args::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => opts.seed = args.value("--seed")?.parse()?,
+            "--count" => opts.count = args.value("--count")?.parse()?,
+            "--budget" => opts.budget = args.value("--budget")?.parse()?,
+            "--json" => opts.json = Some(args.value("--json")?.into()),
+            "--keep" => opts.keep = Some(args.value("--keep")?.into()),
+            _ if is_runner_flag(&arg) => {}
+            _ => anyhow::bail!("unexpected argument: {}", arg),
+        }
+    }
+    Ok(opts)
+}
+
+/// A WIT directory as relative path → contents.
+type Files = BTreeMap<String, String>;
+
+struct Case {
+    name: String,
+    seed: Option<u64>,
+    world: String,
+    files: Files,
+}
+
+/// splitmix64, so a seed names the same package on every machine.
+fn seed_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    let mut out = Vec::with_capacity(len);
+    while out.len() < len {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        out.extend_from_slice(&(z ^ (z >> 31)).to_le_bytes());
+    }
+    out.truncate(len);
+    out
+}
+
+/// Whether `world` exports a function, directly or through an interface: the
+/// only worlds `generate` writes an impl package for.
+fn exports_function(resolve: &Resolve, world: WorldId) -> bool {
+    resolve.worlds[world].exports.values().any(|item| match item {
+        WorldItem::Function(_) => true,
+        WorldItem::Interface { id, .. } => !resolve.interfaces[*id].functions.is_empty(),
+        WorldItem::Type(_) => false,
+    })
+}
+
+/// The package wit-smith draws from `seed`, printed as a WIT directory, with
+/// the name of its largest world that exports a function; `None` when there
+/// is no such world.
+fn smith(seed: u64) -> Result<Option<(String, Files)>> {
+    let bytes = seed_bytes(seed, 16 * 1024);
+    let mut u = Unstructured::new(&bytes);
+    // Fewer packages than the default keeps dependency trees, and builds, small.
+    let config = wit_smith::Config {
+        max_packages: 4,
+        ..Default::default()
+    };
+    let Ok(wasm) = wit_smith::smith(&config, &mut u) else {
+        return Ok(None);
+    };
+    let DecodedWasm::WitPackage(resolve, main) = wit_component::decode(&wasm)? else {
+        anyhow::bail!("wit-smith produced a component, not a WIT package");
+    };
+    let Some(world) = resolve.packages[main]
+        .worlds
+        .iter()
+        .filter(|(_, id)| exports_function(&resolve, **id))
+        .max_by_key(|(_, id)| {
+            let world = &resolve.worlds[**id];
+            world.imports.len() + world.exports.len()
+        })
+        .map(|(name, _)| name.clone())
+    else {
+        return Ok(None);
+    };
+    let mut files = Files::new();
+    for (id, package) in &resolve.packages {
+        let text = WitPrinter::default().print(&resolve, id, &[])?;
+        let path = if id == main {
+            "world.wit".to_string()
+        } else {
+            let name = &package.name;
+            let dir = match &name.version {
+                Some(version) => format!("{}-{}-{}", name.namespace, name.name, version),
+                None => format!("{}-{}", name.namespace, name.name),
+            };
+            format!("deps/{}/package.wit", dir)
+        };
+        files.insert(path, text);
+    }
+    Ok(Some((world, files)))
+}
+
+fn write_files(dir: &Path, files: &Files) -> Result<()> {
+    let _ = std::fs::remove_dir_all(dir);
+    for (path, text) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, text)?;
+    }
+    Ok(())
+}
+
+fn read_files(dir: &Path, prefix: &str, files: &mut Files) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let relative = format!("{}{}", prefix, name);
+        if path.is_dir() {
+            read_files(&path, &format!("{}/", relative), files)?;
+        } else if name.ends_with(".wit") {
+            files.insert(relative, std::fs::read_to_string(&path)?);
+        }
+    }
+    Ok(())
+}
+
+/// Regression cases saved by earlier runs.
+fn saved_cases(dir: &Path) -> Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    if !dir.is_dir() {
+        return Ok(cases);
+    }
+    let mut dirs: Vec<_> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    dirs.sort();
+    for case in dirs.into_iter().filter(|d| d.join("case.json").is_file()) {
+        let info: Value = serde_json::from_str(&std::fs::read_to_string(case.join("case.json"))?)?;
+        let mut files = Files::new();
+        read_files(&case.join("wit"), "", &mut files)?;
+        cases.push(Case {
+            name: case.strip_prefix(repo_root())?.to_string_lossy().replace('\\', "/"),
+            seed: info["seed"].as_u64(),
+            world: info["world"].as_str().context("case.json without a world")?.to_string(),
+            files,
+        });
+    }
+    Ok(cases)
+}
+
+/// The stage that failed and what it printed.
+struct Failure {
+    stage: &'static str,
+    message: String,
+}
+
+fn failed(stage: &'static str, message: impl Into<String>) -> Failure {
+    Failure {
+        stage,
+        message: message.into(),
+    }
+}
+
+fn run(stage: &'static str, command: &mut Command) -> std::result::Result<(), Failure> {
+    let output = command
+        .output()
+        .map_err(|e| failed(stage, format!("failed to run: {}", e)))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // The interesting part of a build log is the first error, not the tail.
+    let message = text
+        .lines()
+        .find(|line| line.contains("rror"))
+        .or_else(|| text.lines().rev().find(|line| !line.trim().is_empty()))
+        .unwrap_or("exited unsuccessfully")
+        .trim();
+    Err(failed(stage, message))
+}
+
+struct Tools {
+    moon_component: PathBuf,
+    moon: PathBuf,
+}
+
+/// Run every stage on the WIT in `files`, in a fresh project under `dir`.
+fn pipeline(tools: &Tools, world: &str, files: &Files, dir: &Path) -> Result<std::result::Result<(), Failure>> {
+    let wit = dir.join("wit");
+    let _ = std::fs::remove_dir_all(dir);
+    write_files(&wit, files)?;
+    std::fs::write(dir.join("moon.mod.json"), "{\n  \"name\": \"smith\",\n  \"version\": \"0.1.0\"\n}\n")?;
+    let core = dir.join("_build/wasm/release/build/impl/impl.wasm");
+    let component = dir.join("component.wasm");
+
+    Ok((|| {
+        run(
+            "generate",
+            Command::new(&tools.moon_component)
+                .arg("generate")
+                .arg(&wit)
+                .args(["--world", world, "-p", "smith", "-o"])
+                .arg(dir),
+        )?;
+        run(
+            "build",
+            Command::new(&tools.moon)
+                .args(["build", "--target", "wasm", "--release", "--directory"])
+                .arg(dir),
+        )?;
+        run(
+            "componentize",
+            Command::new(&tools.moon_component)
+                .arg("componentize")
+                .arg(&core)
+                .arg("--wit-dir")
+                .arg(&wit)
+                .args(["--world", world, "-o"])
+                .arg(&component),
+        )?;
+        let bytes = std::fs::read(&component).map_err(|e| failed("validate", e.to_string()))?;
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(&bytes)
+            .map_err(|e| failed("validate", e.to_string()))?;
+        run(
+            "instantiate",
+            Command::new(env!("CARGO_BIN_EXE_rust-host"))
+                .arg("instantiate")
+                .arg(&component),
+        )
+    })())
+}
+
+/// Whether wit-parser still resolves `files` with `world` in the main package,
+/// and `world` still exports a function.
+fn resolves(files: &Files, world: &str, dir: &Path) -> bool {
+    if write_files(dir, files).is_err() {
+        return false;
+    }
+    let mut resolve = Resolve::default();
+    let Ok((main, _)) = resolve.push_dir(dir) else {
+        return false;
+    };
+    match resolve.select_world(main, Some(world)) {
+        Ok(id) => exports_function(&resolve, id),
+        Err(_) => false,
+    }
+}
+
+/// Line ranges of `text` that can go as a unit: a line ending in `;` or `,`,
+/// or a `{` line through its closing brace. Larger ranges come first.
+fn removable(text: &str) -> Vec<(usize, usize)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut ranges = Vec::new();
+    for (start, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("package ") {
+            continue;
+        }
+        if trimmed.ends_with('{') {
+            let mut depth = 0i32;
+            for (end, line) in lines.iter().enumerate().skip(start) {
+                depth += line.matches('{').count() as i32 - line.matches('}').count() as i32;
+                if depth <= 0 {
+                    ranges.push((start, end + 1));
+                    break;
+                }
+            }
+        } else if trimmed.ends_with(';') || trimmed.ends_with(',') {
+            ranges.push((start, start + 1));
+        }
+    }
+    ranges.sort_by_key(|(start, end)| std::cmp::Reverse(end - start));
+    ranges
+}
+
+/// `text` without the lines in `start..end`, and without the blank lines
+/// that would leave behind.
+fn without(text: &str, (start, end): (usize, usize)) -> String {
+    let mut out = String::new();
+    let mut blank = true;
+    for (i, line) in text.lines().enumerate() {
+        if (start..end).contains(&i) || (blank && line.trim().is_empty()) {
+            continue;
+        }
+        blank = line.trim().is_empty();
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Delete items from `files` while the WIT still resolves and `stage` still
+/// fails, within `budget` pipeline runs.
+fn minimize(tools: &Tools, world: &str, files: &Files, stage: &str, budget: usize, dir: &Path) -> Result<Files> {
+    let mut best = files.clone();
+    let mut runs = 0;
+    let mut progress = true;
+    while progress && runs < budget {
+        progress = false;
+        // Whole dependency packages first, then items file by file.
+        let deps: Vec<String> = best.keys().filter(|p| p.starts_with("deps/")).cloned().collect();
+        let mut candidates: Vec<Files> = deps
+            .iter()
+            .map(|path| {
+                let mut files = best.clone();
+                files.remove(path);
+                files
+            })
+            .collect();
+        for (path, text) in &best {
+            for range in removable(text) {
+                let mut files = best.clone();
+                files.insert(path.clone(), without(text, range));
+                candidates.push(files);
+            }
+        }
+        for candidate in candidates {
+            if runs >= budget {
+                break;
+            }
+            if !resolves(&candidate, world, &dir.join("check")) {
+                continue;
+            }
+            runs += 1;
+            if let Err(failure) = pipeline(tools, world, &candidate, &dir.join("case"))? {
+                if failure.stage == stage {
+                    best = candidate;
+                    progress = true;
+                    break;
+                }
+            }
+        }
+    }
+    Ok(best)
+}
+
+fn save(case: &Case, failure: &Failure, files: &Files) -> Result<PathBuf> {
+    let dir = repo_root()
+        .join("tests/wit-smith")
+        .join(format!("{}-{}", failure.stage, case.seed.unwrap_or_default()));
+    write_files(&dir.join("wit"), files)?;
+    let info = json!({
+        "seed": case.seed,
+        "world": case.world,
+        "stage": failure.stage,
+        "error": failure.message,
+    });
+    std::fs::write(dir.join("case.json"), serde_json::to_string_pretty(&info)? + "\n")?;
+    Ok(dir)
+}
+
+fn main() -> Result<()> {
+    let opts = parse_options()?;
+    let (Some(moon_component), Some(moon)) = (moon_component_bin(), on_path("moon")) else {
+        eprintln!(
+            "moon-component or moon not found; skipping. Build moon-component with `moon build --target native --release` or set MOON_COMPONENT_BIN."
+        );
+        return Ok(());
+    };
+    let tools = Tools { moon_component, moon };
+
+    let mut cases = saved_cases(&repo_root().join("tests/wit-smith"))?;
+    let saved = cases.len();
+    let mut seed = opts.seed;
+    let mut drawn = 0;
+    while drawn < opts.count {
+        if let Some((world, files)) = smith(seed)? {
+            cases.push(Case {
+                name: format!("seed {}", seed),
+                seed: Some(seed),
+                world,
+                files,
+            });
+            drawn += 1;
+        }
+        seed += 1;
+    }
+    println!(
+        "{} saved case(s), {} random case(s) from seeds {}..{}",
+        saved,
+        drawn,
+        opts.seed,
+        seed
+    );
+
+    let work = match &opts.keep {
+        Some(dir) => dir.clone(),
+        None => std::env::temp_dir().join(format!("wit-smith-{}", std::process::id())),
+    };
+    let mut results = Vec::new();
+    let mut reached: BTreeMap<&str, usize> = BTreeMap::new();
+    for (index, case) in cases.iter().enumerate() {
+        let dir = work.join(format!("case-{}", index));
+        let outcome = pipeline(&tools, &case.world, &case.files, &dir)?;
+        let stage = outcome.as_ref().err().map(|f| f.stage).unwrap_or("pass");
+        *reached.entry(stage).or_default() += 1;
+        let mut saved_to = None;
+        if let Err(failure) = &outcome {
+            println!("FAIL: {} (world {}) at {}\n  {}", case.name, case.world, failure.stage, failure.message);
+            // Saved cases are already minimal; only new ones are reduced.
+            if index >= saved {
+                let scratch = work.join(format!("case-{}-minimize", index));
+                let files = minimize(&tools, &case.world, &case.files, failure.stage, opts.budget, &scratch)?;
+                let path = save(case, failure, &files)?;
+                println!("  minimized WIT saved to {}", path.display());
+                saved_to = Some(path);
+            }
+        }
+        results.push(json!({
+            "case": case.name,
+            "seed": case.seed,
+            "world": case.world,
+            "status": if outcome.is_ok() { "pass" } else { "fail" },
+            "stage": outcome.as_ref().err().map(|f| f.stage),
+            "error": outcome.as_ref().err().map(|f| f.message.clone()),
+            "saved": saved_to.map(|p| p.display().to_string()),
+        }));
+    }
+    if opts.keep.is_none() {
+        let _ = std::fs::remove_dir_all(&work);
+    }
+
+    println!("\n{:<14} {:>6}", "failed at", "cases");
+    println!("{}", "-".repeat(21));
+    for stage in STAGES.iter().chain(&["pass"]) {
+        println!("{:<14} {:>6}", stage, reached.get(stage).copied().unwrap_or(0));
+    }
+
+    if let Some(path) = &opts.json {
+        std::fs::write(path, serde_json::to_string_pretty(&json!({ "results": results }))?)?;
+        println!("\nResults written to {}", path.display());
+    }
+
+    let failures = cases.len() - reached.get("pass").copied().unwrap_or(0);
+    if failures > 0 {
+        println!("\n{} of {} case(s) failed", failures, cases.len());
+        std::process::exit(1);
+    }
+    println!("\nAll {} case(s) passed every stage", cases.len());
+    Ok(())
+}
@@ -0,0 +1,26 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+ROOT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")/../.." && pwd)"
+SUITE_DIR="$ROOT_DIR/tests/wac-parser"
+CACHE_DIR="$SUITE_DIR/.wac"
+UPSTREAM_REPO="https://github.com/bytecodealliance/wac.git"
+# Keep in step with the wac-parser version used by examples/host/rust.
+UPSTREAM_REF="${WAC_TESTS_REF:-v0.6.1}"
+
+mkdir -p "$SUITE_DIR"
+
+if [ ! -d "$CACHE_DIR/.git" ]; then
+  git clone --depth 1 --branch "$UPSTREAM_REF" "$UPSTREAM_REPO" "$CACHE_DIR"
+else
+  git -C "$CACHE_DIR" fetch --depth 1 origin "$UPSTREAM_REF"
+  git -C "$CACHE_DIR" checkout -f FETCH_HEAD
+fi
+
+for suite in parser resolution; do
+  mkdir -p "$SUITE_DIR/$suite"
+  rsync -a --delete "$CACHE_DIR/crates/wac-parser/tests/$suite/" "$SUITE_DIR/$suite/"
+done
+
+REV=$(git -C "$CACHE_DIR" rev-parse --short HEAD)
+echo "Updated wac-parser tests to $UPSTREAM_REF ($REV)"